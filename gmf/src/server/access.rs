@@ -0,0 +1,377 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::server::error::GmfError;
+use crate::server::metrics::RejectReason;
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`. A bare address
+/// is a network of one.
+///
+/// IPv4-mapped IPv6 peers (`::ffff:a.b.c.d`, as seen by a dual-stack listener) match
+/// IPv4 networks, and a mapped network such as `::ffff:10.0.0.0/104` is the IPv4
+/// network `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    /// The network containing `addr` with a `prefix_len`-bit prefix. Host bits are
+    /// cleared.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, GmfError> {
+        if let (IpAddr::V4(v4), 96..=128) = (addr.to_canonical(), prefix_len) {
+            if addr.is_ipv6() {
+                return IpNet::new(IpAddr::V4(v4), prefix_len - 96);
+            }
+        }
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max {
+            return Err(GmfError::InvalidCidr(format!("{addr}/{prefix_len}")));
+        }
+        Ok(IpNet {
+            addr: truncate(addr, prefix_len),
+            prefix_len,
+        })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        addr.is_ipv4() == self.addr.is_ipv4() && truncate(addr, self.prefix_len) == self.addr
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = GmfError;
+
+    fn from_str(s: &str) -> Result<Self, GmfError> {
+        let invalid = || GmfError::InvalidCidr(s.to_owned());
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let prefix_len = prefix_len.unwrap_or(if addr.is_ipv4() { 32 } else { 128 });
+        IpNet::new(addr, prefix_len).map_err(|_| invalid())
+    }
+}
+
+impl From<IpAddr> for IpNet {
+    fn from(addr: IpAddr) -> Self {
+        let addr = addr.to_canonical();
+        IpNet {
+            addr,
+            prefix_len: if addr.is_ipv4() { 32 } else { 128 },
+        }
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+fn truncate(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+/// Which TCP clients may connect, checked right after accept, before TLS or any HTTP/2
+/// state. Unix socket clients are not filtered.
+///
+/// A peer in a `deny` network is rejected. Otherwise, if `allow` is not empty, the peer
+/// must be in one of its networks.
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+    /// Per-core limit on concurrent connections from one peer, across all listeners.
+    pub max_connections_per_peer: Option<usize>,
+    /// How peers are grouped for `max_connections_per_peer`.
+    pub peer_prefix: PeerPrefix,
+}
+
+/// Prefix lengths that group client addresses into one peer, e.g. `/24` and `/64` to
+/// limit whole subnets rather than single addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerPrefix {
+    pub v4: u8,
+    pub v6: u8,
+}
+
+impl Default for PeerPrefix {
+    /// One address per peer.
+    fn default() -> Self {
+        PeerPrefix { v4: 32, v6: 128 }
+    }
+}
+
+impl AccessPolicy {
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty() || self.max_connections_per_peer.is_some()
+    }
+
+    fn check(&self, addr: IpAddr) -> Result<(), RejectReason> {
+        if self.deny.iter().any(|net| net.contains(addr)) {
+            return Err(RejectReason::Denied);
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|net| net.contains(addr)) {
+            return Err(RejectReason::NotAllowed);
+        }
+        Ok(())
+    }
+
+    fn peer(&self, addr: IpAddr) -> IpNet {
+        let addr = addr.to_canonical();
+        let prefix_len = match addr {
+            IpAddr::V4(_) => self.peer_prefix.v4.min(32),
+            IpAddr::V6(_) => self.peer_prefix.v6.min(128),
+        };
+        IpNet {
+            addr: truncate(addr, prefix_len),
+            prefix_len,
+        }
+    }
+}
+
+/// One core's open connections per peer, shared by the core's listeners.
+#[derive(Clone, Default)]
+pub(crate) struct PeerTable(Rc<RefCell<HashMap<IpNet, usize>>>);
+
+impl PeerTable {
+    /// Admit a connection from `addr` under `policy`, or say why not. The returned slot
+    /// counts the connection against its peer until dropped.
+    pub(crate) fn admit(
+        &self,
+        policy: &AccessPolicy,
+        addr: IpAddr,
+    ) -> Result<Option<PeerSlot>, RejectReason> {
+        let addr = addr.to_canonical();
+        policy.check(addr)?;
+        let Some(max) = policy.max_connections_per_peer else {
+            return Ok(None);
+        };
+        let peer = policy.peer(addr);
+        let mut peers = self.0.borrow_mut();
+        let open = peers.entry(peer).or_insert(0);
+        if *open >= max {
+            if *open == 0 {
+                peers.remove(&peer);
+            }
+            return Err(RejectReason::PeerLimit);
+        }
+        *open += 1;
+        Ok(Some(PeerSlot {
+            table: self.clone(),
+            peer,
+        }))
+    }
+}
+
+/// A connection counted against its peer in a [`PeerTable`]; released on drop.
+pub(crate) struct PeerSlot {
+    table: PeerTable,
+    peer: IpNet,
+}
+
+impl Drop for PeerSlot {
+    fn drop(&mut self) {
+        let mut peers = self.table.0.borrow_mut();
+        if let Some(open) = peers.get_mut(&self.peer) {
+            *open -= 1;
+            if *open == 0 {
+                peers.remove(&self.peer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_cidrs() {
+        assert_eq!(net("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(net("192.168.0.1").to_string(), "192.168.0.1/32");
+        assert_eq!(net("0.0.0.0/0").to_string(), "0.0.0.0/0");
+        assert_eq!(net("2001:db8:1::1/32").to_string(), "2001:db8::/32");
+        assert_eq!(net("::1").to_string(), "::1/128");
+        assert_eq!(net("::/0").prefix_len(), 0);
+        for s in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "/8",
+            "host/8",
+        ] {
+            assert!(
+                matches!(s.parse::<IpNet>(), Err(GmfError::InvalidCidr(input)) if input == s),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn contains() {
+        assert!(net("10.0.0.0/8").contains(ip("10.255.0.1")));
+        assert!(!net("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(net("0.0.0.0/0").contains(ip("203.0.113.9")));
+        assert!(!net("0.0.0.0/0").contains(ip("2001:db8::1")));
+        assert!(net("2001:db8::/32").contains(ip("2001:db8:ffff::1")));
+        assert!(!net("2001:db8::/32").contains(ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn mapped_ipv6_peers_match_ipv4_networks() {
+        let mapped = ip("::ffff:10.1.2.3");
+        assert!(net("10.0.0.0/8").contains(mapped));
+        assert!(!net("::/0").contains(mapped));
+        assert_eq!(IpNet::from(mapped), net("10.1.2.3"));
+
+        let policy = AccessPolicy {
+            deny: vec![net("10.0.0.0/8")],
+            ..Default::default()
+        };
+        assert_eq!(
+            PeerTable::default().admit(&policy, mapped).err(),
+            Some(RejectReason::Denied)
+        );
+    }
+
+    #[test]
+    fn mapped_networks_are_ipv4() {
+        assert_eq!(net("::ffff:10.1.2.3/104"), net("10.0.0.0/8"));
+        assert_eq!(net("::ffff:10.1.2.3"), net("10.1.2.3"));
+        assert!(net("::ffff:10.0.0.0/104").contains(ip("10.9.9.9")));
+        assert!(net("::ffff:10.0.0.0/104").contains(ip("::ffff:10.9.9.9")));
+        // Shorter prefixes span more than the mapped range and stay IPv6.
+        assert_eq!(net("::ffff:0.0.0.0/95").to_string(), "::fffe:0:0/95");
+        assert!(matches!(
+            "::ffff:10.0.0.0/129".parse::<IpNet>(),
+            Err(GmfError::InvalidCidr(_))
+        ));
+    }
+
+    #[test]
+    fn mapped_peers_share_ipv4_limits() {
+        let policy = AccessPolicy {
+            allow: vec![net("10.0.0.0/8")],
+            max_connections_per_peer: Some(2),
+            peer_prefix: PeerPrefix { v4: 24, v6: 64 },
+            ..Default::default()
+        };
+        let peers = PeerTable::default();
+        let native = peers.admit(&policy, ip("10.0.0.1")).unwrap();
+        let mapped = peers.admit(&policy, ip("::ffff:10.0.0.2")).unwrap();
+        assert_eq!(
+            peers.admit(&policy, ip("10.0.0.3")).err(),
+            Some(RejectReason::PeerLimit)
+        );
+        assert_eq!(
+            peers.admit(&policy, ip("::ffff:10.0.0.4")).err(),
+            Some(RejectReason::PeerLimit)
+        );
+        assert_eq!(
+            peers.admit(&policy, ip("::ffff:192.168.0.1")).err(),
+            Some(RejectReason::NotAllowed)
+        );
+        assert_eq!(peers.0.borrow().len(), 1);
+        drop((native, mapped));
+        assert!(peers.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn deny_is_checked_before_allow() {
+        let policy = AccessPolicy {
+            allow: vec![net("10.0.0.0/8")],
+            deny: vec![net("10.0.0.0/24")],
+            ..Default::default()
+        };
+        let peers = PeerTable::default();
+        assert_eq!(
+            peers.admit(&policy, ip("10.0.0.5")).err(),
+            Some(RejectReason::Denied)
+        );
+        assert!(peers.admit(&policy, ip("10.0.1.5")).is_ok());
+        assert_eq!(
+            peers.admit(&policy, ip("192.168.0.1")).err(),
+            Some(RejectReason::NotAllowed)
+        );
+    }
+
+    #[test]
+    fn peers_are_bucketed_by_prefix() {
+        let policy = AccessPolicy {
+            max_connections_per_peer: Some(2),
+            peer_prefix: PeerPrefix { v4: 24, v6: 64 },
+            ..Default::default()
+        };
+        let peers = PeerTable::default();
+        let first = peers.admit(&policy, ip("2001:db8:0:1::1")).unwrap();
+        let second = peers.admit(&policy, ip("2001:db8:0:1:ffff::2")).unwrap();
+        assert_eq!(
+            peers.admit(&policy, ip("2001:db8:0:1::3")).err(),
+            Some(RejectReason::PeerLimit)
+        );
+        // Another /64 is another peer.
+        assert!(peers.admit(&policy, ip("2001:db8:0:2::1")).is_ok());
+        assert!(peers.admit(&policy, ip("10.0.0.1")).unwrap().is_some());
+
+        drop(first);
+        let third = peers.admit(&policy, ip("2001:db8:0:1::3")).unwrap();
+        drop((second, third));
+        assert!(peers.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn no_slot_without_a_peer_limit() {
+        let policy = AccessPolicy {
+            allow: vec![net("::1")],
+            ..Default::default()
+        };
+        assert!(!AccessPolicy::default().is_enabled());
+        assert!(policy.is_enabled());
+        assert!(matches!(
+            PeerTable::default().admit(&policy, ip("::1")),
+            Ok(None)
+        ));
+    }
+}
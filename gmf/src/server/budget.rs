@@ -0,0 +1,251 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::server::runtime::RuntimeTimer;
+use crate::server::semaphore::{LocalPermit, LocalSemaphore};
+
+/// How often each core rebalances its share of the budget.
+const REBALANCE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Parts of one core's even share kept as the unit of borrowing and returning. A core
+/// caches at most two chunks of unused budget, so idle cores strand at most a quarter
+/// of the budget between rebalances.
+const CHUNKS_PER_SHARE: usize = 8;
+
+/// The process-wide connection budget: the part no core currently holds.
+pub(crate) struct GlobalBudget {
+    spare: AtomicUsize,
+    /// Cores with connections waiting for budget. While there are any, the other cores
+    /// give back their spare permits at their next rebalance.
+    starving: AtomicUsize,
+    total: usize,
+    cores: usize,
+    chunk: usize,
+}
+
+impl GlobalBudget {
+    pub(crate) fn new(total: usize, cores: usize) -> Arc<Self> {
+        Arc::new(GlobalBudget {
+            spare: AtomicUsize::new(0),
+            starving: AtomicUsize::new(0),
+            total,
+            cores,
+            chunk: (total / cores / CHUNKS_PER_SHARE).max(1),
+        })
+    }
+
+    /// Take up to `max` spare permits.
+    fn borrow(&self, max: usize) -> usize {
+        self.spare
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |spare| {
+                (spare > 0).then(|| spare - spare.min(max))
+            })
+            .map_or(0, |spare| spare.min(max))
+    }
+
+    fn give_back(&self, n: usize) {
+        if n > 0 {
+            self.spare.fetch_add(n, Ordering::AcqRel);
+        }
+    }
+}
+
+/// One core's slice of the [`GlobalBudget`].
+///
+/// Connections take permits from the core's local semaphore, so the accept path never
+/// touches shared state while the core has budget left. The shared pool is only used
+/// when the core runs out and on the periodic rebalance.
+#[derive(Clone)]
+pub(crate) struct CoreBudget {
+    global: Arc<GlobalBudget>,
+    permits: LocalSemaphore,
+    /// Whether this core is counted in [`GlobalBudget::starving`].
+    starving: Rc<Cell<bool>>,
+}
+
+impl CoreBudget {
+    /// Start with an even share of the budget.
+    pub(crate) fn new(global: Arc<GlobalBudget>, cpu: usize) -> Self {
+        let share = global.total / global.cores + usize::from(cpu < global.total % global.cores);
+        CoreBudget {
+            permits: LocalSemaphore::new(share),
+            global,
+            starving: Rc::new(Cell::new(false)),
+        }
+    }
+
+    pub(crate) fn try_acquire(&self) -> Option<LocalPermit> {
+        if let Some(permit) = self.permits.try_acquire() {
+            return Some(permit);
+        }
+        self.borrow();
+        self.permits.try_acquire()
+    }
+
+    pub(crate) async fn acquire(&self) -> LocalPermit {
+        if let Some(permit) = self.try_acquire() {
+            return permit;
+        }
+        self.set_starving(true);
+        self.permits.acquire().await
+    }
+
+    /// Rebalance every [`REBALANCE_INTERVAL`] until the future is dropped.
+    pub(crate) async fn run<T: RuntimeTimer>(&self, timer: &T) {
+        loop {
+            timer.sleep(REBALANCE_INTERVAL).await;
+            self.rebalance();
+        }
+    }
+
+    fn rebalance(&self) {
+        let chunk = self.global.chunk;
+        if self.permits.has_waiters() {
+            self.borrow();
+        }
+        // Also clears the mark of a core whose waiters gave up.
+        self.set_starving(self.permits.has_waiters());
+        if self.starving.get() {
+            return;
+        }
+        if self.global.starving.load(Ordering::Acquire) > 0 {
+            self.global.give_back(self.permits.forget(usize::MAX));
+        } else if self.permits.available() > 2 * chunk {
+            let excess = self.permits.available() - chunk;
+            self.global.give_back(self.permits.forget(excess));
+        }
+    }
+
+    /// Move a chunk from the shared pool into this core, if there is one.
+    fn borrow(&self) {
+        let n = self.global.borrow(self.global.chunk);
+        if n > 0 {
+            self.permits.add_permits(n);
+        }
+    }
+
+    fn set_starving(&self, starving: bool) {
+        if self.starving.replace(starving) != starving {
+            match starving {
+                true => self.global.starving.fetch_add(1, Ordering::AcqRel),
+                false => self.global.starving.fetch_sub(1, Ordering::AcqRel),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+
+    fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+        future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    fn starving(global: &GlobalBudget) -> usize {
+        global.starving.load(Ordering::Acquire)
+    }
+
+    fn spare(global: &GlobalBudget) -> usize {
+        global.spare.load(Ordering::Acquire)
+    }
+
+    #[test]
+    fn shares_are_even() {
+        let global = GlobalBudget::new(5, 2);
+        assert_eq!(CoreBudget::new(global.clone(), 0).permits.available(), 3);
+        assert_eq!(CoreBudget::new(global, 1).permits.available(), 2);
+    }
+
+    #[test]
+    fn running_dry_without_waiters_does_not_starve() {
+        let global = GlobalBudget::new(4, 2);
+        let (first, second) = (
+            CoreBudget::new(global.clone(), 0),
+            CoreBudget::new(global.clone(), 1),
+        );
+        let held: Vec<_> = (0..2).map(|_| first.try_acquire().unwrap()).collect();
+        assert!(first.try_acquire().is_none());
+        assert_eq!(starving(&global), 0);
+
+        // The other core keeps its share on the accept path.
+        for _ in 0..5 {
+            first.rebalance();
+            second.rebalance();
+        }
+        assert_eq!(second.permits.available(), 2);
+        assert_eq!(spare(&global), 0);
+        drop(held);
+        assert_eq!(first.permits.available(), 2);
+    }
+
+    #[test]
+    fn waiting_core_borrows_what_others_give_back() {
+        let global = GlobalBudget::new(4, 2);
+        let (first, second) = (
+            CoreBudget::new(global.clone(), 0),
+            CoreBudget::new(global.clone(), 1),
+        );
+        let held: Vec<_> = (0..2).map(|_| first.try_acquire().unwrap()).collect();
+        let mut waiting = Box::pin(first.acquire());
+        assert!(poll(&mut waiting).is_pending());
+        assert_eq!(starving(&global), 1);
+
+        second.rebalance();
+        assert_eq!(second.permits.available(), 0);
+        assert_eq!(spare(&global), 2);
+
+        first.rebalance();
+        let Poll::Ready(permit) = poll(&mut waiting) else {
+            panic!("waiter was not granted a borrowed permit");
+        };
+        first.rebalance();
+        assert_eq!(starving(&global), 0);
+
+        // Without a starving core the rest of the pool stays put until borrowed.
+        second.rebalance();
+        assert_eq!(spare(&global), 1);
+        assert!(second.try_acquire().is_some());
+        assert_eq!(spare(&global), 0);
+        drop((held, permit));
+    }
+
+    #[test]
+    fn abandoned_waiter_stops_starving() {
+        let global = GlobalBudget::new(2, 2);
+        let (first, second) = (
+            CoreBudget::new(global.clone(), 0),
+            CoreBudget::new(global.clone(), 1),
+        );
+        let held = first.try_acquire().unwrap();
+        let mut waiting = Box::pin(first.acquire());
+        assert!(poll(&mut waiting).is_pending());
+        assert_eq!(starving(&global), 1);
+
+        drop(waiting);
+        first.rebalance();
+        assert_eq!(starving(&global), 0);
+        second.rebalance();
+        assert_eq!(second.permits.available(), 1);
+        drop(held);
+    }
+
+    #[test]
+    fn excess_beyond_two_chunks_is_returned() {
+        // 64 per core, in chunks of 8.
+        let global = GlobalBudget::new(128, 2);
+        let core = CoreBudget::new(global.clone(), 0);
+        core.rebalance();
+        assert_eq!(core.permits.available(), 8);
+        assert_eq!(spare(&global), 56);
+    }
+}
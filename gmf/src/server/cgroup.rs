@@ -0,0 +1,284 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::server::cpu::CpuSet;
+use crate::server::topology::{parse_list, read_optional};
+
+/// Where the cgroup filesystem is mounted.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The CPU limits this process's cgroup places on it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CgroupCpu {
+    /// CPU time the cgroup may use per period, in CPUs (`1.5` for `cpu.max` of
+    /// `150000 100000`); the smallest quota among the cgroup and its ancestors. `None`
+    /// when unlimited.
+    pub quota: Option<f64>,
+    /// The CPUs the cgroup may run on (`cpuset.cpus.effective`), if the cpuset
+    /// controller is enabled.
+    pub cpuset: Option<CpuSet>,
+}
+
+impl CgroupCpu {
+    /// Read the limits of this process's cgroup from [`CGROUP_ROOT`].
+    pub fn system() -> io::Result<Self> {
+        Self::from_root(CGROUP_ROOT)
+    }
+
+    /// Read the limits from a cgroup filesystem mounted at `root`, e.g. a fixture tree:
+    /// a cgroup v2 hierarchy (with `cgroup.controllers`), or v1 controller directories
+    /// such as `cpu,cpuacct` and `cpuset`.
+    ///
+    /// The process's cgroup is looked up under `root` by its path in
+    /// `/proc/self/cgroup`. When that path does not exist under `root`, as inside a
+    /// container with its own cgroup namespace or in a fixture, `root` itself is
+    /// taken to be the process's cgroup.
+    pub fn from_root(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref();
+        let memberships = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        if root.join("cgroup.controllers").exists() {
+            let path = membership(&memberships, |controllers| controllers.is_empty());
+            let cgroup = ancestors(root, path);
+            Ok(CgroupCpu {
+                quota: min_quota(&cgroup, |dir| read_cpu_max(&dir.join("cpu.max")))?,
+                cpuset: nearest_cpuset(&cgroup, "cpuset.cpus.effective")?,
+            })
+        } else {
+            let quota = match controller_dir(root, "cpu")? {
+                Some(dir) => {
+                    let path = membership(&memberships, |controllers| {
+                        controllers.split(',').any(|name| name == "cpu")
+                    });
+                    min_quota(&ancestors(&dir, path), read_cfs_quota)?
+                }
+                None => None,
+            };
+            let cpuset = match controller_dir(root, "cpuset")? {
+                Some(dir) => {
+                    let path = membership(&memberships, |controllers| {
+                        controllers.split(',').any(|name| name == "cpuset")
+                    });
+                    nearest_cpuset(&ancestors(&dir, path), "cpuset.effective_cpus")?
+                }
+                None => None,
+            };
+            Ok(CgroupCpu { quota, cpuset })
+        }
+    }
+}
+
+/// The process's cgroup path for the hierarchy whose controller list matches.
+fn membership(memberships: &str, matches: impl Fn(&str) -> bool) -> &str {
+    memberships
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let _id = fields.next()?;
+            Some((fields.next()?, fields.next()?))
+        })
+        .find(|(controllers, _)| matches(controllers))
+        .map_or("/", |(_, path)| path)
+}
+
+/// The process's cgroup directory and its ancestors up to `base`, innermost first.
+fn ancestors(base: &Path, path: &str) -> Vec<PathBuf> {
+    let leaf = base.join(path.trim_start_matches('/'));
+    if !leaf.is_dir() {
+        return vec![base.to_path_buf()];
+    }
+    leaf.ancestors()
+        .take_while(|dir| dir.starts_with(base))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// The v1 hierarchy holding `controller`, e.g. `cpu,cpuacct` for `cpu`.
+fn controller_dir(root: &Path, controller: &str) -> io::Result<Option<PathBuf>> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let holds = name
+            .to_str()
+            .is_some_and(|name| name.split(',').any(|name| name == controller));
+        if holds && entry.path().is_dir() {
+            return Ok(Some(entry.path()));
+        }
+    }
+    Ok(None)
+}
+
+fn min_quota(
+    cgroup: &[PathBuf],
+    read: impl Fn(&Path) -> io::Result<Option<f64>>,
+) -> io::Result<Option<f64>> {
+    let mut min: Option<f64> = None;
+    for dir in cgroup {
+        if let Some(quota) = read(dir)? {
+            min = Some(min.map_or(quota, |min| min.min(quota)));
+        }
+    }
+    Ok(min)
+}
+
+/// `cpu.max`: `$MAX $PERIOD`, where `$MAX` may be `max`.
+fn read_cpu_max(path: &Path) -> io::Result<Option<f64>> {
+    let Some(contents) = read_optional(path)? else {
+        return Ok(None);
+    };
+    let mut fields = contents.split_whitespace();
+    let quota = fields.next().unwrap_or("max");
+    let period = fields.next().unwrap_or("100000");
+    if quota == "max" {
+        return Ok(None);
+    }
+    Ok(Some(
+        parse_number(path, quota)? / parse_number(path, period)?,
+    ))
+}
+
+/// v1 `cpu.cfs_quota_us` (`-1` when unlimited) over `cpu.cfs_period_us`.
+fn read_cfs_quota(dir: &Path) -> io::Result<Option<f64>> {
+    let quota_path = dir.join("cpu.cfs_quota_us");
+    let Some(quota) = read_optional(&quota_path)? else {
+        return Ok(None);
+    };
+    let quota = parse_number(&quota_path, &quota)?;
+    if quota <= 0.0 {
+        return Ok(None);
+    }
+    let period_path = dir.join("cpu.cfs_period_us");
+    let period = match read_optional(&period_path)? {
+        Some(period) => parse_number(&period_path, &period)?,
+        None => 100_000.0,
+    };
+    Ok(Some(quota / period))
+}
+
+/// The innermost cpuset in `cgroup`; an empty one means the controller is not enabled
+/// there.
+fn nearest_cpuset(cgroup: &[PathBuf], file: &str) -> io::Result<Option<CpuSet>> {
+    for dir in cgroup {
+        if let Some(list) = read_optional(&dir.join(file))? {
+            let cpus = parse_list(&list)?;
+            if !cpus.is_empty() {
+                return Ok(Some(cpus));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn parse_number(path: &Path, value: &str) -> io::Result<f64> {
+    match value.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && (n > 0.0 || n == -1.0) => Ok(n),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid value in {}: {value:?}", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cgroup tree with `files` (relative paths and contents). The fixtures hold the
+    /// process's own cgroup at the root, which is where `from_root` falls back to
+    /// when the path from `/proc/self/cgroup` is not in the tree.
+    fn cgroup(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn read(files: &[(&str, &str)]) -> io::Result<CgroupCpu> {
+        CgroupCpu::from_root(cgroup(files).path())
+    }
+
+    #[test]
+    fn v2_unlimited() {
+        let cpu = read(&[
+            ("cgroup.controllers", "cpuset cpu io memory pids\n"),
+            ("cpu.max", "max 100000\n"),
+        ])
+        .unwrap();
+        assert_eq!(cpu, CgroupCpu::default());
+    }
+
+    #[test]
+    fn v2_quota_and_cpuset() {
+        let cpu = read(&[
+            ("cgroup.controllers", "cpuset cpu\n"),
+            ("cpu.max", "150000 100000\n"),
+            ("cpuset.cpus.effective", "0-3,8\n"),
+        ])
+        .unwrap();
+        assert_eq!(cpu.quota, Some(1.5));
+        assert_eq!(cpu.cpuset.unwrap().to_string(), "0-3,8");
+    }
+
+    #[test]
+    fn v2_empty_cpuset_is_no_cpuset() {
+        let cpu = read(&[
+            ("cgroup.controllers", "cpu\n"),
+            ("cpuset.cpus.effective", "\n"),
+        ])
+        .unwrap();
+        assert_eq!(cpu.cpuset, None);
+    }
+
+    #[test]
+    fn v2_invalid_quota() {
+        let e = read(&[
+            ("cgroup.controllers", "cpu\n"),
+            ("cpu.max", "lots 100000\n"),
+        ])
+        .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn v1_unlimited() {
+        let cpu = read(&[
+            ("cpu,cpuacct/cpu.cfs_quota_us", "-1\n"),
+            ("cpu,cpuacct/cpu.cfs_period_us", "100000\n"),
+        ])
+        .unwrap();
+        assert_eq!(cpu, CgroupCpu::default());
+    }
+
+    #[test]
+    fn v1_quota_and_cpuset() {
+        let cpu = read(&[
+            ("cpu,cpuacct/cpu.cfs_quota_us", "250000\n"),
+            ("cpu,cpuacct/cpu.cfs_period_us", "100000\n"),
+            ("cpuset/cpuset.effective_cpus", "2-5\n"),
+        ])
+        .unwrap();
+        assert_eq!(cpu.quota, Some(2.5));
+        assert_eq!(cpu.cpuset.unwrap().to_string(), "2-5");
+    }
+
+    #[test]
+    fn v1_default_period() {
+        let cpu = read(&[("cpu/cpu.cfs_quota_us", "50000\n")]).unwrap();
+        assert_eq!(cpu.quota, Some(0.5));
+    }
+
+    #[test]
+    fn missing_root_has_no_limits() {
+        let root = tempfile::tempdir().unwrap();
+        let cpu = CgroupCpu::from_root(root.path().join("missing")).unwrap();
+        assert_eq!(cpu, CgroupCpu::default());
+    }
+}
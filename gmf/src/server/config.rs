@@ -1,13 +1,500 @@
+use std::io;
 use std::net::SocketAddr;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use socket2::{SockRef, Type};
+
+use crate::server::access::AccessPolicy;
+use crate::server::cgroup::CgroupCpu;
+use crate::server::cpu::{CorePlacement, CpuSet, PinPolicy};
+use crate::server::error::GmfError;
+use crate::server::listener::{ListenAddr, UnixAddr, UnixStrategy};
+use crate::server::socket::SocketOptions;
+#[cfg(feature = "tls")]
+use crate::server::tls::TlsConfig;
+use crate::server::topology::CpuTopology;
+use crate::server::write_timeout::WriteTimeouts;
 
 pub struct ServerConfig {
-    pub addr: SocketAddr,
+    /// Every core runs one accept loop per listener, all feeding the same service.
+    pub listeners: Vec<ListenerConfig>,
+    /// Default per-core connection limit for listeners that do not set their own.
     pub max_connections: usize,
+    /// Connection limit across all cores and listeners.
+    pub global_max_connections: Option<usize>,
     pub num_cores: Option<usize>,
+    /// CPUs the cores may run on; every CPU this process may run on when unset.
+    pub cpus: Option<CpuSet>,
+    /// How core threads are pinned to `cpus`.
+    pub pinning: PinPolicy,
+    /// Group cores by NUMA node and have each core's thread prefer memory from its node.
+    pub numa: bool,
+    /// Where the cgroup filesystem whose CPU quota and cpuset bound the cores is
+    /// mounted ([`CGROUP_ROOT`](crate::server::cgroup::CGROUP_ROOT) by default).
+    pub cgroup_root: PathBuf,
+    /// How long a shutdown waits for in-flight RPCs before closing remaining connections.
+    pub drain_timeout: Duration,
+    /// Reset a response stream after the peer has not accepted any of its data for this
+    /// long (its HTTP/2 flow-control window stayed full).
+    pub stream_write_timeout: Option<Duration>,
+    /// Send GOAWAY and close a connection that had data to send but wrote nothing for
+    /// this long.
+    pub connection_write_timeout: Option<Duration>,
+    /// HTTP/2 settings applied to every connection.
+    pub http2: Http2Config,
+    /// When connections are retired with GOAWAY so clients reconnect.
+    pub lifetime: ConnectionLifetime,
+    /// Close a connection that has not completed the TLS handshake (if any) and sent
+    /// the HTTP/2 preface and its first `SETTINGS` frame this long after accept.
+    pub handshake_timeout: Option<Duration>,
+    /// What happens to new connections while a listener is at its connection limit.
+    pub overload: OverloadPolicy,
+    /// Which TCP clients may connect, and how many connections each may hold.
+    pub access: AccessPolicy,
+    /// Report `READY=1` and `STOPPING=1` to systemd over `NOTIFY_SOCKET`.
+    pub systemd_notify: bool,
 }
 
 impl ServerConfig {
+    /// The number of cores the server runs: `num_cores`, or by default one per physical
+    /// core among the CPUs it may use, but no more than the cgroup's CPU quota.
     pub fn effective_cores(&self) -> usize {
+        match self.placement() {
+            Ok(placement) => placement.cores(),
+            Err(_) => self.fallback_cores(),
+        }
+    }
+
+    /// The core count when no placement can be planned.
+    pub(crate) fn fallback_cores(&self) -> usize {
         self.num_cores.unwrap_or_else(num_cpus::get_physical)
     }
+
+    /// The CPU each core runs on, starting from the CPUs this process may run on
+    /// (`sched_getaffinity` and the cgroup's `cpuset.cpus.effective`) and one CPU per
+    /// physical core (from [`CpuTopology::system`]), within the cgroup's CPU quota.
+    /// Fails if `cpus` names a CPU outside of them.
+    pub fn placement(&self) -> Result<CorePlacement, GmfError> {
+        let cgroup = CgroupCpu::from_root(&self.cgroup_root).unwrap_or_else(|e| {
+            tracing::debug!(
+                error = %e,
+                root = %self.cgroup_root.display(),
+                "cgroup CPU limits unavailable"
+            );
+            CgroupCpu::default()
+        });
+        let mut allowed = CpuSet::allowed()?;
+        if let Some(cpuset) = &cgroup.cpuset {
+            let narrowed = allowed.intersection(cpuset);
+            if narrowed.is_empty() {
+                tracing::warn!(
+                    affinity = %allowed,
+                    cpuset = %cpuset,
+                    "cgroup cpuset and affinity mask do not overlap, ignoring the cpuset"
+                );
+            } else {
+                allowed = narrowed;
+            }
+        }
+        let cpus = match &self.cpus {
+            Some(cpus) => {
+                if let Some(cpu) = cpus.iter().find(|&cpu| !allowed.contains(cpu)) {
+                    return Err(GmfError::Placement(format!(
+                        "CPU {cpu} is not in this process's affinity mask and cgroup cpuset {allowed}"
+                    )));
+                }
+                cpus.clone()
+            }
+            None => allowed,
+        };
+        let topology = CpuTopology::system().unwrap_or_else(|e| {
+            tracing::debug!(error = %e, "CPU topology unavailable, treating every CPU as a core");
+            CpuTopology::default()
+        });
+        CorePlacement::plan(
+            &cpus,
+            topology,
+            self.num_cores,
+            cgroup.quota,
+            &self.pinning,
+            self.numa,
+        )
+    }
+
+    /// Whether connections need [`ConnFrames`](crate::server::frames::ConnFrames) to
+    /// enforce the configured policies.
+    pub(crate) fn tracks_frames(&self) -> bool {
+        self.http2.ping_policy.is_some()
+            || self.lifetime.tracks_idle()
+            || self.handshake_timeout.is_some()
+    }
+
+    pub(crate) fn write_timeouts(&self) -> WriteTimeouts {
+        WriteTimeouts {
+            stream: self.stream_write_timeout,
+            connection: self.connection_write_timeout,
+        }
+    }
+}
+
+/// What a core does with new connections while a listener has `max_connections` open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Accept the connection and close it right away.
+    #[default]
+    Close,
+    /// Stop accepting until a connection closes. New connections wait in the kernel's
+    /// accept queue (see [`SocketOptions::backlog`]) and are refused once it is full.
+    Pause,
+    /// Accept the connection and wait up to this long for a slot before closing it.
+    /// Waiting connections are served in arrival order.
+    Wait(Duration),
+    /// Accept the connection, complete the TLS and HTTP/2 handshakes and send GOAWAY
+    /// with this error code, so the client knows no request was processed and can
+    /// retry elsewhere. The server's `SETTINGS` allow zero concurrent streams.
+    GoAway(GoAwayReason),
+}
+
+/// HTTP/2 error code of a GOAWAY sent by GMF itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GoAwayReason {
+    /// `ENHANCE_YOUR_CALM` (0xb): the client is generating excessive load.
+    EnhanceYourCalm,
+    /// `REFUSED_STREAM` (0x7): nothing was processed, retrying is safe.
+    RefusedStream,
+}
+
+impl GoAwayReason {
+    pub(crate) fn code(self) -> u32 {
+        match self {
+            GoAwayReason::EnhanceYourCalm => 0xb,
+            GoAwayReason::RefusedStream => 0x7,
+        }
+    }
+}
+
+/// Limits on how long a connection is kept, with gRPC's `MAX_CONNECTION_IDLE`,
+/// `MAX_CONNECTION_AGE` and `MAX_CONNECTION_AGE_GRACE` semantics.
+///
+/// A connection that reaches a limit is sent a graceful GOAWAY. Its in-flight streams
+/// finish, and the client reconnects, landing on whichever core's listener the kernel
+/// picks. Unset limits never expire.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionLifetime {
+    /// Retire a connection that has had no open stream for this long.
+    pub max_idle: Option<Duration>,
+    /// Retire a connection this long after it was accepted, give or take 10% so
+    /// connections opened together do not all reconnect at once.
+    pub max_age: Option<Duration>,
+    /// After the GOAWAY, how long in-flight streams may run before the connection is
+    /// closed. Unset waits for them indefinitely (or until shutdown).
+    pub max_age_grace: Option<Duration>,
+}
+
+/// HTTP/2 protocol settings. Unset values keep hyper's defaults.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Http2Config {
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    /// Size the flow-control windows from the measured bandwidth-delay product.
+    /// Overrides the initial window sizes.
+    pub adaptive_window: bool,
+    pub max_concurrent_streams: Option<u32>,
+    pub max_frame_size: Option<u32>,
+    pub max_header_list_size: Option<u32>,
+    pub max_send_buf_size: Option<usize>,
+    pub max_pending_accept_reset_streams: Option<usize>,
+    /// Ping the client after this long without inbound frames.
+    pub keep_alive_interval: Option<Duration>,
+    /// Close the connection if a keepalive ping is not acknowledged within this long
+    /// (hyper's default is 20 seconds).
+    pub keep_alive_timeout: Option<Duration>,
+    /// Limits on pings sent by the client. `None` accepts any ping.
+    pub ping_policy: Option<PingPolicy>,
+}
+
+/// Client ping enforcement, following gRPC's keepalive policy.
+///
+/// A ping that arrives sooner than allowed counts as a strike. A strike is forgiven
+/// each time the server sends headers or data. After more than two strikes, the
+/// server sends GOAWAY with `ENHANCE_YOUR_CALM` and debug data `too_many_pings`, then
+/// closes the connection.
+#[derive(Debug, Clone, Copy)]
+pub struct PingPolicy {
+    /// Minimum time between client pings (default 5 minutes).
+    pub min_interval: Duration,
+    /// Allow pings while no stream is open. When `false`, an idle connection may ping
+    /// at most once every two hours.
+    pub permit_without_streams: bool,
+}
+
+impl Default for PingPolicy {
+    fn default() -> Self {
+        PingPolicy {
+            min_interval: Duration::from_secs(5 * 60),
+            permit_without_streams: false,
+        }
+    }
+}
+
+impl Http2Config {
+    /// Largest flow-control window HTTP/2 allows (2^31 - 1).
+    pub(crate) const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+    /// Bounds of `SETTINGS_MAX_FRAME_SIZE`.
+    pub(crate) const MIN_FRAME_SIZE: u32 = 1 << 14;
+    pub(crate) const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+    pub(crate) fn apply<E>(&self, builder: &mut hyper::server::conn::http2::Builder<E>) {
+        if let Some(size) = self.initial_stream_window_size {
+            builder.initial_stream_window_size(size);
+        }
+        if let Some(size) = self.initial_connection_window_size {
+            builder.initial_connection_window_size(size);
+        }
+        if self.adaptive_window {
+            builder.adaptive_window(true);
+        }
+        if let Some(max) = self.max_concurrent_streams {
+            builder.max_concurrent_streams(max);
+        }
+        if let Some(size) = self.max_frame_size {
+            builder.max_frame_size(size);
+        }
+        if let Some(max) = self.max_header_list_size {
+            builder.max_header_list_size(max);
+        }
+        if let Some(max) = self.max_send_buf_size {
+            builder.max_send_buf_size(max);
+        }
+        if let Some(max) = self.max_pending_accept_reset_streams {
+            builder.max_pending_accept_reset_streams(max);
+        }
+        if let Some(interval) = self.keep_alive_interval {
+            builder.keep_alive_interval(interval);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            builder.keep_alive_timeout(timeout);
+        }
+    }
+}
+
+/// One address the server listens on, with options for the connections accepted there.
+///
+/// Options left unset (TLS, connection limit, socket options) fall back to the server-wide ones from [`GmfServerBuilder`]:
+///
+/// ```ignore
+/// GmfServer::builder()
+///     .addr("0.0.0.0:50051".parse()?)
+///     .tls(tls)
+///     .add_listener("[::]:50051".parse::<SocketAddr>()?) // same TLS, dual-stack
+///     .add_listener(ListenerConfig::new(admin_addr).plaintext().max_connections(16))
+/// ```
+///
+/// [`GmfServerBuilder`]: crate::server::gmf_server::GmfServerBuilder
+#[derive(Clone)]
+pub struct ListenerConfig {
+    pub(crate) addr: ListenAddr,
+    pub(crate) unix_strategy: Option<UnixStrategy>,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) socket_options: Option<SocketOptions>,
+    pub(crate) proxy_protocol: bool,
+    pub(crate) fds: Option<ListenFds>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
+    #[cfg(feature = "tls")]
+    pub(crate) plaintext: bool,
+}
+
+impl ListenerConfig {
+    pub fn new(addr: impl Into<ListenAddr>) -> Self {
+        ListenerConfig {
+            addr: addr.into(),
+            unix_strategy: None,
+            max_connections: None,
+            socket_options: None,
+            proxy_protocol: false,
+            fds: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "tls")]
+            plaintext: false,
+        }
+    }
+
+    /// A listener on a socket that is already listening, such as one passed in by
+    /// systemd (see [`systemd::listen_fds`]). Every core accepts from a duplicate of
+    /// it.
+    ///
+    /// The socket is used as is: bind-time socket options (backlog, `SO_REUSEPORT`)
+    /// are not applied, and a Unix socket file is not removed on shutdown.
+    ///
+    /// [`systemd::listen_fds`]: crate::server::systemd::listen_fds
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        let addr = listening_addr(&fd)?;
+        let mut listener = ListenerConfig::new(addr);
+        listener.fds = Some(ListenFds::Shared(Arc::new(fd)));
+        Ok(listener)
+    }
+
+    /// A listener on one already listening socket per core, e.g. `SO_REUSEPORT`
+    /// sockets opened by a systemd unit with one `ListenStream=` per core. Core `i`
+    /// accepts from `fds[i]`; the server fails to start unless there is exactly one fd
+    /// per core. Otherwise as [`from_fd`](Self::from_fd).
+    pub fn from_fds(fds: Vec<OwnedFd>) -> io::Result<Self> {
+        let first = fds
+            .first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no listener fds given"))?;
+        let addr = listening_addr(first)?;
+        for fd in &fds[1..] {
+            listening_addr(fd)?;
+        }
+        let mut listener = ListenerConfig::new(addr);
+        listener.fds = Some(ListenFds::PerCore(fds.into()));
+        Ok(listener)
+    }
+
+    pub fn addr(&self) -> &ListenAddr {
+        &self.addr
+    }
+
+    /// How the cores share this listener if it is a Unix socket (by default
+    /// [`UnixStrategy::Shared`], or [`UnixStrategy::PerCore`] on runtimes that cannot
+    /// share one).
+    pub fn unix_strategy(mut self, strategy: UnixStrategy) -> Self {
+        self.unix_strategy = Some(strategy);
+        self
+    }
+
+    /// Per-core connection limit for this listener, counted separately from the
+    /// server's other listeners.
+    pub fn max_connections(mut self, n: usize) -> Self {
+        self.max_connections = Some(n);
+        self
+    }
+
+    /// Socket options for this listener and its connections, replacing the server-wide
+    /// ones.
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket_options = Some(options);
+        self
+    }
+
+    /// Expect every connection on this listener to start with a PROXY protocol (v1 or
+    /// v2) header, as sent by HAProxy, Envoy or an AWS NLB in TCP mode. The client
+    /// address it carries replaces the balancer's as the peer address. Connections
+    /// without a valid header are closed.
+    ///
+    /// This applies to Unix socket listeners too, e.g. behind a local Envoy: a TCP
+    /// source address in the header replaces the Unix peer.
+    pub fn proxy_protocol(mut self) -> Self {
+        self.proxy_protocol = true;
+        self
+    }
+
+    /// Terminate TLS on this listener with its own certificate and client auth.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self.plaintext = false;
+        self
+    }
+
+    /// Serve h2c on this listener even if the server has a TLS config.
+    #[cfg(feature = "tls")]
+    pub fn plaintext(mut self) -> Self {
+        self.tls = None;
+        self.plaintext = true;
+        self
+    }
+
+    /// Fill in the options this listener left unset from the server-wide ones.
+    pub(crate) fn inherit(
+        mut self,
+        unix_strategy: UnixStrategy,
+        max_connections: usize,
+        socket_options: &SocketOptions,
+        #[cfg(feature = "tls")] tls: Option<&TlsConfig>,
+    ) -> Self {
+        self.unix_strategy.get_or_insert(unix_strategy);
+        self.max_connections.get_or_insert(max_connections);
+        self.socket_options
+            .get_or_insert_with(|| socket_options.clone());
+        #[cfg(feature = "tls")]
+        if self.tls.is_none() && !self.plaintext {
+            self.tls = tls.cloned();
+        }
+        self
+    }
+}
+
+impl From<ListenAddr> for ListenerConfig {
+    fn from(addr: ListenAddr) -> Self {
+        ListenerConfig::new(addr)
+    }
+}
+
+impl From<SocketAddr> for ListenerConfig {
+    fn from(addr: SocketAddr) -> Self {
+        ListenerConfig::new(addr)
+    }
+}
+
+impl From<UnixAddr> for ListenerConfig {
+    fn from(addr: UnixAddr) -> Self {
+        ListenerConfig::new(addr)
+    }
+}
+
+/// Sockets a listener adopts instead of binding its own.
+#[derive(Clone)]
+pub(crate) enum ListenFds {
+    /// Every core accepts from its own duplicate of one socket.
+    Shared(Arc<OwnedFd>),
+    /// Core `i` accepts from socket `i`.
+    PerCore(Arc<[OwnedFd]>),
+}
+
+impl ListenFds {
+    pub(crate) fn len(&self) -> Option<usize> {
+        match self {
+            ListenFds::Shared(_) => None,
+            ListenFds::PerCore(fds) => Some(fds.len()),
+        }
+    }
+
+    /// A duplicate of the socket core `cpu` accepts from.
+    pub(crate) fn for_core(&self, cpu: usize) -> io::Result<OwnedFd> {
+        match self {
+            ListenFds::Shared(fd) => fd.try_clone(),
+            ListenFds::PerCore(fds) => fds[cpu].try_clone(),
+        }
+    }
+}
+
+/// The address of a listening stream socket, or an error if `fd` is not one.
+fn listening_addr(fd: &OwnedFd) -> io::Result<ListenAddr> {
+    let socket = SockRef::from(fd);
+    if socket.r#type()? != Type::STREAM || !socket.is_listener()? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "fd is not a listening stream socket",
+        ));
+    }
+    let addr = socket.local_addr()?;
+    if let Some(addr) = addr.as_socket() {
+        return Ok(ListenAddr::Tcp(addr));
+    }
+    if let Some(path) = addr.as_pathname() {
+        return Ok(ListenAddr::Unix(UnixAddr::path(path)));
+    }
+    if let Some(name) = addr.as_abstract_namespace() {
+        return Ok(ListenAddr::Unix(UnixAddr::abstract_name(name)));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "unsupported listener address family",
+    ))
 }
@@ -0,0 +1,103 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http::Extensions;
+use tonic::transport::server::TcpConnectInfo;
+
+use crate::server::listener::{ListenAddr, PeerAddr};
+use crate::server::proxy::ProxyHeader;
+
+/// Where a request came from, inserted into the extensions of every request.
+///
+/// ```ignore
+/// let info = request.extensions().get::<GmfConnectInfo>().unwrap();
+/// tracing::info!(peer = %info.peer_addr(), cpu = info.cpu(), "handling request");
+/// ```
+///
+/// For TCP connections GMF also inserts tonic's own connect info (`TcpConnectInfo`, or
+/// `TlsConnectInfo<TcpConnectInfo>` under TLS), the same values tonic's `Connected`
+/// impls produce for its transport, so `Request::remote_addr()` and
+/// `Request::local_addr()` work unchanged.
+#[derive(Debug, Clone)]
+pub struct GmfConnectInfo {
+    pub(crate) peer_addr: PeerAddr,
+    pub(crate) local_addr: ListenAddr,
+    pub(crate) cpu: usize,
+    pub(crate) connection_id: u64,
+    pub(crate) proxy_header: Option<Arc<ProxyHeader>>,
+}
+
+impl GmfConnectInfo {
+    /// The client's address (or credentials, for Unix sockets). Behind a load balancer
+    /// speaking the PROXY protocol, the client address from its header.
+    pub fn peer_addr(&self) -> PeerAddr {
+        self.peer_addr
+    }
+
+    /// The address of the listener that accepted the connection.
+    pub fn local_addr(&self) -> &ListenAddr {
+        &self.local_addr
+    }
+
+    /// The core serving the connection.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Identifies the connection within this server; unique across cores.
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+
+    /// The PROXY protocol header the connection started with, on listeners that
+    /// expect one.
+    pub fn proxy_header(&self) -> Option<&ProxyHeader> {
+        self.proxy_header.as_deref()
+    }
+
+    /// The connection as tonic describes a TCP connection. Behind a load balancer
+    /// speaking the PROXY protocol, both addresses come from its header.
+    pub fn tcp_connect_info(&self) -> TcpConnectInfo {
+        let destination = self.proxy_header().and_then(ProxyHeader::destination);
+        TcpConnectInfo {
+            local_addr: destination.or(self.local_addr.as_tcp()),
+            remote_addr: self.peer_addr.as_tcp(),
+        }
+    }
+
+    /// The client's TCP address, if it connected over TCP.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr.as_tcp()
+    }
+}
+
+/// Wraps a connection's service and copies connection-level values (connect info,
+/// TLS peer info) into the extensions of every request served on that connection.
+///
+/// One instance exists per connection, so no synchronization is involved.
+pub(crate) struct WithExtensions<S> {
+    inner: S,
+    extensions: Extensions,
+}
+
+impl<S> WithExtensions<S> {
+    pub(crate) fn new(inner: S, extensions: Extensions) -> Self {
+        WithExtensions { inner, extensions }
+    }
+}
+
+impl<S, ReqBody> hyper::service::Service<hyper::Request<ReqBody>> for WithExtensions<S>
+where
+    S: hyper::service::Service<hyper::Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: hyper::Request<ReqBody>) -> Self::Future {
+        if !self.extensions.is_empty() {
+            req.extensions_mut().extend(self.extensions.clone());
+        }
+        self.inner.call(req)
+    }
+}
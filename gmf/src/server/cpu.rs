@@ -0,0 +1,565 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use crate::server::error::GmfError;
+use crate::server::topology::CpuTopology;
+
+/// One past the highest CPU number a list may name: the kernel's largest `NR_CPUS`.
+const MAX_CPUS: usize = 8192;
+
+/// Bits per word of an affinity mask.
+#[cfg(target_os = "linux")]
+const MASK_BITS: usize = libc::c_ulong::BITS as usize;
+
+/// An affinity mask covering every CPU up to [`MAX_CPUS`]; `cpu_set_t` only covers
+/// 1024.
+#[cfg(target_os = "linux")]
+type CpuMask = [libc::c_ulong; MAX_CPUS / MASK_BITS];
+
+/// A set of logical CPUs, written in the kernel's list format (`0-3,8,10-11`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuSet(BTreeSet<usize>);
+
+impl CpuSet {
+    pub fn new() -> Self {
+        CpuSet::default()
+    }
+
+    /// The CPUs this process may run on (`sched_getaffinity`), which reflects `taskset`,
+    /// cpuset cgroups and `isolcpus`. Outside Linux, every online CPU.
+    pub fn allowed() -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let mut mask: CpuMask = [0; MAX_CPUS / MASK_BITS];
+            let size = std::mem::size_of_val(&mask);
+            if unsafe { libc::sched_getaffinity(0, size, mask.as_mut_ptr().cast()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((0..MAX_CPUS)
+                .filter(|&cpu| mask[cpu / MASK_BITS] & (1 << (cpu % MASK_BITS)) != 0)
+                .collect())
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Ok((0..num_cpus::get()).collect())
+        }
+    }
+
+    pub fn insert(&mut self, cpu: usize) -> bool {
+        self.0.insert(cpu)
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        self.0.contains(&cpu)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The CPUs in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn intersection(&self, other: &CpuSet) -> CpuSet {
+        CpuSet(self.0.intersection(&other.0).copied().collect())
+    }
+}
+
+impl FromIterator<usize> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        CpuSet(iter.into_iter().collect())
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = GmfError;
+
+    /// Parse a CPU list such as `0-3,8,10-11`. Surrounding whitespace is ignored and an
+    /// empty list is an empty set, as in sysfs and cgroup files. CPUs must be below
+    /// 8192, the most the kernel supports.
+    fn from_str(s: &str) -> Result<Self, GmfError> {
+        let invalid = || GmfError::InvalidCpuList(s.to_owned());
+        let mut set = CpuSet::new();
+        let list = s.trim();
+        if list.is_empty() {
+            return Ok(set);
+        }
+        for part in list.split(',') {
+            let (first, last) = match part.split_once('-') {
+                Some((first, last)) => (first, last),
+                None => (part, part),
+            };
+            let first: usize = first.parse().map_err(|_| invalid())?;
+            let last: usize = last.parse().map_err(|_| invalid())?;
+            if first > last || last >= MAX_CPUS {
+                return Err(invalid());
+            }
+            set.0.extend(first..=last);
+        }
+        Ok(set)
+    }
+}
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cpus = self.iter().peekable();
+        let mut first = true;
+        while let Some(start) = cpus.next() {
+            let mut end = start;
+            while cpus.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+            match end == start {
+                true => write!(f, "{start}")?,
+                false => write!(f, "{start}-{end}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How core threads are pinned to the CPUs the server may use (see
+/// [`GmfServerBuilder::cpus`]).
+///
+/// [`GmfServerBuilder::cpus`]: crate::server::gmf_server::GmfServerBuilder::cpus
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PinPolicy {
+    /// Core `i` runs on the `i`-th CPU of this list. Sets the core count when
+    /// `num_cores` is not set.
+    Fixed(Vec<usize>),
+    /// Spread the cores evenly over the physical cores, e.g. 4 cores on CPUs 0-15
+    /// without SMT run on 0, 4, 8 and 12.
+    Spread,
+    /// Pack the cores onto the lowest-numbered physical cores.
+    #[default]
+    Pack,
+    /// Do not pin; the core threads run anywhere in the CPU set.
+    Unpinned,
+}
+
+/// The CPU each core's thread is pinned to, decided when the server starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorePlacement {
+    cpus: Vec<Option<usize>>,
+    topology: CpuTopology,
+    numa: bool,
+}
+
+impl CorePlacement {
+    /// `cores` threads that are not pinned.
+    pub fn unpinned(cores: usize) -> Self {
+        CorePlacement {
+            cpus: vec![None; cores],
+            topology: CpuTopology::default(),
+            numa: false,
+        }
+    }
+
+    /// Pin core `i` to `cpus[i]`.
+    pub fn fixed(cpus: impl IntoIterator<Item = usize>) -> Self {
+        CorePlacement {
+            cpus: cpus.into_iter().map(Some).collect(),
+            topology: CpuTopology::default(),
+            numa: false,
+        }
+    }
+
+    pub fn cores(&self) -> usize {
+        self.cpus.len()
+    }
+
+    /// The CPU core `core` is pinned to, if any.
+    pub fn cpu(&self, core: usize) -> Option<usize> {
+        self.cpus.get(core).copied().flatten()
+    }
+
+    /// Each core's CPU, indexed by core.
+    pub fn iter(&self) -> impl Iterator<Item = Option<usize>> + '_ {
+        self.cpus.iter().copied()
+    }
+
+    /// The topology the placement was planned on, to look up each CPU's physical core.
+    pub fn topology(&self) -> &CpuTopology {
+        &self.topology
+    }
+
+    /// The NUMA node of the CPU core `core` is pinned to, if known.
+    pub fn node(&self, core: usize) -> Option<usize> {
+        self.topology.cpu(self.cpu(core)?)?.node
+    }
+
+    /// Whether each core's thread prefers memory from its own NUMA node.
+    pub fn numa_local(&self) -> bool {
+        self.numa
+    }
+
+    /// Pin the calling thread, i.e. core `core`'s thread, to its CPU and, with
+    /// [`numa_local`](Self::numa_local), prefer memory from that CPU's node. Called
+    /// before the core builds its runtime, so its rings and buffers are allocated there.
+    pub(crate) fn bind_current_thread(&self, core: usize) -> Result<(), GmfError> {
+        let Some(cpu) = self.cpu(core) else {
+            return Ok(());
+        };
+        pin_current_thread(core, cpu)?;
+        if let Some(node) = self.node(core).filter(|_| self.numa) {
+            if let Err(e) = prefer_node(node) {
+                tracing::warn!(
+                    core = core,
+                    node = node,
+                    error = %e,
+                    "could not set a node-local memory policy, memory may come from any node"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Place `cores` cores on `cpus` under `policy`.
+    ///
+    /// By default there is one core per physical core, on its lowest-numbered CPU, so
+    /// that no two cores share a physical core through SMT. Beyond that, the SMT
+    /// siblings are used before cores share a CPU. Without a topology every CPU counts
+    /// as a physical core, but the default core count stays the number of physical
+    /// cores reported by the OS.
+    ///
+    /// `quota` is the CPU time the process may use, in CPUs (see [`CgroupCpu`]). The
+    /// default core count does not exceed it, and more cores than it allows are warned
+    /// about since their threads get throttled.
+    ///
+    /// With `numa`, `Pack` and `Spread` take the physical cores node by node, so `Pack`
+    /// fills one NUMA node before the next and `Spread` balances the nodes, and cores on
+    /// the same node get consecutive indices. Each core's thread then prefers memory
+    /// from its node.
+    ///
+    /// [`CgroupCpu`]: crate::server::cgroup::CgroupCpu
+    pub fn plan(
+        cpus: &CpuSet,
+        topology: CpuTopology,
+        cores: Option<usize>,
+        quota: Option<f64>,
+        policy: &PinPolicy,
+        numa: bool,
+    ) -> Result<Self, GmfError> {
+        if cpus.is_empty() {
+            return Err(GmfError::Placement("no CPUs available".into()));
+        }
+        let node = |cpu: usize| topology.cpu(cpu).and_then(|logical| logical.node);
+        let mut physical = topology.physical_cores(cpus);
+        if numa {
+            // Unknown nodes last; stable, so each node keeps its ascending order.
+            physical.sort_by_key(|core| node(core[0]).unwrap_or(usize::MAX));
+        }
+        let depth = physical.iter().map(Vec::len).max().unwrap_or(0);
+        // One CPU of each physical core, then the second of each, and so on.
+        let available: Vec<usize> = (0..depth)
+            .flat_map(|thread| physical.iter().filter_map(move |core| core.get(thread)))
+            .copied()
+            .collect();
+        let quota_cores = quota.map(|quota| (quota.floor() as usize).max(1));
+        let cores = cores
+            .unwrap_or_else(|| match policy {
+                PinPolicy::Fixed(list) => list.len(),
+                _ if topology.is_empty() => num_cpus::get_physical()
+                    .min(cpus.len())
+                    .min(quota_cores.unwrap_or(usize::MAX)),
+                _ => physical.len().min(quota_cores.unwrap_or(usize::MAX)),
+            })
+            .max(1);
+        if let (Some(quota), Some(quota_cores)) = (quota, quota_cores) {
+            if cores > quota_cores {
+                tracing::warn!(
+                    cores = cores,
+                    quota = quota,
+                    pinning = ?policy,
+                    "more cores than the cgroup CPU quota allows, their threads will be throttled; \
+                     lower num_cores or raise the quota"
+                );
+            }
+        }
+        if cores > available.len() && matches!(policy, PinPolicy::Spread | PinPolicy::Pack) {
+            tracing::warn!(
+                cores = cores,
+                cpus = %cpus,
+                "more cores than CPUs, some cores share a CPU"
+            );
+        }
+
+        let mut cpus: Vec<Option<usize>> = match policy {
+            PinPolicy::Fixed(list) => {
+                if list.len() < cores {
+                    return Err(GmfError::Placement(format!(
+                        "{} fixed CPUs for {cores} cores",
+                        list.len()
+                    )));
+                }
+                if let Some(cpu) = list.iter().find(|&&cpu| !cpus.contains(cpu)) {
+                    return Err(GmfError::Placement(format!(
+                        "CPU {cpu} is not in the allowed set {cpus}"
+                    )));
+                }
+                list[..cores].iter().copied().map(Some).collect()
+            }
+            PinPolicy::Spread if cores <= physical.len() => (0..cores)
+                .map(|core| Some(available[core * physical.len() / cores]))
+                .collect(),
+            PinPolicy::Spread | PinPolicy::Pack => (0..cores)
+                .map(|core| Some(available[core % available.len()]))
+                .collect(),
+            PinPolicy::Unpinned => vec![None; cores],
+        };
+        if numa && matches!(policy, PinPolicy::Spread | PinPolicy::Pack) {
+            // SMT siblings are taken after every physical core; keep them with their node.
+            cpus.sort_by_key(|cpu| cpu.and_then(node).unwrap_or(usize::MAX));
+        }
+        Ok(CorePlacement {
+            cpus,
+            topology,
+            numa,
+        })
+    }
+}
+
+impl fmt::Display for CorePlacement {
+    /// `core 0 -> cpu 2 (node 0, package 0, core 1), core 1 -> unpinned`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (core, cpu) in self.iter().enumerate() {
+            if core > 0 {
+                f.write_str(", ")?;
+            }
+            match cpu.map(|cpu| (cpu, self.topology.cpu(cpu))) {
+                Some((_, Some(logical))) => write!(f, "core {core} -> {logical}")?,
+                Some((cpu, None)) => write!(f, "core {core} -> cpu {cpu}")?,
+                None => write!(f, "core {core} -> unpinned")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pin the calling thread, i.e. core `core`'s thread, to `cpu`. A no-op outside Linux.
+fn pin_current_thread(core: usize, cpu: usize) -> Result<(), GmfError> {
+    #[cfg(target_os = "linux")]
+    {
+        let error = |source| GmfError::Affinity { core, cpu, source };
+        if cpu >= MAX_CPUS {
+            return Err(error(io::Error::from(io::ErrorKind::InvalidInput)));
+        }
+        let mut mask: CpuMask = [0; MAX_CPUS / MASK_BITS];
+        mask[cpu / MASK_BITS] |= 1 << (cpu % MASK_BITS);
+        let size = std::mem::size_of_val(&mask);
+        if unsafe { libc::sched_setaffinity(0, size, mask.as_ptr().cast()) } != 0 {
+            return Err(error(io::Error::last_os_error()));
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (core, cpu);
+    Ok(())
+}
+
+/// Prefer memory from `node` for the calling thread's allocations (`set_mempolicy` with
+/// `MPOL_PREFERRED`); the kernel falls back to other nodes when it runs out. A no-op
+/// outside Linux.
+fn prefer_node(node: usize) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let bits = libc::c_ulong::BITS as usize;
+        // One spare word: the kernel reads one bit less than `maxnode`.
+        let mut mask = vec![0 as libc::c_ulong; node / bits + 2];
+        mask[node / bits] |= 1 << (node % bits);
+        let maxnode = (mask.len() * bits) as libc::c_ulong;
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_set_mempolicy,
+                libc::MPOL_PREFERRED,
+                mask.as_ptr(),
+                maxnode,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = node;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::topology::tests::{flat, two_nodes};
+
+    fn cpus(list: &str) -> CpuSet {
+        list.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_displays_lists() {
+        let set = cpus("0-3,8,10-11");
+        assert_eq!(set.iter().collect::<Vec<_>>(), [0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(set.to_string(), "0-3,8,10-11");
+        for list in ["0", "0-1", "1,3,5", "0-8191", "2-3,6-7"] {
+            assert_eq!(cpus(list).to_string(), list);
+        }
+        // Overlapping, unordered and padded lists come back in canonical form.
+        assert_eq!(cpus(" 4,0-2,1-3\n").to_string(), "0-4");
+        assert_eq!(cpus("5-5").to_string(), "5");
+    }
+
+    #[test]
+    fn empty_list_is_empty_set() {
+        assert!(cpus("").is_empty());
+        assert!(cpus(" \n").is_empty());
+        assert_eq!(CpuSet::new().to_string(), "");
+    }
+
+    #[test]
+    fn rejects_invalid_lists() {
+        for list in [
+            "3-1", "0,1,", ",0", "0,,1", "0-", "-3", "0-1-2", "a", "1.5", "-1", "0-8192", "8192",
+        ] {
+            assert!(
+                matches!(list.parse::<CpuSet>(), Err(GmfError::InvalidCpuList(input)) if input == list),
+                "{list:?}"
+            );
+        }
+    }
+
+    /// The number of cores `plan` picks by default.
+    fn default_cores(topology: CpuTopology, quota: Option<f64>, policy: PinPolicy) -> usize {
+        let cpus = cpus("0-3");
+        CorePlacement::plan(&cpus, topology, None, quota, &policy, false)
+            .unwrap()
+            .cores()
+    }
+
+    #[test]
+    fn quota_caps_default_cores() {
+        let cores = |quota| default_cores(flat(4), quota, PinPolicy::Pack);
+        assert_eq!(cores(None), 4);
+        assert_eq!(cores(Some(8.0)), 4);
+        assert_eq!(cores(Some(2.0)), 2);
+        // Rounded down, but at least one core.
+        assert_eq!(cores(Some(2.5)), 2);
+        assert_eq!(cores(Some(1.99)), 1);
+        assert_eq!(cores(Some(0.5)), 1);
+        assert_eq!(default_cores(flat(4), Some(2.5), PinPolicy::Spread), 2);
+        assert_eq!(default_cores(flat(4), Some(2.5), PinPolicy::Unpinned), 2);
+    }
+
+    #[test]
+    fn quota_does_not_override_explicit_cores() {
+        let placement = CorePlacement::plan(
+            &cpus("0-3"),
+            flat(4),
+            Some(4),
+            Some(1.5),
+            &PinPolicy::Pack,
+            false,
+        )
+        .unwrap();
+        assert_eq!(placement.cores(), 4);
+        assert_eq!(
+            default_cores(flat(4), Some(1.0), PinPolicy::Fixed(vec![0, 1, 2])),
+            3
+        );
+    }
+
+    fn plan(cores: Option<usize>, policy: PinPolicy, numa: bool) -> CorePlacement {
+        CorePlacement::plan(&cpus("0-7"), two_nodes(), cores, None, &policy, numa).unwrap()
+    }
+
+    fn pinned(placement: &CorePlacement) -> Vec<usize> {
+        placement.iter().map(Option::unwrap).collect()
+    }
+
+    fn nodes(placement: &CorePlacement) -> Vec<usize> {
+        (0..placement.cores())
+            .map(|core| placement.node(core).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn one_core_per_physical_core_by_default() {
+        let placement = plan(None, PinPolicy::Pack, true);
+        assert_eq!(pinned(&placement), [0, 2, 1, 3]);
+        assert_eq!(nodes(&placement), [0, 0, 1, 1]);
+        assert!(placement.numa_local());
+    }
+
+    #[test]
+    fn pack_fills_a_node_first() {
+        let placement = plan(Some(2), PinPolicy::Pack, true);
+        assert_eq!(pinned(&placement), [0, 2]);
+        assert_eq!(nodes(&placement), [0, 0]);
+        // SMT siblings on the first node are used before the second node.
+        let placement = plan(Some(6), PinPolicy::Pack, true);
+        assert_eq!(pinned(&placement), [0, 2, 4, 6, 1, 3]);
+        assert_eq!(nodes(&placement), [0, 0, 0, 0, 1, 1]);
+        // Without NUMA, the lowest-numbered physical cores, across nodes.
+        let placement = plan(Some(2), PinPolicy::Pack, false);
+        assert_eq!(pinned(&placement), [0, 1]);
+        assert_eq!(nodes(&placement), [0, 1]);
+        assert!(!placement.numa_local());
+    }
+
+    #[test]
+    fn spread_balances_nodes() {
+        let placement = plan(Some(2), PinPolicy::Spread, true);
+        assert_eq!(pinned(&placement), [0, 1]);
+        assert_eq!(nodes(&placement), [0, 1]);
+        let placement = plan(Some(4), PinPolicy::Spread, true);
+        assert_eq!(nodes(&placement), [0, 0, 1, 1]);
+        // Beyond the physical cores, each node takes its SMT siblings.
+        let placement = plan(Some(8), PinPolicy::Spread, true);
+        assert_eq!(pinned(&placement), [0, 2, 4, 6, 1, 3, 5, 7]);
+        assert_eq!(nodes(&placement), [0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn node_of_unpinned_or_unknown_core() {
+        let placement = plan(Some(2), PinPolicy::Unpinned, true);
+        assert_eq!(placement.node(0), None);
+        let placement = plan(Some(2), PinPolicy::Spread, true);
+        assert_eq!(placement.node(2), None);
+        assert_eq!(
+            placement.to_string(),
+            "core 0 -> cpu 0 (node 0, package 0, core 0), core 1 -> cpu 1 (node 1, package 1, core 0)"
+        );
+    }
+
+    #[test]
+    fn pinning_narrows_the_allowed_set() {
+        let allowed = CpuSet::allowed().unwrap();
+        let cpu = allowed.iter().last().expect("no allowed CPUs");
+        let pinned = std::thread::spawn(move || {
+            pin_current_thread(0, cpu).unwrap();
+            CpuSet::allowed().unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(pinned.iter().collect::<Vec<_>>(), [cpu]);
+        assert!(matches!(
+            pin_current_thread(0, MAX_CPUS),
+            Err(GmfError::Affinity { .. })
+        ));
+    }
+
+    #[test]
+    fn intersection() {
+        assert_eq!(cpus("0-7").intersection(&cpus("4-11")), cpus("4-7"));
+        assert!(cpus("0-1").intersection(&cpus("2-3")).is_empty());
+    }
+}
@@ -1,10 +1,37 @@
 use std::io;
-use std::net::SocketAddr;
+
+use crate::server::listener::ListenAddr;
 
 #[derive(Debug, thiserror::Error)]
 pub enum GmfError {
     #[error("bind failed on {addr}")]
-    Bind { addr: SocketAddr, source: io::Error },
+    Bind { addr: ListenAddr, source: io::Error },
+
+    #[error("unsupported listener {addr}: {reason}")]
+    UnsupportedListener {
+        addr: ListenAddr,
+        reason: &'static str,
+    },
+
+    #[error("{addr} has {fds} inherited listener fds for {cores} cores")]
+    ListenFdCount {
+        addr: ListenAddr,
+        fds: usize,
+        cores: usize,
+    },
+
+    #[error("invalid CPU list {0:?}")]
+    InvalidCpuList(String),
+
+    #[error("invalid core placement: {0}")]
+    Placement(String),
+
+    #[error("failed to pin core {core} to CPU {cpu}")]
+    Affinity {
+        core: usize,
+        cpu: usize,
+        source: io::Error,
+    },
 
     #[error("executor spawn failed on CPU {cpu}")]
     SpawnExecutor { cpu: usize, source: io::Error },
@@ -15,6 +42,16 @@ pub enum GmfError {
     #[error("hyper error")]
     Hyper(#[from] hyper::Error),
 
+    #[error("invalid CIDR network {0:?}")]
+    InvalidCidr(String),
+
+    #[error("upgrade failed")]
+    Upgrade(#[source] io::Error),
+
     #[error("IO error")]
     Io(#[from] io::Error),
+
+    #[cfg(feature = "tls")]
+    #[error("invalid TLS configuration")]
+    TlsConfig(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
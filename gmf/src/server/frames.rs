@@ -0,0 +1,855 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::server::config::{GoAwayReason, PingPolicy};
+use crate::server::listener::PeerAddr;
+
+/// Client connection preface, sent before the first frame.
+const PREFACE_LEN: usize = 24;
+const FRAME_HEADER_LEN: usize = 9;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const FLAG_ACK: u8 = 0x1;
+
+/// Strikes tolerated before a pinging client is sent GOAWAY (gRPC's `maxPingStrikes`).
+const MAX_PING_STRIKES: u32 = 2;
+/// Ping interval allowed on a connection with no open streams when the policy does not
+/// permit such pings, matching gRPC.
+const IDLE_PING_INTERVAL: Duration = Duration::from_secs(2 * 60 * 60);
+
+struct FrameHeader {
+    len: u32,
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+}
+
+impl FrameHeader {
+    fn parse(raw: &[u8; FRAME_HEADER_LEN]) -> Self {
+        FrameHeader {
+            len: u32::from_be_bytes([0, raw[0], raw[1], raw[2]]),
+            kind: raw[3],
+            flags: raw[4],
+            stream_id: u32::from_be_bytes([raw[5], raw[6], raw[7], raw[8]]) & 0x7fff_ffff,
+        }
+    }
+}
+
+/// Splits one direction of an HTTP/2 byte stream into frames, looking only at headers.
+struct FrameParser {
+    preface: usize,
+    header: [u8; FRAME_HEADER_LEN],
+    filled: usize,
+    payload: usize,
+    /// Frames passed in full, payload included.
+    complete: u64,
+}
+
+impl FrameParser {
+    fn new(preface: usize) -> Self {
+        FrameParser {
+            preface,
+            header: [0; FRAME_HEADER_LEN],
+            filled: 0,
+            payload: 0,
+            complete: 0,
+        }
+    }
+
+    fn feed(&mut self, mut data: &[u8], mut on_frame: impl FnMut(FrameHeader)) {
+        while !data.is_empty() {
+            let n = if self.preface > 0 {
+                let n = self.preface.min(data.len());
+                self.preface -= n;
+                n
+            } else if self.payload > 0 {
+                let n = self.payload.min(data.len());
+                self.payload -= n;
+                if self.payload == 0 {
+                    self.complete += 1;
+                }
+                n
+            } else {
+                let n = (FRAME_HEADER_LEN - self.filled).min(data.len());
+                self.header[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+                self.filled += n;
+                if self.filled == FRAME_HEADER_LEN {
+                    self.filled = 0;
+                    let header = FrameHeader::parse(&self.header);
+                    self.payload = header.len as usize;
+                    if self.payload == 0 {
+                        self.complete += 1;
+                    }
+                    on_frame(header);
+                }
+                n
+            };
+            data = &data[n..];
+        }
+    }
+
+    /// Bytes that can pass before the stream is between two frames. Stops at the end of
+    /// a partial header, since the payload length is not known before then.
+    fn until_boundary(&self) -> usize {
+        if self.preface > 0 {
+            self.preface
+        } else if self.filled > 0 {
+            FRAME_HEADER_LEN - self.filled
+        } else {
+            self.payload
+        }
+    }
+}
+
+/// HTTP/2 frame and stream tracking for one connection, shared by its IO and stream
+/// executor.
+///
+/// hyper does not expose the frames it receives or let the server pick a GOAWAY error
+/// code, so GMF watches the plaintext byte stream under hyper and writes its own GOAWAY
+/// between two of hyper's frames when a client has to be cut off.
+#[derive(Clone)]
+pub(crate) struct ConnFrames(Rc<Inner>);
+
+struct Inner {
+    peer_addr: PeerAddr,
+    cpu: usize,
+    inbound: RefCell<FrameParser>,
+    outbound: RefCell<FrameParser>,
+    last_stream_id: Cell<u32>,
+    active_streams: Cell<usize>,
+    idle_since: Cell<Option<Instant>>,
+    pings: Option<PingStrikes>,
+    goaway: RefCell<Option<Goaway>>,
+    read_waker: RefCell<Option<Waker>>,
+}
+
+struct PingStrikes {
+    policy: PingPolicy,
+    last: Cell<Option<Instant>>,
+    strikes: Cell<u32>,
+}
+
+struct Goaway {
+    frame: Vec<u8>,
+    written: usize,
+    sent: bool,
+    debug: &'static str,
+}
+
+impl ConnFrames {
+    pub(crate) fn new(peer_addr: PeerAddr, cpu: usize, ping_policy: Option<PingPolicy>) -> Self {
+        ConnFrames(Rc::new(Inner {
+            peer_addr,
+            cpu,
+            inbound: RefCell::new(FrameParser::new(PREFACE_LEN)),
+            outbound: RefCell::new(FrameParser::new(0)),
+            last_stream_id: Cell::new(0),
+            active_streams: Cell::new(0),
+            idle_since: Cell::new(Some(Instant::now())),
+            pings: ping_policy.map(|policy| PingStrikes {
+                policy,
+                last: Cell::new(None),
+                strikes: Cell::new(0),
+            }),
+            goaway: RefCell::new(None),
+            read_waker: RefCell::new(None),
+        }))
+    }
+
+    /// Count a stream as open until the returned guard is dropped.
+    pub(crate) fn stream_opened(&self) -> OpenStream {
+        let active = &self.0.active_streams;
+        active.set(active.get() + 1);
+        self.0.idle_since.set(None);
+        OpenStream(self.clone())
+    }
+
+    /// Whether the client has sent the connection preface and a complete first frame,
+    /// which HTTP/2 requires to be its `SETTINGS`.
+    pub(crate) fn handshake_done(&self) -> bool {
+        self.0.inbound.borrow().complete > 0
+    }
+
+    /// How long the connection has had no open stream, or `None` while one is open.
+    pub(crate) fn idle_for(&self, now: Instant) -> Option<Duration> {
+        self.0
+            .idle_since
+            .get()
+            .map(|since| now.saturating_duration_since(since))
+    }
+
+    /// Debug data of the GOAWAY GMF sent on this connection, if any.
+    pub(crate) fn goaway_sent(&self) -> Option<&'static str> {
+        self.0.goaway.borrow().as_ref().map(|goaway| goaway.debug)
+    }
+
+    fn received(&self, data: &[u8]) {
+        self.0
+            .inbound
+            .borrow_mut()
+            .feed(data, |frame| match frame.kind {
+                HEADERS if frame.stream_id % 2 == 1 => {
+                    let last = &self.0.last_stream_id;
+                    last.set(last.get().max(frame.stream_id));
+                }
+                PING if frame.flags & FLAG_ACK == 0 => self.client_ping(),
+                _ => {}
+            });
+    }
+
+    fn sent(&self, data: &[u8]) {
+        self.0.outbound.borrow_mut().feed(data, |frame| {
+            if let (DATA | HEADERS, Some(pings)) = (frame.kind, &self.0.pings) {
+                pings.strikes.set(0);
+            }
+        });
+        if self.goaway_pending() && self.0.outbound.borrow().until_boundary() == 0 {
+            if let Some(waker) = self.0.read_waker.borrow_mut().take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn client_ping(&self) {
+        let Some(pings) = &self.0.pings else {
+            return;
+        };
+        let now = Instant::now();
+        let min_interval =
+            if self.0.active_streams.get() == 0 && !pings.policy.permit_without_streams {
+                IDLE_PING_INTERVAL
+            } else {
+                pings.policy.min_interval
+            };
+        let too_soon = pings
+            .last
+            .replace(Some(now))
+            .is_some_and(|last| now.duration_since(last) < min_interval);
+        if !too_soon {
+            return;
+        }
+
+        let strikes = pings.strikes.get() + 1;
+        pings.strikes.set(strikes);
+        if strikes > MAX_PING_STRIKES {
+            tracing::warn!(
+                cpu = self.0.cpu,
+                peer = %self.0.peer_addr,
+                "client pinged too often, sending GOAWAY"
+            );
+            self.goaway(GoAwayReason::EnhanceYourCalm, "too_many_pings");
+        }
+    }
+
+    /// Queue a GOAWAY to be written at the next frame boundary, after which the
+    /// connection is closed.
+    fn goaway(&self, reason: GoAwayReason, debug: &'static str) {
+        let mut goaway = self.0.goaway.borrow_mut();
+        if goaway.is_some() {
+            return;
+        }
+
+        let mut frame = Vec::new();
+        goaway_frame(&mut frame, self.0.last_stream_id.get(), reason, debug);
+        *goaway = Some(Goaway {
+            frame,
+            written: 0,
+            sent: false,
+            debug,
+        });
+    }
+
+    fn goaway_pending(&self) -> bool {
+        self.0.goaway.borrow().is_some()
+    }
+
+    /// Write the queued GOAWAY once hyper's output is between frames. Resolves to the
+    /// error handed back to hyper to end the connection.
+    fn poll_goaway<I: hyper::rt::Write + Unpin>(
+        &self,
+        cx: &mut Context<'_>,
+        io: &mut I,
+    ) -> Poll<io::Error> {
+        let mut goaway = self.0.goaway.borrow_mut();
+        let Some(goaway) = goaway.as_mut() else {
+            unreachable!("no GOAWAY queued");
+        };
+        if !goaway.sent {
+            if self.0.outbound.borrow().until_boundary() > 0 {
+                *self.0.read_waker.borrow_mut() = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            while goaway.written < goaway.frame.len() {
+                match ready!(Pin::new(&mut *io).poll_write(cx, &goaway.frame[goaway.written..])) {
+                    Ok(0) => return Poll::Ready(io::ErrorKind::WriteZero.into()),
+                    Ok(n) => goaway.written += n,
+                    Err(e) => return Poll::Ready(e),
+                }
+            }
+            if let Err(e) = ready!(Pin::new(&mut *io).poll_flush(cx)) {
+                return Poll::Ready(e);
+            }
+            goaway.sent = true;
+        }
+        Poll::Ready(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            format!("sent GOAWAY: {}", goaway.debug),
+        ))
+    }
+}
+
+fn goaway_frame(out: &mut Vec<u8>, last_stream_id: u32, reason: GoAwayReason, debug: &str) {
+    let len = 8 + debug.len() as u32;
+    out.extend_from_slice(&len.to_be_bytes()[1..]);
+    out.extend_from_slice(&[GOAWAY, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&last_stream_id.to_be_bytes());
+    out.extend_from_slice(&reason.code().to_be_bytes());
+    out.extend_from_slice(debug.as_bytes());
+}
+
+/// Complete the HTTP/2 handshake on a connection that will not be served, then send
+/// GOAWAY and close it.
+///
+/// The server's `SETTINGS` allow no concurrent streams, and the GOAWAY names stream 0
+/// as the last one processed, so the client can safely retry every request.
+pub(crate) async fn refuse<I>(mut io: I, reason: GoAwayReason) -> io::Result<()>
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin,
+{
+    // SETTINGS with SETTINGS_MAX_CONCURRENT_STREAMS (0x3) = 0.
+    let settings = [0, 0, 6, SETTINGS, 0, 0, 0, 0, 0, 0, 0x3, 0, 0, 0, 0];
+    write_all(&mut io, &settings).await?;
+
+    let mut inbound = FrameParser::new(PREFACE_LEN);
+    let mut buf = [0; 512];
+    while inbound.complete == 0 {
+        let n = std::future::poll_fn(|cx| {
+            let mut read = hyper::rt::ReadBuf::new(&mut buf);
+            ready!(Pin::new(&mut io).poll_read(cx, read.unfilled()))?;
+            Poll::Ready(Ok::<_, io::Error>(read.filled().len()))
+        })
+        .await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        inbound.feed(&buf[..n], |_| {});
+    }
+
+    let mut out = vec![0, 0, 0, SETTINGS, FLAG_ACK, 0, 0, 0, 0];
+    goaway_frame(&mut out, 0, reason, "overloaded");
+    write_all(&mut io, &out).await?;
+    std::future::poll_fn(|cx| Pin::new(&mut io).poll_flush(cx)).await?;
+    std::future::poll_fn(|cx| Pin::new(&mut io).poll_shutdown(cx)).await
+}
+
+async fn write_all<I: hyper::rt::Write + Unpin>(io: &mut I, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        let n = std::future::poll_fn(|cx| Pin::new(&mut *io).poll_write(cx, data)).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        data = &data[n..];
+    }
+    Ok(())
+}
+
+/// Guard for a stream counted by [`ConnFrames::stream_opened`].
+pub(crate) struct OpenStream(ConnFrames);
+
+impl Drop for OpenStream {
+    fn drop(&mut self) {
+        let inner = &self.0 .0;
+        inner.active_streams.set(inner.active_streams.get() - 1);
+        if inner.active_streams.get() == 0 {
+            inner.idle_since.set(Some(Instant::now()));
+        }
+    }
+}
+
+/// Connection IO that feeds [`ConnFrames`] and injects its GOAWAY. A plain passthrough
+/// when the connection has no frame tracking.
+pub(crate) struct FrameIo<I> {
+    io: I,
+    frames: Option<ConnFrames>,
+}
+
+impl<I> FrameIo<I> {
+    pub(crate) fn new(io: I, frames: Option<ConnFrames>) -> Self {
+        FrameIo { io, frames }
+    }
+}
+
+impl<I: hyper::rt::Read + hyper::rt::Write + Unpin> hyper::rt::Read for FrameIo<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let Some(frames) = &this.frames else {
+            return Pin::new(&mut this.io).poll_read(cx, buf);
+        };
+        if frames.goaway_pending() {
+            return frames.poll_goaway(cx, &mut this.io).map(Err);
+        }
+
+        let filled = {
+            // SAFETY: the inner reader only initializes bytes, and only the bytes it
+            // reports as filled are handed on to `buf`.
+            let mut inner = hyper::rt::ReadBuf::uninit(unsafe { buf.as_mut() });
+            ready!(Pin::new(&mut this.io).poll_read(cx, inner.unfilled()))?;
+            frames.received(inner.filled());
+            inner.filled().len()
+        };
+        // SAFETY: `filled` bytes were initialized by the read above.
+        unsafe { buf.advance(filled) };
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: hyper::rt::Write + Unpin> hyper::rt::Write for FrameIo<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(frames) = &this.frames else {
+            return Pin::new(&mut this.io).poll_write(cx, buf);
+        };
+
+        let mut buf = buf;
+        if frames.goaway_pending() {
+            // Finish the frame hyper is in the middle of, then write the GOAWAY.
+            let limit = frames.0.outbound.borrow().until_boundary();
+            if limit == 0 {
+                return frames.poll_goaway(cx, &mut this.io).map(Err);
+            }
+            buf = &buf[..limit.min(buf.len())];
+        }
+        let n = ready!(Pin::new(&mut this.io).poll_write(cx, buf))?;
+        frames.sent(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(frames) = &this.frames else {
+            return Pin::new(&mut this.io).poll_write_vectored(cx, bufs);
+        };
+        if frames.goaway_pending() {
+            let buf = bufs
+                .iter()
+                .find(|buf| !buf.is_empty())
+                .map_or(&[][..], |buf| buf);
+            return Pin::new(this).poll_write(cx, buf);
+        }
+
+        let mut n = ready!(Pin::new(&mut this.io).poll_write_vectored(cx, bufs))?;
+        let written = n;
+        for buf in bufs {
+            let len = buf.len().min(n);
+            frames.sent(&buf[..len]);
+            n -= len;
+            if n == 0 {
+                break;
+            }
+        }
+        Poll::Ready(Ok(written))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    const PREFACE: &[u8; PREFACE_LEN] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+    fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        out.extend_from_slice(&[kind, flags]);
+        out.extend_from_slice(&stream_id.to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn ping() -> Vec<u8> {
+        frame(PING, 0, 0, &[0; 8])
+    }
+
+    /// Feed `data` in chunks ending at each of `splits`, recording every frame header.
+    fn parse_split(data: &[u8], splits: &[usize]) -> (Vec<(u8, u32, u32)>, u64) {
+        let mut parser = FrameParser::new(PREFACE_LEN);
+        let mut frames = Vec::new();
+        let mut start = 0;
+        for &end in splits.iter().chain([data.len()].iter()) {
+            parser.feed(&data[start..end], |header| {
+                frames.push((header.kind, header.stream_id, header.len))
+            });
+            start = end;
+        }
+        (frames, parser.complete)
+    }
+
+    fn stream() -> Vec<u8> {
+        let mut data = PREFACE.to_vec();
+        data.extend(frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0, 100]));
+        data.extend(frame(HEADERS, 0x4, 1, &[0x82; 5]));
+        data.extend(frame(DATA, 0x1, 1, &[7; 20]));
+        data.extend(ping());
+        data
+    }
+
+    #[test]
+    fn parser_finds_frames_across_any_split() {
+        let data = stream();
+        let expected = vec![
+            (SETTINGS, 0, 6),
+            (HEADERS, 1, 5),
+            (DATA, 1, 20),
+            (PING, 0, 8),
+        ];
+        assert_eq!(parse_split(&data, &[]), (expected.clone(), 4));
+        for split in 0..=data.len() {
+            assert_eq!(
+                parse_split(&data, &[split]),
+                (expected.clone(), 4),
+                "{split}"
+            );
+        }
+        let every_byte: Vec<usize> = (1..data.len()).collect();
+        assert_eq!(parse_split(&data, &every_byte), (expected, 4));
+    }
+
+    #[test]
+    fn parser_reports_distance_to_boundary() {
+        let mut parser = FrameParser::new(0);
+        assert_eq!(parser.until_boundary(), 0);
+        let data = frame(DATA, 0, 1, &[0; 10]);
+        parser.feed(&data[..4], |_| {});
+        assert_eq!(parser.until_boundary(), 5);
+        parser.feed(&data[4..12], |_| {});
+        assert_eq!(parser.until_boundary(), 7);
+        parser.feed(&data[12..], |_| {});
+        assert_eq!(parser.until_boundary(), 0);
+        assert_eq!(parser.complete, 1);
+    }
+
+    fn frames(policy: PingPolicy) -> ConnFrames {
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let frames = ConnFrames::new(PeerAddr::Tcp(addr), 0, Some(policy));
+        let mut start = PREFACE.to_vec();
+        start.extend(frame(SETTINGS, 0, 0, &[]));
+        start.extend(frame(HEADERS, 0x4, 1, &[0x82]));
+        start.extend(frame(HEADERS, 0x4, 3, &[0x82]));
+        frames.received(&start);
+        frames
+    }
+
+    fn strict() -> PingPolicy {
+        PingPolicy {
+            min_interval: Duration::from_secs(3600),
+            permit_without_streams: true,
+        }
+    }
+
+    fn strikes(frames: &ConnFrames) -> u32 {
+        frames.0.pings.as_ref().unwrap().strikes.get()
+    }
+
+    #[test]
+    fn early_pings_count_strikes_until_goaway() {
+        let frames = frames(strict());
+        assert!(frames.handshake_done());
+        // The first ping sets the baseline; each one after it is too soon.
+        for expected in 0..=MAX_PING_STRIKES {
+            frames.received(&ping());
+            assert_eq!(strikes(&frames), expected);
+            assert!(!frames.goaway_pending());
+        }
+        // A ping ACK is not a ping.
+        frames.received(&frame(PING, FLAG_ACK, 0, &[0; 8]));
+        assert!(!frames.goaway_pending());
+
+        frames.received(&ping());
+        assert!(frames.goaway_pending());
+        assert_eq!(frames.goaway_sent(), Some("too_many_pings"));
+    }
+
+    #[test]
+    fn strikes_reset_when_headers_or_data_are_sent() {
+        let frames = frames(strict());
+        frames.received(&ping());
+        frames.received(&ping());
+        frames.received(&ping());
+        assert_eq!(strikes(&frames), 2);
+
+        frames.sent(&frame(HEADERS, 0x4, 1, &[0x88]));
+        assert_eq!(strikes(&frames), 0);
+        frames.received(&ping());
+        frames.received(&ping());
+        assert_eq!(strikes(&frames), 2);
+
+        // Split across writes: the reset happens once the DATA header is complete.
+        let data = frame(DATA, 0, 1, &[1; 4]);
+        frames.sent(&data[..5]);
+        assert_eq!(strikes(&frames), 2);
+        frames.sent(&data[5..]);
+        assert_eq!(strikes(&frames), 0);
+
+        // Other frames, such as a PING ACK, do not reset.
+        frames.received(&ping());
+        frames.sent(&frame(PING, FLAG_ACK, 0, &[0; 8]));
+        assert_eq!(strikes(&frames), 1);
+        assert!(!frames.goaway_pending());
+    }
+
+    #[test]
+    fn pings_are_allowed_at_the_minimum_interval() {
+        let frames = frames(PingPolicy {
+            min_interval: Duration::ZERO,
+            permit_without_streams: true,
+        });
+        for _ in 0..10 {
+            frames.received(&ping());
+        }
+        assert_eq!(strikes(&frames), 0);
+    }
+
+    #[test]
+    fn idle_connection_pings_are_limited_unless_permitted() {
+        let frames = frames(PingPolicy {
+            min_interval: Duration::ZERO,
+            permit_without_streams: false,
+        });
+        frames.received(&ping());
+        frames.received(&ping());
+        assert_eq!(strikes(&frames), 1);
+
+        let _stream = frames.stream_opened();
+        frames.received(&ping());
+        assert_eq!(strikes(&frames), 1);
+    }
+
+    /// Accepts at most `chunk` bytes per write.
+    struct Sink {
+        written: Vec<u8>,
+        chunk: usize,
+    }
+
+    impl hyper::rt::Write for Sink {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let n = buf.len().min(self.chunk);
+            self.get_mut().written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A client that sends `input` in chunks of at most `chunk` bytes, then stalls.
+    struct Client {
+        input: Vec<u8>,
+        read: usize,
+        chunk: usize,
+        sink: Sink,
+        shut_down: bool,
+    }
+
+    impl Client {
+        fn new(input: Vec<u8>, chunk: usize) -> Self {
+            Client {
+                input,
+                read: 0,
+                chunk,
+                sink: Sink {
+                    written: Vec::new(),
+                    chunk,
+                },
+                shut_down: false,
+            }
+        }
+    }
+
+    impl hyper::rt::Read for Client {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            mut buf: hyper::rt::ReadBufCursor<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            let rest = &this.input[this.read..];
+            if rest.is_empty() {
+                return Poll::Pending;
+            }
+            let n = rest.len().min(this.chunk).min(buf.remaining());
+            buf.put_slice(&rest[..n]);
+            this.read += n;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl hyper::rt::Write for Client {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.get_mut().sink).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().shut_down = true;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn poll_refuse(client: &mut Client, reason: GoAwayReason) -> Poll<io::Result<()>> {
+        let mut refusing = std::pin::pin!(refuse(client, reason));
+        refusing
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    fn write(io: &mut FrameIo<Sink>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        hyper::rt::Write::poll_write(Pin::new(io), &mut cx, buf)
+    }
+
+    #[test]
+    fn goaway_is_injected_at_a_frame_boundary() {
+        for chunk in [1, 3, 7, 64] {
+            let frames = frames(strict());
+            let mut io = FrameIo::new(
+                Sink {
+                    written: Vec::new(),
+                    chunk,
+                },
+                Some(frames.clone()),
+            );
+            let mut out = frame(SETTINGS, FLAG_ACK, 0, &[]);
+            out.extend(frame(DATA, 0, 1, &[9; 30]));
+            out.extend(frame(DATA, 0, 3, &[8; 30]));
+
+            // hyper is part way into the first DATA frame when the GOAWAY is queued.
+            let mut offset = 0;
+            while offset < 15 {
+                let Poll::Ready(Ok(n)) = write(&mut io, &out[offset..15]) else {
+                    panic!("write failed");
+                };
+                offset += n;
+            }
+            for _ in 0..=MAX_PING_STRIKES + 1 {
+                frames.received(&ping());
+            }
+            assert!(frames.goaway_pending());
+
+            let error = loop {
+                match write(&mut io, &out[offset..]) {
+                    Poll::Ready(Ok(n)) => offset += n,
+                    Poll::Ready(Err(e)) => break e,
+                    Poll::Pending => panic!("write pending"),
+                }
+            };
+            assert_eq!(error.kind(), io::ErrorKind::ConnectionAborted);
+
+            // The first DATA frame is finished, the second is never started.
+            let boundary = 9 + 9 + 30;
+            assert_eq!(offset, boundary, "chunk {chunk}");
+            let written = &io.io.written;
+            assert_eq!(&written[..boundary], &out[..boundary]);
+            let mut goaway = Vec::new();
+            goaway_frame(
+                &mut goaway,
+                3,
+                GoAwayReason::EnhanceYourCalm,
+                "too_many_pings",
+            );
+            assert_eq!(&written[boundary..], &goaway[..]);
+            assert_eq!(&goaway[3..5], &[GOAWAY, 0]);
+            assert_eq!(&goaway[9..13], &3u32.to_be_bytes());
+            assert_eq!(&goaway[13..17], &0xbu32.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn refuse_writes_settings_then_goaway() {
+        for (reason, code) in [
+            (GoAwayReason::RefusedStream, 0x7),
+            (GoAwayReason::EnhanceYourCalm, 0xb),
+        ] {
+            for chunk in [1, 5, 512] {
+                let mut input = PREFACE.to_vec();
+                input.extend(frame(SETTINGS, 0, 0, &[0, 0x4, 0, 0, 0xff, 0xff]));
+                let mut client = Client::new(input, chunk);
+                assert!(matches!(
+                    poll_refuse(&mut client, reason),
+                    Poll::Ready(Ok(()))
+                ));
+
+                let mut expected = frame(SETTINGS, 0, 0, &[0, 0x3, 0, 0, 0, 0]);
+                expected.extend(frame(SETTINGS, FLAG_ACK, 0, &[]));
+                let mut goaway = 0u32.to_be_bytes().to_vec();
+                goaway.extend_from_slice(&u32::to_be_bytes(code));
+                goaway.extend_from_slice(b"overloaded");
+                expected.extend(frame(GOAWAY, 0, 0, &goaway));
+                assert_eq!(client.sink.written, expected, "chunk {chunk}");
+                assert!(client.shut_down);
+            }
+        }
+    }
+
+    #[test]
+    fn refuse_waits_for_the_client_settings() {
+        // A client that stalls before its SETTINGS frame gets only the server's
+        // SETTINGS; the caller's deadline (`REFUSE_TIMEOUT` by default) closes it.
+        let mut client = Client::new(PREFACE.to_vec(), 512);
+        assert!(poll_refuse(&mut client, GoAwayReason::RefusedStream).is_pending());
+        assert_eq!(
+            client.sink.written,
+            frame(SETTINGS, 0, 0, &[0, 0x3, 0, 0, 0, 0])
+        );
+        assert!(!client.shut_down);
+    }
+}
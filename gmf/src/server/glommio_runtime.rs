@@ -2,63 +2,87 @@ use std::cell::Cell;
 use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
-use std::rc::Rc;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::pin::Pin;
+use std::time::Duration;
 
 use glommio::net::{TcpListener as GlommioTcpListener, TcpStream as GlommioTcpStream};
 use glommio::{executor, Latency, LocalExecutorBuilder, Placement, Shares};
 
+use crate::server::cpu::CorePlacement;
 use crate::server::error::GmfError;
 use crate::server::hyper_io::HyperIo;
+use crate::server::listener::{PeerCredentials, UnixAddr};
 use crate::server::runtime::{
-    Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeTcpListener, RuntimeTcpStream,
+    CoreJoinHandle, Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeStream, RuntimeTcpListener,
+    RuntimeTimer, RuntimeUnixListener,
 };
+use crate::server::semaphore::{LocalPermit, LocalSemaphore};
+use crate::server::socket::SocketOptions;
+use crate::server::util::LocalSleep;
 
 /// Thread-per-core runtime using glommio (io_uring, Linux only).
 pub struct GlommioRuntime;
 
 impl Runtime for GlommioRuntime {
     type TcpListener = GlommioListener;
+    type UnixListener = GlommioUnixListener;
     type Executor = GlommioExec;
     type Semaphore = GlommioSemaphore;
+    type Timer = GlommioTimer;
 
-    fn run_multi_core<F, Fut>(cores: usize, f: F) -> Result<(), GmfError>
+    // Listeners can only be created by `bind`, so each core binds its own.
+    const SHARED_UNIX_LISTENER: bool = false;
+
+    fn spawn_multi_core<F, Fut>(
+        placement: &CorePlacement,
+        f: F,
+    ) -> Result<Vec<CoreJoinHandle>, GmfError>
     where
         F: Fn(usize) -> Fut + Send + Clone + 'static,
         Fut: Future<Output = Result<(), GmfError>> + 'static,
     {
-        let mut handles = Vec::with_capacity(cores);
+        let mut handles = Vec::with_capacity(placement.cores());
 
-        for cpu in 0..cores {
+        for cpu in 0..placement.cores() {
             let f = f.clone();
-            let handle = LocalExecutorBuilder::new(Placement::Fixed(cpu))
-                .name(&format!("gmf_core_{cpu}"))
-                .spawn(move || async move {
-                    let tq = executor().create_task_queue(
-                        Shares::default(),
-                        Latency::NotImportant,
-                        &format!("gmf_tq_{cpu}"),
-                    );
-
-                    // Store the task queue in thread-local so the executor can use it
-                    TASK_QUEUE.with(|cell| cell.set(Some(tq)));
-
-                    f(cpu).await
+            let placement = placement.clone();
+            // Build the executor inside our own thread (rather than `LocalExecutorBuilder::spawn`)
+            // so every runtime hands back a plain `std::thread::JoinHandle`.
+            let handle = std::thread::Builder::new()
+                .name(format!("gmf_core_{cpu}"))
+                .spawn(move || -> Result<(), GmfError> {
+                    // The thread is pinned here rather than through `Placement::Fixed`
+                    // so that a failure is reported like on the other runtimes.
+                    placement.bind_current_thread(cpu)?;
+                    let local_ex = LocalExecutorBuilder::new(Placement::Unbound)
+                        .name(&format!("gmf_core_{cpu}"))
+                        .make()
+                        .map_err(|e| GmfError::SpawnExecutor {
+                            cpu,
+                            source: io::Error::other(e.to_string()),
+                        })?;
+
+                    local_ex.run(async move {
+                        let tq = executor().create_task_queue(
+                            Shares::default(),
+                            Latency::NotImportant,
+                            &format!("gmf_tq_{cpu}"),
+                        );
+
+                        // Store the task queue in thread-local so the executor can use it
+                        TASK_QUEUE.with(|cell| cell.set(Some(tq)));
+
+                        f(cpu).await
+                    })
                 })
-                .map_err(|e| GmfError::SpawnExecutor {
-                    cpu,
-                    source: io::Error::other(e.to_string()),
-                })?;
+                .map_err(|e| GmfError::SpawnExecutor { cpu, source: e })?;
 
             handles.push(handle);
         }
 
-        for handle in handles {
-            handle
-                .join()
-                .map_err(|e| GmfError::Io(io::Error::other(e.to_string())))?;
-        }
-
-        Ok(())
+        Ok(handles)
     }
 }
 
@@ -75,28 +99,49 @@ fn current_task_queue() -> glommio::TaskQueueHandle {
 
 // -- TCP Listener --
 
-pub struct GlommioListener(GlommioTcpListener);
+/// glommio's listener does not expose its fd, so it is kept alongside.
+pub struct GlommioListener(GlommioTcpListener, RawFd);
+
+impl AsRawFd for GlommioListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.1
+    }
+}
 
 impl RuntimeTcpListener for GlommioListener {
     type Stream = GlommioStream;
 
-    async fn bind(addr: SocketAddr) -> io::Result<Self> {
-        let listener = GlommioTcpListener::bind(addr)?;
-        Ok(GlommioListener(listener))
+    fn from_std(listener: std::net::TcpListener) -> io::Result<Self> {
+        // glommio's own bind leaves the socket blocking; accepts go through io_uring.
+        listener.set_nonblocking(false)?;
+        let fd = listener.into_raw_fd();
+        // SAFETY: the fd is a bound, listening TCP socket whose ownership moves here.
+        let listener = unsafe { GlommioTcpListener::from_raw_fd(fd) };
+        Ok(GlommioListener(listener, fd))
     }
 
     async fn accept(&self) -> io::Result<(Self::Stream, SocketAddr)> {
         let stream = self.0.accept().await?;
-        let addr = stream.local_addr()?;
+        let addr = stream.peer_addr()?;
         Ok((GlommioStream(stream), addr))
     }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.0.local_addr()?)
+    }
 }
 
 // -- TCP Stream --
 
 pub struct GlommioStream(GlommioTcpStream);
 
-impl RuntimeTcpStream for GlommioStream {
+impl AsRawFd for GlommioStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl RuntimeStream for GlommioStream {
     type HyperIo = HyperIo<GlommioTcpStream>;
 
     fn into_hyper_io(self) -> Self::HyperIo {
@@ -104,6 +149,55 @@ impl RuntimeTcpStream for GlommioStream {
     }
 }
 
+// -- Unix Listener --
+
+pub struct GlommioUnixListener(glommio::net::UnixListener);
+
+impl RuntimeUnixListener for GlommioUnixListener {
+    type Stream = GlommioUnixStream;
+
+    /// glommio binds the socket itself with a fixed backlog, so the socket options do
+    /// not apply to Unix listeners on this runtime.
+    fn bind(addr: &UnixAddr, _options: &SocketOptions) -> io::Result<Self> {
+        addr.remove_stale()?;
+        Ok(GlommioUnixListener(glommio::net::UnixListener::bind(
+            addr.to_path(),
+        )?))
+    }
+
+    /// glommio listeners can only be created by `bind`, so a socket cannot be shared
+    /// across cores; use [`UnixStrategy::PerCore`](crate::server::listener::UnixStrategy).
+    fn from_std(_listener: StdUnixListener) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "glommio cannot adopt an existing Unix listener; use UnixStrategy::PerCore",
+        ))
+    }
+
+    /// glommio does not expose the listener's fd, so Unix listeners cannot be handed
+    /// over on this runtime.
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// glommio does not expose the accepted socket's fd, so credentials are unavailable.
+    async fn accept(&self) -> io::Result<(Self::Stream, Option<PeerCredentials>)> {
+        Ok((GlommioUnixStream(self.0.accept().await?), None))
+    }
+}
+
+// -- Unix Stream --
+
+pub struct GlommioUnixStream(glommio::net::UnixStream);
+
+impl RuntimeStream for GlommioUnixStream {
+    type HyperIo = HyperIo<glommio::net::UnixStream>;
+
+    fn into_hyper_io(self) -> Self::HyperIo {
+        HyperIo(self.0)
+    }
+}
+
 // -- Executor --
 
 #[derive(Clone, Default)]
@@ -146,28 +240,51 @@ where
     }
 }
 
+// -- Timer --
+
+#[derive(Clone, Default)]
+pub struct GlommioTimer;
+
+impl RuntimeTimer for GlommioTimer {
+    async fn sleep(&self, duration: Duration) {
+        glommio::timer::sleep(duration).await;
+    }
+
+    fn hyper_sleep(&self, duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        Box::pin(LocalSleep::new(glommio::timer::sleep(duration)))
+    }
+}
+
 // -- Semaphore --
 
-pub struct GlommioSemaphore(Rc<Cell<usize>>);
+#[derive(Clone)]
+pub struct GlommioSemaphore(LocalSemaphore);
 
 impl RuntimeSemaphore for GlommioSemaphore {
+    type Permit = GlommioPermit;
+
     fn new(permits: usize) -> Self {
-        GlommioSemaphore(Rc::new(Cell::new(permits)))
+        GlommioSemaphore(LocalSemaphore::new(permits))
+    }
+
+    fn try_acquire(&self) -> Option<Self::Permit> {
+        self.0
+            .try_acquire()
+            .map(|permit| GlommioPermit { _permit: permit })
     }
 
-    fn try_acquire(&self) -> bool {
-        let current = self.0.get();
-        if current > 0 {
-            self.0.set(current - 1);
-            true
-        } else {
-            false
+    async fn acquire(&self) -> Self::Permit {
+        GlommioPermit {
+            _permit: self.0.acquire().await,
         }
     }
-}
 
-impl GlommioSemaphore {
-    pub fn release(&self) {
-        self.0.set(self.0.get() + 1);
+    fn available(&self) -> usize {
+        self.0.available()
     }
 }
+
+/// A connection permit; returns its slot to the `GlommioSemaphore` on drop.
+pub struct GlommioPermit {
+    _permit: LocalPermit,
+}
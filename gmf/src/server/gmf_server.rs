@@ -1,44 +1,127 @@
+use std::cell::Cell;
 use std::future::Future;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::path::PathBuf;
+use std::pin::{pin, Pin};
+use std::sync::{mpsc, Arc};
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use http_body::Body as HttpBody;
 use hyper::body::Incoming;
 use hyper::rt::bounds::Http2ServerConnExec;
 
-use crate::server::config::ServerConfig;
+use crate::server::access::{AccessPolicy, IpNet, PeerPrefix, PeerSlot, PeerTable};
+use crate::server::budget::{CoreBudget, GlobalBudget};
+use crate::server::cgroup::CGROUP_ROOT;
+use crate::server::config::{
+    ConnectionLifetime, GoAwayReason, Http2Config, ListenFds, ListenerConfig, OverloadPolicy,
+    PingPolicy, ServerConfig,
+};
+use crate::server::connect_info::{GmfConnectInfo, WithExtensions};
+use crate::server::cpu::{CorePlacement, CpuSet, PinPolicy};
 use crate::server::error::GmfError;
+use crate::server::frames::{self, ConnFrames, FrameIo};
+use crate::server::handle::{ServerHandle, Startup};
+use crate::server::io::ConnIo;
+use crate::server::lifetime::Expiry;
+use crate::server::listener::{
+    Acceptor, ListenAddr, PeerAddr, TcpAcceptor, UnixAcceptor, UnixAddr, UnixStrategy,
+};
+use crate::server::metrics::{HandshakeStage, RejectReason, ServerMetrics, WriteTimeoutScope};
+use crate::server::proxy::{self, Rewind};
 use crate::server::runtime::{
-    Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeTcpListener, RuntimeTcpStream,
+    HyperTimer, Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeStream, RuntimeTcpListener,
+    RuntimeTimer, RuntimeUnixListener,
 };
+use crate::server::semaphore::LocalPermit;
+use crate::server::shutdown::{CoreDrain, Signal, StageWait};
+use crate::server::socket::SocketOptions;
+use crate::server::systemd;
+#[cfg(feature = "tls")]
+use crate::server::tls::{self, TlsConfig};
+use crate::server::upgrade::ListenerTable;
+use crate::server::util::{select, try_join_all, until, Either};
+use crate::server::write_timeout::{stall, ConnProgress, ProgressIo, StreamExec, TrackedService};
 
 /// A runtime-agnostic, thread-per-core gRPC server.
 pub struct GmfServer<R: Runtime> {
     config: ServerConfig,
+    /// Decided at build time so that the metrics match the core count; an invalid
+    /// placement fails `start`.
+    placement: Result<CorePlacement, GmfError>,
+    metrics: Arc<ServerMetrics>,
     _runtime: PhantomData<R>,
 }
 
 /// Builder for constructing a `GmfServer`.
 pub struct GmfServerBuilder<R: Runtime> {
-    addr: SocketAddr,
+    addr: ListenAddr,
+    primary: Option<ListenerConfig>,
+    unix_strategy: Option<UnixStrategy>,
     max_connections: usize,
     num_cores: Option<usize>,
+    cpus: Option<CpuSet>,
+    pinning: PinPolicy,
+    numa: bool,
+    cgroup_root: PathBuf,
+    drain_timeout: Duration,
+    stream_write_timeout: Option<Duration>,
+    connection_write_timeout: Option<Duration>,
+    http2: Http2Config,
+    lifetime: ConnectionLifetime,
+    handshake_timeout: Option<Duration>,
+    overload: OverloadPolicy,
+    global_max_connections: Option<usize>,
+    access: AccessPolicy,
+    proxy_protocol: bool,
+    systemd_notify: bool,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    socket_options: SocketOptions,
+    listeners: Vec<ListenerConfig>,
     _runtime: PhantomData<R>,
 }
 
 impl<R: Runtime> GmfServer<R> {
     pub fn builder() -> GmfServerBuilder<R> {
         GmfServerBuilder {
-            addr: ([0, 0, 0, 0], 50051).into(),
+            addr: ListenAddr::Tcp(([0, 0, 0, 0], 50051).into()),
+            primary: None,
+            unix_strategy: None,
             max_connections: 10240,
             num_cores: None,
+            cpus: None,
+            pinning: PinPolicy::default(),
+            numa: false,
+            cgroup_root: PathBuf::from(CGROUP_ROOT),
+            drain_timeout: Duration::from_secs(30),
+            stream_write_timeout: None,
+            connection_write_timeout: None,
+            http2: Http2Config::default(),
+            lifetime: ConnectionLifetime::default(),
+            handshake_timeout: None,
+            overload: OverloadPolicy::default(),
+            global_max_connections: None,
+            access: AccessPolicy::default(),
+            proxy_protocol: false,
+            systemd_notify: false,
+            #[cfg(feature = "tls")]
+            tls: None,
+            socket_options: SocketOptions::default(),
+            listeners: Vec::new(),
             _runtime: PhantomData,
         }
     }
 
+    /// Counters for this server, shared with the [`ServerHandle`] once started.
+    pub fn metrics(&self) -> Arc<ServerMetrics> {
+        self.metrics.clone()
+    }
+
     /// Serve a tower `Service` (e.g. a tonic gRPC service) using the configured runtime.
     ///
     /// Accepts `tower_service::Service` (as produced by tonic) and adapts it to hyper's
@@ -53,33 +136,15 @@ impl<R: Runtime> GmfServer<R> {
         S::Future: 'static,
         RespBd: HttpBody<Data = Bytes> + 'static,
         RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-        R::Executor: Http2ServerConnExec<
-            <TowerToHyperService<S> as hyper::service::Service<hyper::Request<Incoming>>>::Future,
-            RespBd,
-        >,
     {
-        let addr = self.config.addr;
-        let max_conns = self.config.max_connections;
-        let cores = self.config.effective_cores();
-        let shutdown = Arc::new(AtomicBool::new(false));
-
-        tracing::info!(
-            addr = %addr,
-            cores = cores,
-            max_connections = max_conns,
-            "starting gmf server"
-        );
-
-        let hyper_svc = TowerToHyperService(service);
-
-        R::run_multi_core(cores, move |cpu| {
-            let service = hyper_svc.clone();
-            let shutdown = shutdown.clone();
-            async move { accept_loop::<R, _, RespBd>(addr, max_conns, cpu, service, shutdown).await }
-        })
+        first_error(self.start(service)?.join())
     }
 
     /// Serve with a shutdown signal.
+    ///
+    /// When `signal` resolves, every core stops accepting, sends a GOAWAY on each live
+    /// connection and waits up to the configured drain timeout for in-flight RPCs
+    /// before returning.
     pub fn serve_with_shutdown<S, RespBd, Sig>(
         self,
         service: S,
@@ -94,43 +159,149 @@ impl<R: Runtime> GmfServer<R> {
         S::Future: 'static,
         RespBd: HttpBody<Data = Bytes> + 'static,
         RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-        R::Executor: Http2ServerConnExec<
-            <TowerToHyperService<S> as hyper::service::Service<hyper::Request<Incoming>>>::Future,
-            RespBd,
-        >,
         Sig: Future<Output = ()> + Send + 'static,
     {
-        let addr = self.config.addr;
-        let max_conns = self.config.max_connections;
-        let cores = self.config.effective_cores();
-        let shutdown = Arc::new(AtomicBool::new(false));
-        let shutdown_for_signal = shutdown.clone();
+        let handle = self.start(service)?;
+        let shutdown = handle.shutdown_signal();
 
         std::thread::spawn(move || {
             block_on_simple(async move {
                 signal.await;
-                shutdown_for_signal.store(true, Ordering::SeqCst);
                 tracing::info!("shutdown signal received");
+                shutdown.trigger();
             });
         });
 
+        first_error(handle.join())
+    }
+
+    /// Start serving without blocking the calling thread.
+    ///
+    /// Returns once every core has bound its listener. The returned [`ServerHandle`]
+    /// exposes the bound addresses and is used to shut the server down and join it.
+    pub fn start<S, RespBd>(self, service: S) -> Result<ServerHandle, GmfError>
+    where
+        S: tower_service::Service<hyper::Request<Incoming>, Response = hyper::Response<RespBd>>
+            + Clone
+            + Send
+            + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        S::Future: 'static,
+        RespBd: HttpBody<Data = Bytes> + 'static,
+        RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let config = Arc::new(self.config);
+        let placement = self.placement?;
+        let cores = placement.cores();
+
         tracing::info!(
-            addr = %addr,
+            addr = %config.listeners[0].addr,
+            listeners = config.listeners.len(),
             cores = cores,
-            max_connections = max_conns,
+            max_connections = config.max_connections,
+            placement = %placement,
             "starting gmf server"
         );
 
+        for listener in &config.listeners {
+            let shared_unix = matches!(listener.addr, ListenAddr::Unix(_))
+                && listener.unix_strategy == Some(UnixStrategy::Shared)
+                && listener.fds.is_none();
+            if shared_unix && !R::SHARED_UNIX_LISTENER {
+                return Err(GmfError::UnsupportedListener {
+                    addr: listener.addr.clone(),
+                    reason: "this runtime cannot share a Unix listener across cores, \
+                             use UnixStrategy::PerCore",
+                });
+            }
+            if let Some(fds) = listener.fds.as_ref().and_then(ListenFds::len) {
+                if fds != cores {
+                    return Err(GmfError::ListenFdCount {
+                        addr: listener.addr.clone(),
+                        fds,
+                        cores,
+                    });
+                }
+            }
+        }
+
+        // A shared Unix socket is bound once, here, and every core accepts from a
+        // duplicate of it.
+        let shared_unix = config
+            .listeners
+            .iter()
+            .map(|listener| match &listener.addr {
+                ListenAddr::Unix(addr)
+                    if listener.unix_strategy == Some(UnixStrategy::Shared)
+                        && listener.fds.is_none() =>
+                {
+                    let options = listener.socket_options.clone().unwrap_or_default();
+                    let std = addr.bind_std(&options).map_err(|e| GmfError::Bind {
+                        addr: listener.addr.clone(),
+                        source: e,
+                    })?;
+                    Ok(Some(Arc::new(std)))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Arc<[_]>, GmfError>>()?;
+
+        let listeners = config.listeners.len();
+        let shutdown = Signal::new();
+        let (bound_tx, bound_rx) = mpsc::channel();
+        let startup = Startup::new(cores, listeners, bound_tx);
+        let ready = startup.ready_signal();
+        let notify = config.systemd_notify;
         let hyper_svc = TowerToHyperService(service);
+        let core_shutdown = shutdown.clone();
+        let metrics = self.metrics;
+        let core_metrics = metrics.clone();
+        let budget = config
+            .global_max_connections
+            .map(|total| GlobalBudget::new(total, cores));
+        let sockets = ListenerTable::new(listeners, cores);
+        let core_sockets = sockets.clone();
 
-        R::run_multi_core(cores, move |cpu| {
+        let handles = R::spawn_multi_core(&placement, move |cpu| {
+            let config = config.clone();
+            let metrics = core_metrics.clone();
             let service = hyper_svc.clone();
-            let shutdown = shutdown.clone();
-            async move { accept_loop::<R, _, RespBd>(addr, max_conns, cpu, service, shutdown).await }
+            let shutdown = core_shutdown.clone();
+            let startup = startup.clone();
+            let shared_unix = shared_unix.clone();
+            let budget = budget.clone();
+            let sockets = core_sockets.clone();
+            async move {
+                let core = CoreContext {
+                    config,
+                    metrics,
+                    cpu,
+                    cores,
+                    shutdown,
+                    connections: Cell::new(0),
+                    budget: budget.map(|global| CoreBudget::new(global, cpu)),
+                    peers: PeerTable::default(),
+                    sockets,
+                };
+                accept_loop::<R, _, RespBd>(core, service, startup, shared_unix).await
+            }
         })
+        .inspect_err(|_| shutdown.trigger())?;
+
+        if notify {
+            systemd::spawn_notifier(ready.clone(), shutdown.clone());
+        }
+
+        ServerHandle::new(handles, shutdown, ready, metrics, sockets, placement)
+            .wait_bound(listeners, bound_rx)
     }
 }
 
+/// Collapse per-core results into the first error, if any.
+fn first_error(results: Vec<Result<(), GmfError>>) -> Result<(), GmfError> {
+    results.into_iter().collect()
+}
+
 /// Adapter from `tower_service::Service` (takes `&mut self`, has `poll_ready`)
 /// to `hyper::service::Service` (takes `&self`, no `poll_ready`).
 ///
@@ -155,7 +326,7 @@ where
 }
 
 /// Minimal block_on for the shutdown signal thread.
-fn block_on_simple<F: Future>(fut: F) -> F::Output {
+pub(crate) fn block_on_simple<F: Future>(fut: F) -> F::Output {
     use std::pin::pin;
     use std::task::{Context, Poll, Wake, Waker};
 
@@ -179,40 +350,403 @@ fn block_on_simple<F: Future>(fut: F) -> F::Output {
 }
 
 impl<R: Runtime> GmfServerBuilder<R> {
+    /// Address every core listens on (the primary listener).
+    ///
+    /// Port 0 picks a free port once and shares it across all cores; read it back from
+    /// [`ServerHandle::local_addr`].
     pub fn addr(mut self, addr: SocketAddr) -> Self {
-        self.addr = addr;
+        self.addr = ListenAddr::Tcp(addr);
+        self
+    }
+
+    /// Listen on a Unix domain socket (a filesystem path or an abstract name) instead
+    /// of TCP. A stale socket file left behind by a previous process is replaced.
+    pub fn unix(mut self, addr: UnixAddr) -> Self {
+        self.addr = ListenAddr::Unix(addr);
         self
     }
 
+    /// How the cores share a Unix socket listener (by default
+    /// [`UnixStrategy::Shared`], or [`UnixStrategy::PerCore`] on the glommio runtime).
+    pub fn unix_strategy(mut self, strategy: UnixStrategy) -> Self {
+        self.unix_strategy = Some(strategy);
+        self
+    }
+
+    /// Use `listener` as the primary listener instead of one built from
+    /// [`addr`](Self::addr) or [`unix`](Self::unix), e.g. a socket passed in by systemd
+    /// ([`ListenerConfig::from_fd`]).
+    pub fn listener(mut self, listener: impl Into<ListenerConfig>) -> Self {
+        self.primary = Some(listener.into());
+        self
+    }
+
+    /// Listen on another address as well, e.g. `[::]` next to `0.0.0.0` or an internal
+    /// port next to the public one. Every core runs an accept loop per listener and
+    /// feeds all of them into the same service.
+    ///
+    /// Options the [`ListenerConfig`] leaves unset (TLS, connection limit) are taken
+    /// from this builder.
+    pub fn add_listener(mut self, listener: impl Into<ListenerConfig>) -> Self {
+        self.listeners.push(listener.into());
+        self
+    }
+
+    /// Socket options for every listener that does not set its own: backlog,
+    /// `TCP_NODELAY`, keepalive, buffer sizes and so on.
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket_options = options;
+        self
+    }
+
+    /// Per-core connection limit of each listener that does not set its own.
     pub fn max_connections(mut self, n: usize) -> Self {
         self.max_connections = n;
         self
     }
 
+    /// Cap connections across the whole process, on top of each core's per-listener
+    /// `max_connections`.
+    ///
+    /// The budget starts split evenly across the cores. A core that uses up its share
+    /// borrows from cores with headroom: spare permits are handed back to a shared pool
+    /// on a periodic rebalance, so accepting a connection stays core-local and only a
+    /// core that has run out touches shared state.
+    pub fn global_max_connections(mut self, n: usize) -> Self {
+        self.global_max_connections = Some(n);
+        self
+    }
+
+    /// Expect a PROXY protocol header on the connections of the listener at
+    /// [`addr`](Self::addr). See [`ListenerConfig::proxy_protocol`]; other listeners
+    /// opt in individually.
+    pub fn proxy_protocol(mut self) -> Self {
+        self.proxy_protocol = true;
+        self
+    }
+
+    /// Notify systemd (`Type=notify` units) with `READY=1` once every core is accepting
+    /// and `STOPPING=1` when shutdown starts. Does nothing when `NOTIFY_SOCKET` is not
+    /// set.
+    pub fn systemd_notify(mut self) -> Self {
+        self.systemd_notify = true;
+        self
+    }
+
+    /// Accept TCP connections only from peers in `net`. May be called repeatedly to
+    /// allow several networks; with no allowed networks, every peer not denied is.
+    pub fn allow_cidr(mut self, net: IpNet) -> Self {
+        self.access.allow.push(net);
+        self
+    }
+
+    /// Reject TCP connections from peers in `net`, even if they are also allowed.
+    pub fn deny_cidr(mut self, net: IpNet) -> Self {
+        self.access.deny.push(net);
+        self
+    }
+
+    /// Per-core limit on concurrent TCP connections from one peer, across all listeners.
+    /// Connections over it are closed right after accept.
+    pub fn max_connections_per_peer(mut self, n: usize) -> Self {
+        self.access.max_connections_per_peer = Some(n);
+        self
+    }
+
+    /// Group peers by prefix for [`max_connections_per_peer`](Self::max_connections_per_peer),
+    /// e.g. `(24, 64)` to limit whole subnets. Default `(32, 128)`: one address per peer.
+    pub fn peer_prefix(mut self, v4: u8, v6: u8) -> Self {
+        self.access.peer_prefix = PeerPrefix { v4, v6 };
+        self
+    }
+
     pub fn num_cores(mut self, n: usize) -> Self {
         self.num_cores = Some(n);
         self
     }
 
+    /// Run the cores on these CPUs. Each must be in the process's affinity mask
+    /// (`sched_getaffinity`, e.g. as narrowed by `taskset`), which is the default set.
+    pub fn cpus(mut self, cpus: CpuSet) -> Self {
+        self.cpus = Some(cpus);
+        self
+    }
+
+    /// How core threads are pinned to the CPUs (default [`PinPolicy::Pack`]).
+    pub fn pinning(mut self, policy: PinPolicy) -> Self {
+        self.pinning = policy;
+        self
+    }
+
+    /// Place cores by NUMA node, from the node of each CPU in sysfs: `Pack` fills one
+    /// node before the next, `Spread` balances the nodes, and cores on a node get
+    /// consecutive indices. Each core's thread also sets a preferred-node memory policy
+    /// (`set_mempolicy` with `MPOL_PREFERRED`) before its runtime allocates its rings
+    /// and buffers. The node of each core is logged at startup and available from
+    /// [`CorePlacement::node`].
+    pub fn numa_aware(mut self) -> Self {
+        self.numa = true;
+        self
+    }
+
+    /// Read the cgroup CPU quota (`cpu.max`, or `cpu.cfs_quota_us` on cgroup v1) and
+    /// cpuset from the cgroup filesystem at `root` instead of
+    /// [`CGROUP_ROOT`](crate::server::cgroup::CGROUP_ROOT). Unless `num_cores` is set,
+    /// the server runs no more cores than the quota allows, on CPUs of the cpuset.
+    pub fn cgroup_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.cgroup_root = root.into();
+        self
+    }
+
+    /// Maximum time a shutdown waits for in-flight RPCs to finish (default 30s).
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Reset a response stream (`RST_STREAM`) once the client has accepted none of its
+    /// data for `timeout`, e.g. a server-streaming client that stopped reading. Frees the
+    /// handler and its buffered messages. Disabled by default.
+    pub fn stream_write_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_write_timeout = Some(timeout);
+        self
+    }
+
+    /// Send GOAWAY and close a connection once it has had data to send but wrote
+    /// nothing for `timeout`. Disabled by default.
+    pub fn connection_write_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_write_timeout = Some(timeout);
+        self
+    }
+
+    /// What to do with new connections while a listener is at its connection limit
+    /// (default [`OverloadPolicy::Close`]).
+    pub fn overload_policy(mut self, policy: OverloadPolicy) -> Self {
+        self.overload = policy;
+        self
+    }
+
+    /// Close connections that have not finished their handshake `timeout` after being
+    /// accepted: the TLS handshake when TLS is used, then the HTTP/2 preface and the
+    /// client's first `SETTINGS` frame. Guards connection slots against clients that
+    /// connect and then stall. Timeouts are counted in
+    /// [`ServerMetrics::handshake_timeouts`]. Disabled by default.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Send GOAWAY to a connection that has had no open stream for `idle`.
+    pub fn max_connection_idle(mut self, idle: Duration) -> Self {
+        self.lifetime.max_idle = Some(idle);
+        self
+    }
+
+    /// Send GOAWAY to a connection once it is `age` old (±10% jitter), so long-lived
+    /// clients reconnect and the kernel spreads them across the cores again.
+    pub fn max_connection_age(mut self, age: Duration) -> Self {
+        self.lifetime.max_age = Some(age);
+        self
+    }
+
+    /// How long streams may keep running after an idle or age GOAWAY before the
+    /// connection is closed. Unset waits for them to finish.
+    pub fn max_connection_age_grace(mut self, grace: Duration) -> Self {
+        self.lifetime.max_age_grace = Some(grace);
+        self
+    }
+
+    /// Initial HTTP/2 flow-control window of each stream, in bytes. Clamped to
+    /// 2^31 - 1.
+    pub fn http2_initial_stream_window_size(mut self, size: u32) -> Self {
+        self.http2.initial_stream_window_size = Some(size.min(Http2Config::MAX_WINDOW_SIZE));
+        self
+    }
+
+    /// Initial HTTP/2 flow-control window of each connection, in bytes. Clamped to
+    /// 2^31 - 1.
+    pub fn http2_initial_connection_window_size(mut self, size: u32) -> Self {
+        self.http2.initial_connection_window_size = Some(size.min(Http2Config::MAX_WINDOW_SIZE));
+        self
+    }
+
+    /// Size flow-control windows from the measured bandwidth-delay product instead of
+    /// the fixed initial sizes.
+    pub fn http2_adaptive_window(mut self, enabled: bool) -> Self {
+        self.http2.adaptive_window = enabled;
+        self
+    }
+
+    /// `SETTINGS_MAX_CONCURRENT_STREAMS` advertised to clients.
+    pub fn http2_max_concurrent_streams(mut self, max: u32) -> Self {
+        self.http2.max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// `SETTINGS_MAX_FRAME_SIZE`, clamped to the 16 KiB to 16 MiB range HTTP/2 allows.
+    pub fn http2_max_frame_size(mut self, size: u32) -> Self {
+        self.http2.max_frame_size =
+            Some(size.clamp(Http2Config::MIN_FRAME_SIZE, Http2Config::MAX_FRAME_SIZE));
+        self
+    }
+
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`: the largest request header block accepted.
+    pub fn http2_max_header_list_size(mut self, max: u32) -> Self {
+        self.http2.max_header_list_size = Some(max);
+        self
+    }
+
+    /// Maximum bytes buffered per stream before the handler's writes wait.
+    pub fn http2_max_send_buf_size(mut self, max: usize) -> Self {
+        self.http2.max_send_buf_size = Some(max);
+        self
+    }
+
+    /// Maximum streams a client may open and reset before the server has accepted
+    /// them, guarding against rapid-reset floods.
+    pub fn http2_max_pending_accept_reset_streams(mut self, max: usize) -> Self {
+        self.http2.max_pending_accept_reset_streams = Some(max);
+        self
+    }
+
+    /// Send a keepalive ping after `interval` without inbound frames, so connections
+    /// to vanished clients are closed instead of holding a connection slot.
+    pub fn http2_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.http2.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// How long to wait for a keepalive ping to be acknowledged before closing the
+    /// connection (default 20 seconds).
+    pub fn http2_keepalive_timeout(mut self, timeout: Duration) -> Self {
+        self.http2.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Minimum interval allowed between client pings; clients that ping more often are
+    /// sent GOAWAY with `ENHANCE_YOUR_CALM`. Enables [`PingPolicy`] enforcement.
+    pub fn http2_min_client_ping_interval(mut self, interval: Duration) -> Self {
+        self.http2
+            .ping_policy
+            .get_or_insert_with(PingPolicy::default)
+            .min_interval = interval;
+        self
+    }
+
+    /// Whether clients may ping while no stream is open. Enables [`PingPolicy`]
+    /// enforcement.
+    pub fn http2_permit_pings_without_streams(mut self, permit: bool) -> Self {
+        self.http2
+            .ping_policy
+            .get_or_insert_with(PingPolicy::default)
+            .permit_without_streams = permit;
+        self
+    }
+
+    /// Terminate TLS (ALPN `h2`) on every accepted connection instead of serving h2c.
+    /// Listeners can override this with [`ListenerConfig::tls`] or
+    /// [`ListenerConfig::plaintext`].
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
     pub fn build(self) -> GmfServer<R> {
+        let mut primary = self
+            .primary
+            .unwrap_or_else(|| ListenerConfig::new(self.addr));
+        if let Some(strategy) = self.unix_strategy {
+            primary = primary.unix_strategy(strategy);
+        }
+        if self.proxy_protocol {
+            primary = primary.proxy_protocol();
+        }
+        let unix_strategy = match R::SHARED_UNIX_LISTENER {
+            true => UnixStrategy::Shared,
+            false => UnixStrategy::PerCore,
+        };
+        let listeners = std::iter::once(primary)
+            .chain(self.listeners)
+            .map(|listener| {
+                listener.inherit(
+                    unix_strategy,
+                    self.max_connections,
+                    &self.socket_options,
+                    #[cfg(feature = "tls")]
+                    self.tls.as_ref(),
+                )
+            })
+            .collect();
+
+        let config = ServerConfig {
+            listeners,
+            max_connections: self.max_connections,
+            num_cores: self.num_cores,
+            cpus: self.cpus,
+            pinning: self.pinning,
+            numa: self.numa,
+            cgroup_root: self.cgroup_root,
+            drain_timeout: self.drain_timeout,
+            stream_write_timeout: self.stream_write_timeout,
+            connection_write_timeout: self.connection_write_timeout,
+            http2: self.http2,
+            lifetime: self.lifetime,
+            handshake_timeout: self.handshake_timeout,
+            overload: self.overload,
+            global_max_connections: self.global_max_connections,
+            access: self.access,
+            systemd_notify: self.systemd_notify,
+        };
+        let placement = config.placement();
+        let cores = match &placement {
+            Ok(placement) => placement.cores(),
+            Err(_) => config.fallback_cores(),
+        };
+        let metrics = Arc::new(ServerMetrics::new(cores));
+
         GmfServer {
-            config: ServerConfig {
-                addr: self.addr,
-                max_connections: self.max_connections,
-                num_cores: self.num_cores,
-            },
+            config,
+            placement,
+            metrics,
             _runtime: PhantomData,
         }
     }
 }
 
-/// The core accept loop, shared across all runtimes.
-async fn accept_loop<R, S, RespBd>(
-    addr: SocketAddr,
-    max_connections: usize,
+/// Per-core state handed to the accept loop.
+struct CoreContext {
+    config: Arc<ServerConfig>,
+    metrics: Arc<ServerMetrics>,
     cpu: usize,
+    cores: usize,
+    shutdown: Signal,
+    /// Per-core sequence behind connection ids, shared by the core's listeners.
+    connections: Cell<u64>,
+    /// This core's part of the global connection budget, if one is set.
+    budget: Option<CoreBudget>,
+    /// Open connections per peer, for the access policy's per-peer limit.
+    peers: PeerTable,
+    /// Every core's listening sockets, for handing them over in an upgrade.
+    sockets: ListenerTable,
+}
+
+impl CoreContext {
+    /// A server-wide unique connection id without cross-core coordination: core `cpu`
+    /// hands out `cpu`, `cpu + cores`, `cpu + 2 * cores`, ...
+    fn next_connection_id(&self) -> u64 {
+        let seq = self.connections.get();
+        self.connections.set(seq + 1);
+        seq * self.cores as u64 + self.cpu as u64
+    }
+}
+
+/// Run this core's accept loops, one per listener.
+async fn accept_loop<R, S, RespBd>(
+    core: CoreContext,
     service: S,
-    shutdown: Arc<AtomicBool>,
+    startup: Startup,
+    shared_unix: Arc<[Option<Arc<StdUnixListener>>]>,
 ) -> Result<(), GmfError>
 where
     R: Runtime,
@@ -223,24 +757,254 @@ where
     S::Future: 'static,
     RespBd: HttpBody<Data = Bytes> + 'static,
     RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    R::Executor: Http2ServerConnExec<S::Future, RespBd>,
 {
-    let listener = R::TcpListener::bind(addr)
-        .await
-        .map_err(|e| GmfError::Bind { addr, source: e })?;
+    let loops = core
+        .config
+        .listeners
+        .iter()
+        .zip(shared_unix.iter())
+        .enumerate()
+        .map(|(index, (listener, shared))| {
+            run_listener::<R, _, RespBd>(
+                &core,
+                index,
+                listener,
+                service.clone(),
+                &startup,
+                shared.clone(),
+            )
+        });
+    let loops = pin!(try_join_all(loops));
+    match &core.budget {
+        Some(budget) => {
+            let timer = R::Timer::default();
+            let rebalance = pin!(budget.run(&timer));
+            match select(loops, rebalance).await {
+                Either::Left(result) => result?,
+                Either::Right(()) => unreachable!("rebalancing never ends"),
+            }
+        }
+        None => loops.await?,
+    }
 
-    let semaphore = R::Semaphore::new(max_connections);
+    tracing::info!(cpu = core.cpu, "accept loop stopped");
+
+    Ok(())
+}
+
+/// Bind one listener on this core and serve it until shutdown.
+async fn run_listener<R, S, RespBd>(
+    core: &CoreContext,
+    index: usize,
+    listener: &ListenerConfig,
+    service: S,
+    startup: &Startup,
+    shared_unix: Option<Arc<StdUnixListener>>,
+) -> Result<(), GmfError>
+where
+    R: Runtime,
+    S: hyper::service::Service<hyper::Request<Incoming>, Response = hyper::Response<RespBd>>
+        + Clone
+        + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S::Future: 'static,
+    RespBd: HttpBody<Data = Bytes> + 'static,
+    RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let cpu = core.cpu;
+    let options = listener.socket_options.clone().unwrap_or_default();
+
+    if let Some(fds) = &listener.fds {
+        let bind_error = |e| GmfError::Bind {
+            addr: listener.addr.clone(),
+            source: e,
+        };
+        let fd = fds.for_core(cpu).map_err(bind_error)?;
+        match &listener.addr {
+            ListenAddr::Tcp(_) => {
+                let acceptor = R::TcpListener::from_std(std::net::TcpListener::from(fd))
+                    .map_err(bind_error)?;
+                startup.bound(cpu, index, listener.addr.clone());
+                serve_listener::<R, _, _, RespBd>(
+                    core,
+                    index,
+                    listener,
+                    startup,
+                    TcpAcceptor {
+                        listener: acceptor,
+                        options,
+                    },
+                    listener.addr.clone(),
+                    service,
+                )
+                .await;
+            }
+            ListenAddr::Unix(_) => {
+                let std = StdUnixListener::from(fd);
+                let acceptor = std
+                    .set_nonblocking(true)
+                    .and_then(|()| R::UnixListener::from_std(std))
+                    .map_err(bind_error)?;
+                startup.bound(cpu, index, listener.addr.clone());
+                serve_listener::<R, _, _, RespBd>(
+                    core,
+                    index,
+                    listener,
+                    startup,
+                    UnixAcceptor(acceptor),
+                    listener.addr.clone(),
+                    service,
+                )
+                .await;
+            }
+        }
+        return Ok(());
+    }
+
+    match &listener.addr {
+        ListenAddr::Tcp(requested) => {
+            let Some(addr) = startup
+                .listen_addr(cpu, index, *requested, &core.shutdown)
+                .await
+            else {
+                return Ok(());
+            };
+
+            let bound = match R::TcpListener::bind(addr, &options) {
+                Ok(listener) => listener.local_addr().map(|local| (listener, local)),
+                Err(e) => Err(e),
+            };
+            if cpu == 0 {
+                startup.publish_addr(index, bound.as_ref().ok().map(|(_, local)| *local));
+            }
+            let (acceptor, local_addr) = bound.map_err(|e| GmfError::Bind {
+                addr: ListenAddr::Tcp(addr),
+                source: e,
+            })?;
+            let local_addr = ListenAddr::Tcp(local_addr);
+            startup.bound(cpu, index, local_addr.clone());
+
+            serve_listener::<R, _, _, RespBd>(
+                core,
+                index,
+                listener,
+                startup,
+                TcpAcceptor {
+                    listener: acceptor,
+                    options,
+                },
+                local_addr,
+                service,
+            )
+            .await;
+        }
+        ListenAddr::Unix(name) => {
+            let addr = match listener.unix_strategy {
+                Some(UnixStrategy::PerCore) => name.for_core(cpu),
+                _ => name.clone(),
+            };
+            let acceptor = match shared_unix {
+                Some(shared) => shared.try_clone().and_then(R::UnixListener::from_std),
+                None => R::UnixListener::bind(&addr, &options),
+            }
+            .map_err(|e| GmfError::Bind {
+                addr: ListenAddr::Unix(addr.clone()),
+                source: e,
+            })?;
+            let local_addr = ListenAddr::Unix(addr.clone());
+            startup.bound(cpu, index, local_addr.clone());
+
+            serve_listener::<R, _, _, RespBd>(
+                core,
+                index,
+                listener,
+                startup,
+                UnixAcceptor(acceptor),
+                local_addr,
+                service,
+            )
+            .await;
+
+            // Core 0 owns the shared socket file, unless a new process took it over.
+            let owned = listener.unix_strategy == Some(UnixStrategy::PerCore) || cpu == 0;
+            if owned && !core.sockets.is_handed_over() {
+                addr.cleanup();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The core accept loop, shared across all runtimes and listener kinds.
+///
+/// Returns once shutdown was requested and the core's connections have drained (or
+/// the drain timeout elapsed).
+async fn serve_listener<R, A, S, RespBd>(
+    core: &CoreContext,
+    index: usize,
+    options: &ListenerConfig,
+    startup: &Startup,
+    listener: A,
+    local_addr: ListenAddr,
+    service: S,
+) where
+    R: Runtime,
+    A: Acceptor,
+    S: hyper::service::Service<hyper::Request<Incoming>, Response = hyper::Response<RespBd>>
+        + Clone
+        + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S::Future: 'static,
+    RespBd: HttpBody<Data = Bytes> + 'static,
+    RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let CoreContext {
+        config,
+        metrics,
+        cpu,
+        shutdown,
+        ..
+    } = core;
+    let cpu = *cpu;
+
+    let semaphore = R::Semaphore::new(options.max_connections.unwrap_or(config.max_connections));
+    let write_timeouts = config.write_timeouts();
     let executor = R::Executor::default();
+    let drain = CoreDrain::new();
+
+    let registered = core.sockets.register(index, cpu, listener.raw_fd());
+    startup.accepting();
+    tracing::info!(cpu = cpu, addr = %local_addr, "accepting connections");
 
-    tracing::info!(cpu = cpu, addr = %addr, "accepting connections");
+    let mut stop = pin!(shutdown.wait());
 
     loop {
-        if shutdown.load(Ordering::Relaxed) {
-            tracing::info!(cpu = cpu, "shutting down accept loop");
-            break;
-        }
+        // Under `Pause`, hold a slot before accepting so that, at the limit, new
+        // connections wait in the kernel's accept queue rather than in this process.
+        let reserved = match config.overload {
+            OverloadPolicy::Pause => {
+                if semaphore.available() == 0 {
+                    tracing::debug!(cpu = cpu, addr = %local_addr, "connection limit reached, pausing accept");
+                }
+                let mut acquire = pin!(acquire_slot(&semaphore, core.budget.as_ref()));
+                match select(acquire.as_mut(), stop.as_mut()).await {
+                    Either::Left(permit) => Some(permit),
+                    Either::Right(()) => break,
+                }
+            }
+            _ => None,
+        };
 
-        let (stream, peer_addr) = match listener.accept().await {
+        let accepted = {
+            let mut accept = pin!(listener.accept());
+            match select(accept.as_mut(), stop.as_mut()).await {
+                Either::Left(accepted) => accepted,
+                Either::Right(()) => break,
+            }
+        };
+
+        let (stream, peer_addr) = match accepted {
             Ok(pair) => pair,
             Err(e) => {
                 tracing::warn!(cpu = cpu, error = %e, "accept error, continuing");
@@ -248,26 +1012,491 @@ where
             }
         };
 
-        if !semaphore.try_acquire() {
-            tracing::warn!(cpu = cpu, peer = %peer_addr, "max connections reached, dropping");
-            drop(stream);
-            continue;
-        }
+        // Behind a PROXY protocol balancer the access policy applies to the client
+        // address from the header, once it has been read.
+        let proxied = options.proxy_protocol;
+        let peer_slot = match proxied {
+            true => None,
+            false => match admit_peer(&config.access, &core.peers, peer_addr) {
+                Ok(slot) => slot,
+                Err(reason) => {
+                    rejected(metrics, cpu, peer_addr, reason);
+                    drop(stream);
+                    continue;
+                }
+            },
+        };
+
+        let admission = match reserved.or_else(|| try_slot(&semaphore, core.budget.as_ref())) {
+            Some(slot) => Admission::Slot(slot),
+            None => match config.overload {
+                OverloadPolicy::Wait(timeout) => Admission::Wait(
+                    semaphore.clone(),
+                    core.budget.clone(),
+                    Instant::now() + timeout,
+                ),
+                OverloadPolicy::GoAway(reason) => {
+                    tracing::warn!(cpu = cpu, peer = %peer_addr, "connection limit reached, refusing");
+                    Admission::Refuse(reason)
+                }
+                OverloadPolicy::Close | OverloadPolicy::Pause => {
+                    tracing::warn!(cpu = cpu, peer = %peer_addr, "connection limit reached, dropping");
+                    drop(stream);
+                    continue;
+                }
+            },
+        };
 
-        tracing::debug!(cpu = cpu, peer = %peer_addr, "accepted connection");
+        let mut connect_info = GmfConnectInfo {
+            peer_addr,
+            local_addr: local_addr.clone(),
+            cpu,
+            connection_id: core.next_connection_id(),
+            proxy_header: None,
+        };
+        tracing::debug!(
+            cpu = cpu,
+            peer = %peer_addr,
+            connection_id = connect_info.connection_id,
+            "accepted connection"
+        );
 
-        let io = stream.into_hyper_io();
+        let io = Rewind::new(stream.into_hyper_io());
         let svc = service.clone();
-        let exec = executor.clone();
+        let config = config.clone();
+        let conn_executor = executor.clone();
+        let peers = core.peers.clone();
+        let guard = drain.track();
+        let draining = drain.draining();
+        let closing = drain.closing();
+        let metrics = metrics.clone();
+        #[cfg(feature = "tls")]
+        let tls = options.tls.clone();
 
         executor.spawn(async move {
-            let conn = hyper::server::conn::http2::Builder::new(exec).serve_connection(io, svc);
+            let timer = R::Timer::default();
+            let (slot, refuse) = match admission {
+                Admission::Slot(slot) => (Some(slot), None),
+                Admission::Wait(semaphore, budget, deadline) => {
+                    let slot = acquire_slot(&semaphore, budget.as_ref());
+                    match until(&timer, Some(deadline), slot).await {
+                        Some(slot) => (Some(slot), None),
+                        None => {
+                            tracing::warn!(
+                                cpu = cpu,
+                                peer = %peer_addr,
+                                "no connection slot freed up in time, closing"
+                            );
+                            return;
+                        }
+                    }
+                }
+                Admission::Refuse(reason) => (None, Some(reason)),
+            };
+            let handshake_deadline = config
+                .handshake_timeout
+                .map(|timeout| Instant::now() + timeout);
+
+            let mut io = io;
+            let (peer_addr, peer_slot) = if proxied {
+                let deadline =
+                    handshake_deadline.unwrap_or_else(|| Instant::now() + PROXY_HEADER_TIMEOUT);
+                let header = match until(&timer, Some(deadline), proxy::read_header(&mut io)).await
+                {
+                    Some(Ok((header, rest))) => {
+                        io.rewind(rest);
+                        header
+                    }
+                    Some(Err(e)) => {
+                        tracing::debug!(
+                            cpu = cpu,
+                            peer = %peer_addr,
+                            error = %e,
+                            "invalid PROXY protocol header"
+                        );
+                        return;
+                    }
+                    None => {
+                        handshake_timed_out(&metrics, cpu, peer_addr, HandshakeStage::Proxy);
+                        return;
+                    }
+                };
+                let client = header.source().map_or(peer_addr, PeerAddr::Tcp);
+                let peer_slot = match admit_peer(&config.access, &peers, client) {
+                    Ok(slot) => slot,
+                    Err(reason) => {
+                        rejected(&metrics, cpu, client, reason);
+                        return;
+                    }
+                };
+                tracing::debug!(
+                    cpu = cpu,
+                    peer = %client,
+                    proxy = %peer_addr,
+                    connection_id = connect_info.connection_id,
+                    "PROXY protocol header received"
+                );
+                connect_info.peer_addr = client;
+                connect_info.proxy_header = Some(Arc::new(header));
+                (client, peer_slot)
+            } else {
+                (peer_addr, peer_slot)
+            };
+
+            let progress = write_timeouts
+                .is_enabled()
+                .then(|| ConnProgress::new(write_timeouts, peer_addr, cpu, metrics.clone()));
+            let frames = config
+                .tracks_frames()
+                .then(|| ConnFrames::new(peer_addr, cpu, config.http2.ping_policy));
+            let exec = StreamExec::new(
+                conn_executor,
+                timer.clone(),
+                progress.clone(),
+                frames.clone(),
+            );
+            let mut http2 = hyper::server::conn::http2::Builder::new(exec);
+            http2.timer(HyperTimer::<R::Timer>::new());
+            config.http2.apply(&mut http2);
+
+            #[cfg(feature = "tls")]
+            let (io, mut extensions) = match tls {
+                Some(tls) => match until(
+                    &timer,
+                    handshake_deadline,
+                    tls.accept(io, connect_info.tcp_connect_info()),
+                )
+                .await
+                {
+                    Some(Ok(io)) => {
+                        let extensions = tls::session_extensions(&io);
+                        (ConnIo::Tls(Box::new(io)), extensions)
+                    }
+                    None => {
+                        handshake_timed_out(&metrics, cpu, peer_addr, HandshakeStage::Tls);
+                        return;
+                    }
+                    Some(Err(e)) => {
+                        let reason = tls::failure_reason(&e);
+                        metrics.tls().record_handshake_failure(reason);
+                        tracing::debug!(
+                            peer = %peer_addr,
+                            reason = %reason,
+                            error = %e,
+                            "TLS handshake failed"
+                        );
+                        return;
+                    }
+                },
+                None => (ConnIo::Plain(io), plain_extensions(&connect_info)),
+            };
+            #[cfg(not(feature = "tls"))]
+            let (io, mut extensions) = (ConnIo::Plain(io), plain_extensions(&connect_info));
 
-            if let Err(e) = conn.await {
-                tracing::debug!(peer = %peer_addr, error = %e, "connection closed");
+            if let Some(reason) = refuse {
+                let deadline =
+                    handshake_deadline.unwrap_or_else(|| Instant::now() + REFUSE_TIMEOUT);
+                match until(&timer, Some(deadline), frames::refuse(io, reason)).await {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) => {
+                        tracing::debug!(peer = %peer_addr, error = %e, "refused connection closed")
+                    }
+                    None => handshake_timed_out(&metrics, cpu, peer_addr, HandshakeStage::Http2),
+                }
+                return;
             }
+
+            if let PeerAddr::Unix(Some(cred)) = peer_addr {
+                extensions.insert(cred);
+            }
+            extensions.insert(connect_info);
+
+            let io = ProgressIo::new(FrameIo::new(io, frames.clone()), progress.clone());
+            let svc = TrackedService::new(WithExtensions::new(svc, extensions), progress.clone());
+            let watch = ConnWatch {
+                progress,
+                frames,
+                lifetime: config.lifetime,
+                handshake_deadline,
+                metrics,
+                cpu,
+            };
+            serve_connection::<_, _, _, R::Timer, _>(
+                io, svc, http2, draining, closing, peer_addr, watch,
+            )
+            .await;
+
+            // Hand the slot back once hyper is done with the connection.
+            drop(slot);
+            drop(peer_slot);
+            drop(guard);
         });
     }
 
-    Ok(())
+    // Stop accepting before draining so new clients go to the kernel's other listeners
+    // (or get refused) instead of queueing on a core that is about to exit.
+    drop(registered);
+    drop(listener);
+    drain.start();
+
+    let active = drain.active();
+    if active > 0 {
+        tracing::info!(cpu = cpu, connections = active, "draining connections");
+
+        let timer = R::Timer::default();
+        let mut idle = pin!(drain.idle());
+        let mut deadline = pin!(timer.sleep(config.drain_timeout));
+        if let Either::Right(()) = select(idle.as_mut(), deadline.as_mut()).await {
+            tracing::warn!(
+                cpu = cpu,
+                connections = drain.active(),
+                "drain timeout elapsed, closing remaining connections"
+            );
+            // Not every runtime drops its tasks on exit, so close the sockets here.
+            drain.close();
+            idle.await;
+        }
+    }
+}
+
+/// Connection-level request extensions for a plaintext connection: tonic's
+/// `TcpConnectInfo` for TCP peers, so `Request::remote_addr()` works as it does under
+/// tonic's own transport.
+fn plain_extensions(connect_info: &GmfConnectInfo) -> http::Extensions {
+    let mut extensions = http::Extensions::new();
+    if let ListenAddr::Tcp(_) = connect_info.local_addr {
+        extensions.insert(connect_info.tcp_connect_info());
+    }
+    extensions
+}
+
+/// Per-connection policies `serve_connection` enforces on top of hyper.
+struct ConnWatch {
+    progress: Option<ConnProgress>,
+    frames: Option<ConnFrames>,
+    lifetime: ConnectionLifetime,
+    /// When the client must have sent the preface and its first `SETTINGS` frame.
+    handshake_deadline: Option<Instant>,
+    metrics: Arc<ServerMetrics>,
+    cpu: usize,
+}
+
+/// How `serve_connection` ended.
+enum Closed {
+    Conn(Result<(), hyper::Error>),
+    HandshakeTimeout,
+    WriteStalled,
+    GraceElapsed(Expiry),
+    DrainTimeout,
+}
+
+/// Apply the access policy to a new connection. Unix socket peers are not filtered.
+fn admit_peer(
+    policy: &AccessPolicy,
+    peers: &PeerTable,
+    peer_addr: PeerAddr,
+) -> Result<Option<PeerSlot>, RejectReason> {
+    match peer_addr {
+        PeerAddr::Tcp(addr) if policy.is_enabled() => peers.admit(policy, addr.ip()),
+        _ => Ok(None),
+    }
+}
+
+/// A connection's place under its listener's limit and, if configured, the global
+/// connection budget. Both are released on drop.
+struct Slot<P> {
+    _listener: P,
+    _budget: Option<LocalPermit>,
+}
+
+fn try_slot<S: RuntimeSemaphore>(
+    semaphore: &S,
+    budget: Option<&CoreBudget>,
+) -> Option<Slot<S::Permit>> {
+    let listener = semaphore.try_acquire()?;
+    let budget = match budget {
+        Some(budget) => Some(budget.try_acquire()?),
+        None => None,
+    };
+    Some(Slot {
+        _listener: listener,
+        _budget: budget,
+    })
+}
+
+async fn acquire_slot<S: RuntimeSemaphore>(
+    semaphore: &S,
+    budget: Option<&CoreBudget>,
+) -> Slot<S::Permit> {
+    let listener = semaphore.acquire().await;
+    let budget = match budget {
+        Some(budget) => Some(budget.acquire().await),
+        None => None,
+    };
+    Slot {
+        _listener: listener,
+        _budget: budget,
+    }
+}
+
+/// How an accepted connection gets its slot.
+enum Admission<S: RuntimeSemaphore> {
+    Slot(Slot<S::Permit>),
+    /// Wait for a slot until the deadline ([`OverloadPolicy::Wait`]).
+    Wait(S, Option<CoreBudget>, Instant),
+    /// Complete the handshakes and send GOAWAY ([`OverloadPolicy::GoAway`]).
+    Refuse(GoAwayReason),
+}
+
+/// How long a refused connection may take to complete its handshakes when no
+/// handshake timeout is configured.
+const REFUSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a balancer may take to send the PROXY protocol header when no handshake
+/// timeout is configured.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Count and log a connection rejected by the access policy.
+fn rejected(metrics: &ServerMetrics, cpu: usize, peer_addr: PeerAddr, reason: RejectReason) {
+    metrics.rejections().record(cpu, reason);
+    tracing::warn!(cpu = cpu, peer = %peer_addr, reason = %reason, "connection rejected");
+}
+
+/// Count and log a connection closed for not finishing its handshake in time.
+fn handshake_timed_out(
+    metrics: &ServerMetrics,
+    cpu: usize,
+    peer_addr: PeerAddr,
+    stage: HandshakeStage,
+) {
+    metrics.handshake_timeouts().record(cpu, stage);
+    tracing::debug!(cpu = cpu, peer = %peer_addr, stage = %stage, "handshake timed out");
+}
+
+/// Serve HTTP/2 on one accepted connection until it closes.
+///
+/// When the core starts draining, or the connection reaches its maximum idle time or
+/// age, hyper sends GOAWAY and lets in-flight streams finish. A connection that exceeds
+/// its write timeout is sent GOAWAY and closed right away, as is every connection still
+/// open when the core's drain timeout elapses.
+async fn serve_connection<I, S, E, T, RespBd>(
+    io: I,
+    service: S,
+    http2: hyper::server::conn::http2::Builder<E>,
+    mut draining: StageWait,
+    mut closing: StageWait,
+    peer_addr: PeerAddr,
+    watch: ConnWatch,
+) where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + 'static,
+    S: hyper::service::Service<hyper::Request<Incoming>, Response = hyper::Response<RespBd>>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    RespBd: HttpBody<Data = Bytes> + 'static,
+    RespBd::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    E: Http2ServerConnExec<S::Future, RespBd> + Clone,
+    T: RuntimeTimer,
+{
+    let ConnWatch {
+        progress,
+        frames,
+        lifetime,
+        handshake_deadline,
+        metrics,
+        cpu,
+    } = watch;
+    let timer = T::default();
+    let conn = http2.serve_connection(io, service);
+    let mut conn = pin!(conn);
+    let mut goaway_sent = false;
+
+    let mut handshake = pin!(async {
+        if let (Some(deadline), Some(frames)) = (handshake_deadline, &frames) {
+            timer
+                .sleep(deadline.saturating_duration_since(Instant::now()))
+                .await;
+            if !frames.handshake_done() {
+                return;
+            }
+        }
+        std::future::pending().await
+    });
+
+    let expired = Cell::new(None);
+    let mut retire = pin!(async {
+        let expiry = lifetime.expired(&timer, frames.as_ref()).await;
+        expired.set(Some(expiry));
+        match lifetime.max_age_grace {
+            Some(grace) => timer.sleep(grace).await,
+            None => std::future::pending().await,
+        }
+        expiry
+    });
+
+    let watched = progress
+        .as_ref()
+        .and_then(|p| Some((p, p.connection_timeout()?)));
+    let mut stalled = pin!(async {
+        match watched {
+            Some((progress, timeout)) => {
+                stall(&timer, timeout, |now| progress.stalled_for(now)).await
+            }
+            None => std::future::pending().await,
+        }
+    });
+
+    let result = std::future::poll_fn(|cx| {
+        if !goaway_sent && Pin::new(&mut draining).poll(cx).is_ready() {
+            // Sends GOAWAY and lets in-flight streams run to completion.
+            conn.as_mut().graceful_shutdown();
+            goaway_sent = true;
+        }
+        if Pin::new(&mut closing).poll(cx).is_ready() {
+            return Poll::Ready(Closed::DrainTimeout);
+        }
+        if handshake.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Closed::HandshakeTimeout);
+        }
+        let grace_elapsed = retire.as_mut().poll(cx);
+        if let Some(expiry) = expired.take() {
+            tracing::debug!(peer = %peer_addr, reason = %expiry, "retiring connection");
+            if !goaway_sent {
+                conn.as_mut().graceful_shutdown();
+                goaway_sent = true;
+            }
+        }
+        if let Poll::Ready(expiry) = grace_elapsed {
+            let _ = conn.as_mut().poll(cx);
+            return Poll::Ready(Closed::GraceElapsed(expiry));
+        }
+        if stalled.as_mut().poll(cx).is_ready() {
+            // Queue a GOAWAY and give it one chance to reach the socket, then close.
+            conn.as_mut().graceful_shutdown();
+            let _ = conn.as_mut().poll(cx);
+            return Poll::Ready(Closed::WriteStalled);
+        }
+        conn.as_mut().poll(cx).map(Closed::Conn)
+    })
+    .await;
+
+    match result {
+        Closed::Conn(Err(_)) if frames.as_ref().and_then(ConnFrames::goaway_sent).is_some() => {}
+        Closed::Conn(Err(e)) => {
+            tracing::debug!(peer = %peer_addr, error = %e, "connection closed")
+        }
+        Closed::Conn(Ok(())) => {}
+        Closed::HandshakeTimeout => {
+            handshake_timed_out(&metrics, cpu, peer_addr, HandshakeStage::Http2)
+        }
+        Closed::WriteStalled => {
+            if let Some(progress) = &progress {
+                progress.evicted(WriteTimeoutScope::Connection);
+            }
+        }
+        Closed::GraceElapsed(expiry) => tracing::debug!(
+            peer = %peer_addr,
+            reason = %expiry,
+            "grace period elapsed, closing connection"
+        ),
+        Closed::DrainTimeout => {}
+    }
 }
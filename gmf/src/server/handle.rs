@@ -0,0 +1,348 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::pin::pin;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::server::cpu::CorePlacement;
+use crate::server::error::GmfError;
+use crate::server::listener::ListenAddr;
+use crate::server::metrics::ServerMetrics;
+use crate::server::runtime::{join_core, CoreJoinHandle};
+use crate::server::shutdown::Signal;
+use crate::server::upgrade::{ListenerTable, UpgradeChannel, UPGRADE_FD_VAR};
+use crate::server::util::{select, Either};
+
+/// Handle to a running server, returned by [`GmfServer::start`].
+///
+/// Dropping the handle does not stop the server; the core threads keep running
+/// detached. Call [`shutdown`](Self::shutdown) and then [`join`](Self::join) to stop it.
+///
+/// [`GmfServer::start`]: crate::server::gmf_server::GmfServer::start
+pub struct ServerHandle {
+    listener_addrs: Vec<Vec<ListenAddr>>,
+    local_addrs: Vec<SocketAddr>,
+    shutdown: Signal,
+    ready: Signal,
+    cores: Vec<CoreJoinHandle>,
+    metrics: Arc<ServerMetrics>,
+    sockets: ListenerTable,
+    placement: CorePlacement,
+}
+
+impl ServerHandle {
+    /// The address each core's primary listener is bound to, indexed by core.
+    pub fn listen_addrs(&self) -> &[ListenAddr] {
+        &self.listener_addrs[0]
+    }
+
+    /// The bound addresses of every listener, indexed by listener (the primary one
+    /// first, then in [`add_listener`] order) and then by core.
+    ///
+    /// [`add_listener`]: crate::server::gmf_server::GmfServerBuilder::add_listener
+    pub fn listener_addrs(&self) -> &[Vec<ListenAddr>] {
+        &self.listener_addrs
+    }
+
+    /// The TCP address each core's primary listener is bound to, indexed by core.
+    /// Empty when the primary listener is a Unix socket.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// The TCP address of core 0's primary listener. With `SO_REUSEPORT` every core
+    /// shares it.
+    ///
+    /// # Panics
+    ///
+    /// If the primary listener is a Unix socket; use [`listen_addrs`](Self::listen_addrs).
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    /// The CPU each core runs on, indexed by core.
+    pub fn placement(&self) -> &CorePlacement {
+        &self.placement
+    }
+
+    /// Counters shared by every core.
+    pub fn metrics(&self) -> &Arc<ServerMetrics> {
+        &self.metrics
+    }
+
+    /// Start a graceful shutdown. Returns immediately; use [`join`](Self::join) to wait
+    /// for the cores to drain and exit.
+    pub fn shutdown(&self) {
+        self.shutdown.trigger();
+    }
+
+    /// Resolves once every core has entered its accept loop.
+    pub fn ready(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.ready.wait()
+    }
+
+    /// `true` once every core thread has exited.
+    pub fn is_finished(&self) -> bool {
+        self.cores.iter().all(|core| core.is_finished())
+    }
+
+    /// Block until every core exits and return each core's result, indexed by core.
+    pub fn join(self) -> Vec<Result<(), GmfError>> {
+        self.cores.into_iter().map(join_core).collect()
+    }
+
+    /// Start `command` as the new process of a zero-downtime upgrade and hand it this
+    /// server's listening sockets (see [`hand_over`](Self::hand_over)).
+    ///
+    /// The channel's fd is passed in [`UPGRADE_FD_VAR`]; the new process picks it up
+    /// with [`UpgradeChannel::from_env`]:
+    ///
+    /// ```ignore
+    /// let upgrade = UpgradeChannel::from_env()?;
+    /// let builder = match &upgrade {
+    ///     Some(channel) => {
+    ///         let mut listeners = channel.receive(Duration::from_secs(5))?;
+    ///         GmfServer::builder().listener(listeners.remove(0))
+    ///     }
+    ///     None => GmfServer::builder().addr(addr),
+    /// };
+    /// let handle = builder.build().start(service)?;
+    /// if let Some(channel) = upgrade {
+    ///     channel.ready(&handle)?;
+    /// }
+    /// ```
+    ///
+    /// If the upgrade fails the child is killed and this server keeps running.
+    pub fn upgrade(&self, command: &mut Command, timeout: Duration) -> Result<Child, GmfError> {
+        let (channel, theirs) = UpgradeChannel::pair().map_err(GmfError::Upgrade)?;
+        let inherited = theirs.inheritable().map_err(GmfError::Upgrade)?;
+        let mut child = command
+            .env(UPGRADE_FD_VAR, inherited.as_raw_fd().to_string())
+            .spawn()
+            .map_err(GmfError::Upgrade)?;
+        drop(inherited);
+        drop(theirs);
+
+        match self.hand_over(&channel, timeout) {
+            Ok(()) => Ok(child),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+        }
+    }
+
+    /// Send every core's listening sockets over `channel` and, once the process on the
+    /// other end reports that it is serving on them, [`shutdown`](Self::shutdown) this
+    /// server: it stops accepting and drains its connections.
+    ///
+    /// Both servers accept from the same sockets in between, so no connection is
+    /// refused, and Unix socket files are left in place for the new process.
+    ///
+    /// Fails, leaving this server running, if a listener cannot be handed over (Unix
+    /// listeners on glommio) or the other process does not report ready within
+    /// `timeout`.
+    pub fn hand_over(&self, channel: &UpgradeChannel, timeout: Duration) -> Result<(), GmfError> {
+        let deadline = Instant::now() + timeout;
+        while !self.ready.is_triggered() {
+            if self.shutdown.is_triggered() || Instant::now() >= deadline {
+                return Err(GmfError::Upgrade(io::Error::other(
+                    "the server is not accepting on every core",
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let sockets = self.sockets.duplicate().map_err(GmfError::Upgrade)?;
+        channel
+            .send(&sockets, deadline)
+            .and_then(|()| channel.wait_ready(deadline))
+            .map_err(GmfError::Upgrade)?;
+
+        tracing::info!("listeners handed over, shutting down");
+        self.sockets.set_handed_over();
+        self.shutdown();
+        Ok(())
+    }
+
+    pub(crate) fn shutdown_signal(&self) -> Signal {
+        self.shutdown.clone()
+    }
+
+    /// A handle for cores that are still binding; see [`wait_bound`](Self::wait_bound).
+    pub(crate) fn new(
+        cores: Vec<CoreJoinHandle>,
+        shutdown: Signal,
+        ready: Signal,
+        metrics: Arc<ServerMetrics>,
+        sockets: ListenerTable,
+        placement: CorePlacement,
+    ) -> Self {
+        ServerHandle {
+            listener_addrs: Vec::new(),
+            local_addrs: Vec::new(),
+            shutdown,
+            ready,
+            cores,
+            metrics,
+            sockets,
+            placement,
+        }
+    }
+
+    /// Wait for every core to report the bound address of each listener.
+    ///
+    /// If a core exits before binding all of them, the remaining cores are shut down and
+    /// the failing core's error is returned.
+    pub(crate) fn wait_bound(
+        mut self,
+        listeners: usize,
+        bound: mpsc::Receiver<Bound>,
+    ) -> Result<Self, GmfError> {
+        let cores = self.cores.len();
+        let mut listener_addrs = vec![vec![None; cores]; listeners];
+        let mut unbound = vec![listeners; cores];
+        let mut pending = cores * listeners;
+
+        while pending > 0 {
+            match bound.recv_timeout(Duration::from_millis(10)) {
+                Ok(Bound {
+                    cpu,
+                    listener,
+                    addr,
+                }) => {
+                    listener_addrs[listener][cpu] = Some(addr);
+                    unbound[cpu] -= 1;
+                    pending -= 1;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let failed = self
+                        .cores
+                        .iter()
+                        .zip(&unbound)
+                        .any(|(core, unbound)| *unbound > 0 && core.is_finished());
+                    if failed {
+                        return Err(Self::abort(self.cores, &self.shutdown));
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Self::abort(self.cores, &self.shutdown));
+                }
+            }
+        }
+
+        self.listener_addrs = listener_addrs
+            .into_iter()
+            .map(|addrs| addrs.into_iter().flatten().collect())
+            .collect();
+        self.local_addrs = self.listener_addrs[0]
+            .iter()
+            .filter_map(ListenAddr::as_tcp)
+            .collect();
+        Ok(self)
+    }
+
+    fn abort(cores: Vec<CoreJoinHandle>, shutdown: &Signal) -> GmfError {
+        shutdown.trigger();
+        cores
+            .into_iter()
+            .map(join_core)
+            .find_map(Result::err)
+            .unwrap_or_else(|| GmfError::Io(io::Error::other("core exited before binding")))
+    }
+}
+
+/// A listener bound on one core, as reported to [`ServerHandle::wait_bound`].
+pub(crate) struct Bound {
+    cpu: usize,
+    listener: usize,
+    addr: ListenAddr,
+}
+
+/// Per-core startup reporting, cloned into every core.
+#[derive(Clone)]
+pub(crate) struct Startup {
+    bound: mpsc::Sender<Bound>,
+    remaining: Arc<AtomicUsize>,
+    ready: Signal,
+    /// Port-0 coordination, one slot per listener.
+    shared_addrs: Arc<[SharedAddr]>,
+}
+
+#[derive(Default)]
+struct SharedAddr {
+    addr: OnceLock<Option<SocketAddr>>,
+    set: Signal,
+}
+
+impl Startup {
+    pub(crate) fn new(cores: usize, listeners: usize, bound: mpsc::Sender<Bound>) -> Self {
+        Startup {
+            bound,
+            remaining: Arc::new(AtomicUsize::new(cores * listeners)),
+            ready: Signal::new(),
+            shared_addrs: (0..listeners).map(|_| SharedAddr::default()).collect(),
+        }
+    }
+
+    /// The address this core should bind.
+    ///
+    /// With port 0 each core would otherwise get its own random port. Instead core 0
+    /// binds first and publishes the port the kernel picked, and the other cores wait for
+    /// it and join the same port via `SO_REUSEPORT`. Returns `None` if core 0 failed to
+    /// bind or the server shut down first; core 0 reports the bind error in that case.
+    pub(crate) async fn listen_addr(
+        &self,
+        cpu: usize,
+        listener: usize,
+        requested: SocketAddr,
+        shutdown: &Signal,
+    ) -> Option<SocketAddr> {
+        if requested.port() != 0 || cpu == 0 {
+            return Some(requested);
+        }
+
+        let shared = &self.shared_addrs[listener];
+        let mut published = pin!(shared.set.wait());
+        let mut stop = pin!(shutdown.wait());
+        match select(published.as_mut(), stop.as_mut()).await {
+            Either::Left(()) => shared.addr.get().copied().flatten(),
+            Either::Right(()) => None,
+        }
+    }
+
+    /// Called by core 0 once its bind attempt for `listener` finished (`None` on
+    /// failure).
+    pub(crate) fn publish_addr(&self, listener: usize, addr: Option<SocketAddr>) {
+        let shared = &self.shared_addrs[listener];
+        let _ = shared.addr.set(addr);
+        shared.set.trigger();
+    }
+
+    pub(crate) fn ready_signal(&self) -> Signal {
+        self.ready.clone()
+    }
+
+    /// Report the address one of this core's listeners ended up bound to.
+    pub(crate) fn bound(&self, cpu: usize, listener: usize, addr: ListenAddr) {
+        let _ = self.bound.send(Bound {
+            cpu,
+            listener,
+            addr,
+        });
+    }
+
+    /// Mark one of this core's listeners as accepting; the last one across all cores
+    /// resolves `ServerHandle::ready`.
+    pub(crate) fn accepting(&self) {
+        if self.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.ready.trigger();
+        }
+    }
+}
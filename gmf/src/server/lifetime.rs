@@ -0,0 +1,86 @@
+use std::fmt;
+use std::pin::pin;
+use std::time::Duration;
+
+use crate::server::config::ConnectionLifetime;
+use crate::server::frames::ConnFrames;
+use crate::server::runtime::RuntimeTimer;
+use crate::server::util::{select, Either};
+use crate::server::write_timeout::stall;
+
+/// Fraction of `max_age` added or removed at random, as gRPC does.
+const AGE_JITTER: f64 = 0.1;
+
+/// Why a connection was retired.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Expiry {
+    Idle,
+    Age,
+}
+
+impl fmt::Display for Expiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expiry::Idle => f.write_str("max_connection_idle"),
+            Expiry::Age => f.write_str("max_connection_age"),
+        }
+    }
+}
+
+impl ConnectionLifetime {
+    /// Whether connections need their streams tracked to detect idleness.
+    pub(crate) fn tracks_idle(&self) -> bool {
+        self.max_idle.is_some()
+    }
+
+    /// Resolves once the connection has been idle or alive for too long. Idleness is
+    /// only detected when `frames` tracks the connection's streams.
+    pub(crate) async fn expired<T: RuntimeTimer>(
+        &self,
+        timer: &T,
+        frames: Option<&ConnFrames>,
+    ) -> Expiry {
+        let idle = pin!(async {
+            match (self.max_idle, frames) {
+                (Some(max_idle), Some(frames)) => {
+                    stall(timer, max_idle, |now| frames.idle_for(now)).await
+                }
+                _ => std::future::pending().await,
+            }
+        });
+        let age = pin!(async {
+            match self.max_age {
+                Some(max_age) => timer.sleep(jittered(max_age)).await,
+                None => std::future::pending().await,
+            }
+        });
+
+        match select(idle, age).await {
+            Either::Left(()) => Expiry::Idle,
+            Either::Right(()) => Expiry::Age,
+        }
+    }
+}
+
+fn jittered(age: Duration) -> Duration {
+    age.mul_f64(1.0 - AGE_JITTER + 2.0 * AGE_JITTER * fastrand::f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_stays_within_a_tenth() {
+        let age = Duration::from_secs(100);
+        let (low, high) = (age.mul_f64(0.9), age.mul_f64(1.1));
+        let ages: Vec<_> = (0..1000).map(|_| jittered(age)).collect();
+        assert!(ages
+            .iter()
+            .all(|&jittered| low <= jittered && jittered <= high));
+        // Spread out, not pinned to one end.
+        assert!(ages.iter().any(|&jittered| jittered < age));
+        assert!(ages.iter().any(|&jittered| jittered > age));
+        assert_eq!(jittered(Duration::ZERO), Duration::ZERO);
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{SocketAddr as StdUnixAddr, UnixListener as StdUnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use socket2::SockRef;
+
+use crate::server::runtime::{RuntimeStream, RuntimeTcpListener, RuntimeUnixListener};
+use crate::server::socket::SocketOptions;
+
+/// Where a server listens: a TCP socket address or a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(UnixAddr),
+}
+
+impl ListenAddr {
+    /// The TCP address, if this is a TCP listener.
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            ListenAddr::Tcp(addr) => Some(*addr),
+            ListenAddr::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ListenAddr::Tcp(addr)
+    }
+}
+
+impl From<UnixAddr> for ListenAddr {
+    fn from(addr: UnixAddr) -> Self {
+        ListenAddr::Unix(addr)
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => addr.fmt(f),
+            ListenAddr::Unix(addr) => addr.fmt(f),
+        }
+    }
+}
+
+/// A Unix domain socket name: a filesystem path or, on Linux, a name in the abstract
+/// namespace (no file is created and it disappears with the last socket).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UnixAddr {
+    Path(PathBuf),
+    Abstract(Vec<u8>),
+}
+
+impl UnixAddr {
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        UnixAddr::Path(path.into())
+    }
+
+    pub fn abstract_name(name: impl Into<Vec<u8>>) -> Self {
+        UnixAddr::Abstract(name.into())
+    }
+
+    /// The per-core variant of this name: `.{cpu}` is appended.
+    pub fn for_core(&self, cpu: usize) -> Self {
+        match self {
+            UnixAddr::Path(path) => {
+                let mut path = path.clone().into_os_string();
+                path.push(format!(".{cpu}"));
+                UnixAddr::Path(path.into())
+            }
+            UnixAddr::Abstract(name) => {
+                let mut name = name.clone();
+                name.extend_from_slice(format!(".{cpu}").as_bytes());
+                UnixAddr::Abstract(name)
+            }
+        }
+    }
+
+    /// The name as a path for APIs that only take paths. Abstract names get the leading
+    /// NUL byte that marks them in `sockaddr_un`.
+    #[cfg(feature = "glommio-runtime")]
+    pub(crate) fn to_path(&self) -> PathBuf {
+        match self {
+            UnixAddr::Path(path) => path.clone(),
+            UnixAddr::Abstract(name) => {
+                use std::ffi::OsStr;
+                use std::os::unix::ffi::OsStrExt;
+
+                let mut bytes = Vec::with_capacity(name.len() + 1);
+                bytes.push(0);
+                bytes.extend_from_slice(name);
+                PathBuf::from(OsStr::from_bytes(&bytes))
+            }
+        }
+    }
+
+    pub(crate) fn to_std(&self) -> io::Result<StdUnixAddr> {
+        match self {
+            UnixAddr::Path(path) => StdUnixAddr::from_pathname(path),
+            #[cfg(target_os = "linux")]
+            UnixAddr::Abstract(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                StdUnixAddr::from_abstract_name(name)
+            }
+            #[cfg(not(target_os = "linux"))]
+            UnixAddr::Abstract(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract Unix socket names require Linux",
+            )),
+        }
+    }
+
+    /// Bind a non-blocking std listener, removing a stale socket file left behind by a
+    /// previous process first.
+    pub(crate) fn bind_std(&self, options: &SocketOptions) -> io::Result<StdUnixListener> {
+        self.remove_stale()?;
+        let listener = StdUnixListener::bind_addr(&self.to_std()?)?;
+        // std listens with a fixed backlog; listening again only resizes the queue.
+        SockRef::from(&listener).listen(options.backlog)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    }
+
+    /// Remove the socket file at this path if nothing is listening on it any more.
+    /// Fails with `AddrInUse` at bind time if a live server still owns it.
+    pub(crate) fn remove_stale(&self) -> io::Result<()> {
+        let UnixAddr::Path(path) = self else {
+            return Ok(());
+        };
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => {
+                if let Err(e) = UnixStream::connect(path) {
+                    if e.kind() == io::ErrorKind::ConnectionRefused {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the socket file once the listener is closed.
+    pub(crate) fn cleanup(&self) {
+        if let UnixAddr::Path(path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl fmt::Display for UnixAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnixAddr::Path(path) => write!(f, "unix:{}", path.display()),
+            UnixAddr::Abstract(name) => write!(f, "unix:@{}", String::from_utf8_lossy(name)),
+        }
+    }
+}
+
+impl From<&Path> for UnixAddr {
+    fn from(path: &Path) -> Self {
+        UnixAddr::Path(path.to_owned())
+    }
+}
+
+/// How the cores of a server share a Unix socket listener. The default depends on
+/// the runtime: `Shared` where supported, `PerCore` on glommio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnixStrategy {
+    /// Bind one socket and let every core accept from it. Clients use a single name.
+    /// Not supported by the glommio runtime, which cannot adopt an existing listener.
+    Shared,
+    /// Each core binds its own socket at `{name}.{cpu}`; clients (or a proxy in front)
+    /// spread themselves across the names.
+    PerCore,
+}
+
+/// Credentials of the process on the other end of a Unix socket (`SO_PEERCRED`),
+/// captured when the connection is accepted and inserted into every request's
+/// extensions. Not available on the glommio runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerCredentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[cfg(any(feature = "monoio-runtime", feature = "tokio-runtime"))]
+impl PeerCredentials {
+    #[cfg(target_os = "linux")]
+    pub(crate) fn from_fd(fd: std::os::fd::RawFd) -> io::Result<Self> {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PeerCredentials {
+            pid: cred.pid,
+            uid: cred.uid,
+            gid: cred.gid,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn from_fd(_fd: std::os::fd::RawFd) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SO_PEERCRED requires Linux",
+        ))
+    }
+}
+
+/// The remote end of an accepted connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// A Unix socket client, identified by its credentials when the kernel reports them.
+    Unix(Option<PeerCredentials>),
+}
+
+impl PeerAddr {
+    /// The TCP address, if the peer connected over TCP.
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(*addr),
+            PeerAddr::Unix(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => addr.fmt(f),
+            PeerAddr::Unix(Some(cred)) => {
+                write!(
+                    f,
+                    "unix(pid={},uid={},gid={})",
+                    cred.pid, cred.uid, cred.gid
+                )
+            }
+            PeerAddr::Unix(None) => f.write_str("unix"),
+        }
+    }
+}
+
+/// A bound listener of either kind, as driven by the accept loop.
+pub(crate) trait Acceptor {
+    type Stream: RuntimeStream;
+
+    async fn accept(&self) -> io::Result<(Self::Stream, PeerAddr)>;
+
+    /// The listening socket, if the runtime exposes it.
+    fn raw_fd(&self) -> Option<RawFd>;
+}
+
+pub(crate) struct TcpAcceptor<L> {
+    pub(crate) listener: L,
+    pub(crate) options: SocketOptions,
+}
+
+impl<L: RuntimeTcpListener> Acceptor for TcpAcceptor<L> {
+    type Stream = L::Stream;
+
+    async fn accept(&self) -> io::Result<(Self::Stream, PeerAddr)> {
+        let (stream, addr) = self.listener.accept().await?;
+        // A failed setsockopt only concerns this connection (e.g. the peer already
+        // reset it), so it is served with the options that did apply.
+        if let Err(e) = self.options.apply_accepted(stream.as_raw_fd()) {
+            tracing::warn!(peer = %addr, error = %e, "failed to apply socket options");
+        }
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.listener.as_raw_fd())
+    }
+}
+
+pub(crate) struct UnixAcceptor<L>(pub(crate) L);
+
+impl<L: RuntimeUnixListener> Acceptor for UnixAcceptor<L> {
+    type Stream = L::Stream;
+
+    async fn accept(&self) -> io::Result<(Self::Stream, PeerAddr)> {
+        let (stream, cred) = self.0.accept().await?;
+        Ok((stream, PeerAddr::Unix(cred)))
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.0.raw_fd()
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::server::listener::PeerAddr;
+
+/// Counters shared by every core of one server.
+///
+/// Only rare events (failures, rejections) are recorded here, so the atomics stay off
+/// the per-request path. Obtain it from [`GmfServer::metrics`] or
+/// [`ServerHandle::metrics`].
+///
+/// [`GmfServer::metrics`]: crate::server::gmf_server::GmfServer::metrics
+/// [`ServerHandle::metrics`]: crate::server::handle::ServerHandle::metrics
+#[derive(Debug)]
+pub struct ServerMetrics {
+    #[cfg(feature = "tls")]
+    tls: TlsMetrics,
+    write_timeouts: WriteTimeoutMetrics,
+    handshake_timeouts: HandshakeTimeoutMetrics,
+    rejections: RejectionMetrics,
+}
+
+impl ServerMetrics {
+    pub(crate) fn new(cores: usize) -> Self {
+        ServerMetrics {
+            #[cfg(feature = "tls")]
+            tls: TlsMetrics::default(),
+            write_timeouts: WriteTimeoutMetrics::default(),
+            handshake_timeouts: HandshakeTimeoutMetrics::new(cores),
+            rejections: RejectionMetrics::new(cores),
+        }
+    }
+
+    /// TLS handshake counters.
+    #[cfg(feature = "tls")]
+    pub fn tls(&self) -> &TlsMetrics {
+        &self.tls
+    }
+
+    /// Streams and connections evicted for not reading their responses.
+    pub fn write_timeouts(&self) -> &WriteTimeoutMetrics {
+        &self.write_timeouts
+    }
+
+    /// Connections closed for not completing their handshake in time.
+    pub fn handshake_timeouts(&self) -> &HandshakeTimeoutMetrics {
+        &self.handshake_timeouts
+    }
+
+    /// Connections rejected at accept by the server's [`AccessPolicy`].
+    ///
+    /// [`AccessPolicy`]: crate::server::access::AccessPolicy
+    pub fn rejections(&self) -> &RejectionMetrics {
+        &self.rejections
+    }
+}
+
+/// Why a TLS handshake was rejected.
+#[cfg(feature = "tls")]
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TlsFailureReason {
+    /// Client authentication is required but the client sent no certificate.
+    NoClientCertificate,
+    /// The client chain does not lead to a configured CA.
+    UnknownIssuer,
+    Expired,
+    NotValidYet,
+    Revoked,
+    /// No CRL covers the client certificate.
+    UnknownRevocationStatus,
+    ExpiredRevocationList,
+    BadSignature,
+    /// The certificate is not valid for client authentication.
+    InvalidPurpose,
+    BadEncoding,
+    /// Any other certificate verification error.
+    OtherCertificateError,
+    /// A TLS protocol error that is not about certificates (e.g. a plaintext client or
+    /// no common cipher suite).
+    Protocol,
+    /// The transport failed during the handshake.
+    Io,
+}
+
+#[cfg(feature = "tls")]
+impl TlsFailureReason {
+    /// Every reason, in counter order.
+    pub const ALL: [TlsFailureReason; 13] = [
+        TlsFailureReason::NoClientCertificate,
+        TlsFailureReason::UnknownIssuer,
+        TlsFailureReason::Expired,
+        TlsFailureReason::NotValidYet,
+        TlsFailureReason::Revoked,
+        TlsFailureReason::UnknownRevocationStatus,
+        TlsFailureReason::ExpiredRevocationList,
+        TlsFailureReason::BadSignature,
+        TlsFailureReason::InvalidPurpose,
+        TlsFailureReason::BadEncoding,
+        TlsFailureReason::OtherCertificateError,
+        TlsFailureReason::Protocol,
+        TlsFailureReason::Io,
+    ];
+
+    /// A stable label, suitable for a metrics tag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TlsFailureReason::NoClientCertificate => "no_client_certificate",
+            TlsFailureReason::UnknownIssuer => "unknown_issuer",
+            TlsFailureReason::Expired => "expired",
+            TlsFailureReason::NotValidYet => "not_valid_yet",
+            TlsFailureReason::Revoked => "revoked",
+            TlsFailureReason::UnknownRevocationStatus => "unknown_revocation_status",
+            TlsFailureReason::ExpiredRevocationList => "expired_revocation_list",
+            TlsFailureReason::BadSignature => "bad_signature",
+            TlsFailureReason::InvalidPurpose => "invalid_purpose",
+            TlsFailureReason::BadEncoding => "bad_encoding",
+            TlsFailureReason::OtherCertificateError => "other_certificate_error",
+            TlsFailureReason::Protocol => "protocol",
+            TlsFailureReason::Io => "io",
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl std::fmt::Display for TlsFailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Failed TLS handshakes, counted per [`TlsFailureReason`].
+#[cfg(feature = "tls")]
+#[derive(Debug, Default)]
+pub struct TlsMetrics {
+    handshake_failures: [AtomicU64; TlsFailureReason::ALL.len()],
+}
+
+#[cfg(feature = "tls")]
+impl TlsMetrics {
+    /// Handshakes rejected for `reason` since the server was built.
+    pub fn handshake_failures(&self, reason: TlsFailureReason) -> u64 {
+        self.handshake_failures[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Every reason with its count, in [`TlsFailureReason::ALL`] order.
+    pub fn snapshot(&self) -> Vec<(TlsFailureReason, u64)> {
+        TlsFailureReason::ALL
+            .iter()
+            .map(|&reason| (reason, self.handshake_failures(reason)))
+            .collect()
+    }
+
+    pub(crate) fn record_handshake_failure(&self, reason: TlsFailureReason) {
+        self.handshake_failures[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// What a write timeout evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WriteTimeoutScope {
+    /// A single stream was reset with `RST_STREAM`.
+    Stream,
+    /// The whole connection was sent GOAWAY and closed.
+    Connection,
+}
+
+/// One write-timeout eviction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowPeer {
+    pub peer_addr: PeerAddr,
+    /// The core that served the connection.
+    pub cpu: usize,
+    pub scope: WriteTimeoutScope,
+    pub at: SystemTime,
+}
+
+/// Write-progress timeout evictions.
+///
+/// Besides the totals, the most recent evictions are kept (up to
+/// [`RECENT_CAPACITY`](Self::RECENT_CAPACITY)) so operators can see which peers are
+/// slow without an unbounded per-peer table.
+#[derive(Debug, Default)]
+pub struct WriteTimeoutMetrics {
+    streams_reset: AtomicU64,
+    connections_closed: AtomicU64,
+    recent: Mutex<VecDeque<SlowPeer>>,
+}
+
+impl WriteTimeoutMetrics {
+    pub const RECENT_CAPACITY: usize = 64;
+
+    /// Streams reset because the peer stopped reading them.
+    pub fn streams_reset(&self) -> u64 {
+        self.streams_reset.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed because the peer stopped reading from them.
+    pub fn connections_closed(&self) -> u64 {
+        self.connections_closed.load(Ordering::Relaxed)
+    }
+
+    /// The most recent evictions, oldest first.
+    pub fn recent_slow_peers(&self) -> Vec<SlowPeer> {
+        self.recent.lock().unwrap().iter().copied().collect()
+    }
+
+    pub(crate) fn record(&self, peer: SlowPeer) {
+        match peer.scope {
+            WriteTimeoutScope::Stream => &self.streams_reset,
+            WriteTimeoutScope::Connection => &self.connections_closed,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == Self::RECENT_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(peer);
+    }
+}
+
+/// The part of the handshake a connection had not finished when it timed out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandshakeStage {
+    /// The PROXY protocol header, on listeners that expect one.
+    Proxy,
+    /// The TLS handshake.
+    Tls,
+    /// The HTTP/2 connection preface and the client's first `SETTINGS` frame.
+    Http2,
+}
+
+impl HandshakeStage {
+    pub const ALL: [HandshakeStage; 3] = [
+        HandshakeStage::Proxy,
+        HandshakeStage::Tls,
+        HandshakeStage::Http2,
+    ];
+
+    /// A stable label, suitable for a metrics tag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HandshakeStage::Proxy => "proxy",
+            HandshakeStage::Tls => "tls",
+            HandshakeStage::Http2 => "http2",
+        }
+    }
+}
+
+impl std::fmt::Display for HandshakeStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Handshake timeouts, counted per core and [`HandshakeStage`].
+#[derive(Debug)]
+pub struct HandshakeTimeoutMetrics {
+    per_core: Box<[[AtomicU64; HandshakeStage::ALL.len()]]>,
+}
+
+impl HandshakeTimeoutMetrics {
+    fn new(cores: usize) -> Self {
+        HandshakeTimeoutMetrics {
+            per_core: (0..cores).map(|_| Default::default()).collect(),
+        }
+    }
+
+    /// Connections core `cpu` closed during `stage`.
+    pub fn count(&self, cpu: usize, stage: HandshakeStage) -> u64 {
+        self.per_core
+            .get(cpu)
+            .map_or(0, |core| core[stage as usize].load(Ordering::Relaxed))
+    }
+
+    /// Timeouts per core, all stages combined, indexed by core.
+    pub fn per_core(&self) -> Vec<u64> {
+        (0..self.per_core.len())
+            .map(|cpu| {
+                HandshakeStage::ALL
+                    .iter()
+                    .map(|&stage| self.count(cpu, stage))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Timeouts across all cores.
+    pub fn total(&self) -> u64 {
+        self.per_core().iter().sum()
+    }
+
+    pub(crate) fn record(&self, cpu: usize, stage: HandshakeStage) {
+        if let Some(core) = self.per_core.get(cpu) {
+            core[stage as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Why a connection was rejected at accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectReason {
+    /// The peer is in a denied network.
+    Denied,
+    /// An allow list is set and the peer is in none of its networks.
+    NotAllowed,
+    /// The peer already has `max_connections_per_peer` connections open on this core.
+    PeerLimit,
+}
+
+impl RejectReason {
+    pub const ALL: [RejectReason; 3] = [
+        RejectReason::Denied,
+        RejectReason::NotAllowed,
+        RejectReason::PeerLimit,
+    ];
+
+    /// A stable label, suitable for a metrics tag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RejectReason::Denied => "denied",
+            RejectReason::NotAllowed => "not_allowed",
+            RejectReason::PeerLimit => "peer_limit",
+        }
+    }
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Rejected connections, counted per core and [`RejectReason`].
+#[derive(Debug)]
+pub struct RejectionMetrics {
+    per_core: Box<[[AtomicU64; RejectReason::ALL.len()]]>,
+}
+
+impl RejectionMetrics {
+    fn new(cores: usize) -> Self {
+        RejectionMetrics {
+            per_core: (0..cores).map(|_| Default::default()).collect(),
+        }
+    }
+
+    /// Connections core `cpu` rejected for `reason`.
+    pub fn count(&self, cpu: usize, reason: RejectReason) -> u64 {
+        self.per_core
+            .get(cpu)
+            .map_or(0, |core| core[reason as usize].load(Ordering::Relaxed))
+    }
+
+    /// Rejections for `reason` across all cores.
+    pub fn by_reason(&self, reason: RejectReason) -> u64 {
+        (0..self.per_core.len())
+            .map(|cpu| self.count(cpu, reason))
+            .sum()
+    }
+
+    /// Rejections per core, all reasons combined, indexed by core.
+    pub fn per_core(&self) -> Vec<u64> {
+        (0..self.per_core.len())
+            .map(|cpu| {
+                RejectReason::ALL
+                    .iter()
+                    .map(|&reason| self.count(cpu, reason))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Rejections across all cores.
+    pub fn total(&self) -> u64 {
+        self.per_core().iter().sum()
+    }
+
+    pub(crate) fn record(&self, cpu: usize, reason: RejectReason) {
+        if let Some(core) = self.per_core.get(cpu) {
+            core[reason as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -1,7 +1,30 @@
+pub mod access;
+mod budget;
+pub mod cgroup;
 pub mod config;
+pub mod connect_info;
+pub mod cpu;
 pub mod error;
+mod frames;
 pub mod gmf_server;
+pub mod handle;
+mod io;
+mod lifetime;
+pub mod listener;
+pub mod metrics;
+pub mod proxy;
 pub mod runtime;
+mod semaphore;
+mod shutdown;
+pub mod socket;
+pub mod systemd;
+pub mod topology;
+pub mod upgrade;
+mod util;
+mod write_timeout;
+
+#[cfg(feature = "tls")]
+pub mod tls;
 
 #[cfg(feature = "glommio-runtime")]
 mod hyper_io;
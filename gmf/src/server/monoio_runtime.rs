@@ -1,49 +1,52 @@
-use std::cell::Cell;
 use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
-use std::rc::Rc;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::pin::Pin;
+use std::time::Duration;
 
 use monoio::net::{TcpListener as MonoioTcpListener, TcpStream as MonoioTcpStream};
 
+use crate::server::cpu::CorePlacement;
 use crate::server::error::GmfError;
+use crate::server::listener::PeerCredentials;
 use crate::server::runtime::{
-    Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeTcpListener, RuntimeTcpStream,
+    CoreJoinHandle, Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeStream, RuntimeTcpListener,
+    RuntimeTimer, RuntimeUnixListener,
 };
+use crate::server::semaphore::{LocalPermit, LocalSemaphore};
+use crate::server::util::LocalSleep;
 
 /// Thread-per-core runtime using monoio (io_uring on Linux, kqueue on macOS).
 pub struct MonoioRuntime;
 
 impl Runtime for MonoioRuntime {
     type TcpListener = MonoioListener;
+    type UnixListener = MonoioUnixListener;
     type Executor = MonoioExec;
     type Semaphore = MonoioSemaphore;
+    type Timer = MonoioTimer;
 
-    fn run_multi_core<F, Fut>(cores: usize, f: F) -> Result<(), GmfError>
+    fn spawn_multi_core<F, Fut>(
+        placement: &CorePlacement,
+        f: F,
+    ) -> Result<Vec<CoreJoinHandle>, GmfError>
     where
         F: Fn(usize) -> Fut + Send + Clone + 'static,
         Fut: Future<Output = Result<(), GmfError>> + 'static,
     {
-        let mut handles = Vec::with_capacity(cores);
+        let mut handles = Vec::with_capacity(placement.cores());
 
-        for cpu in 0..cores {
+        for cpu in 0..placement.cores() {
             let f = f.clone();
+            let placement = placement.clone();
             let handle = std::thread::Builder::new()
                 .name(format!("gmf_core_{cpu}"))
                 .spawn(move || -> Result<(), GmfError> {
-                    // Pin to CPU core on Linux for optimal thread-per-core performance.
-                    #[cfg(target_os = "linux")]
-                    {
-                        unsafe {
-                            let mut cpuset: libc::cpu_set_t = std::mem::zeroed();
-                            libc::CPU_SET(cpu, &mut cpuset);
-                            libc::sched_setaffinity(
-                                0,
-                                std::mem::size_of::<libc::cpu_set_t>(),
-                                &cpuset,
-                            );
-                        }
-                    }
+                    // Pin before building the runtime so its io_uring lives on this CPU and,
+                    // with NUMA placement, is allocated from its node.
+                    placement.bind_current_thread(cpu)?;
 
                     let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
                         .enable_timer()
@@ -57,13 +60,7 @@ impl Runtime for MonoioRuntime {
             handles.push(handle);
         }
 
-        for handle in handles {
-            handle
-                .join()
-                .map_err(|_| GmfError::Io(io::Error::other("thread panicked")))??;
-        }
-
-        Ok(())
+        Ok(handles)
     }
 }
 
@@ -71,11 +68,20 @@ impl Runtime for MonoioRuntime {
 
 pub struct MonoioListener(MonoioTcpListener);
 
+impl AsRawFd for MonoioListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
 impl RuntimeTcpListener for MonoioListener {
     type Stream = MonoioStream;
 
-    async fn bind(addr: SocketAddr) -> io::Result<Self> {
-        let listener = MonoioTcpListener::bind(addr)?;
+    fn from_std(listener: std::net::TcpListener) -> io::Result<Self> {
+        // Matches monoio's own bind, which needs a non-blocking socket for the legacy
+        // (epoll/kqueue) driver.
+        listener.set_nonblocking(true)?;
+        let listener = MonoioTcpListener::from_std(listener)?;
         Ok(MonoioListener(listener))
     }
 
@@ -83,13 +89,23 @@ impl RuntimeTcpListener for MonoioListener {
         let (stream, addr) = self.0.accept().await?;
         Ok((MonoioStream(stream), addr))
     }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
 }
 
 // -- TCP Stream --
 
 pub struct MonoioStream(MonoioTcpStream);
 
-impl RuntimeTcpStream for MonoioStream {
+impl AsRawFd for MonoioStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl RuntimeStream for MonoioStream {
     // monoio TcpStream is completion-based; wrap with StreamWrapper for poll-based compat,
     // then MonoioIo for hyper::rt::{Read, Write}.
     type HyperIo = monoio_compat::hyper::MonoioIo<monoio_compat::StreamWrapper<MonoioTcpStream>>;
@@ -100,6 +116,44 @@ impl RuntimeTcpStream for MonoioStream {
     }
 }
 
+// -- Unix Listener --
+
+pub struct MonoioUnixListener(monoio::net::UnixListener);
+
+impl RuntimeUnixListener for MonoioUnixListener {
+    type Stream = MonoioUnixStream;
+
+    fn from_std(listener: StdUnixListener) -> io::Result<Self> {
+        Ok(MonoioUnixListener(monoio::net::UnixListener::from_std(
+            listener,
+        )?))
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.0.as_raw_fd())
+    }
+
+    async fn accept(&self) -> io::Result<(Self::Stream, Option<PeerCredentials>)> {
+        let (stream, _) = self.0.accept().await?;
+        let cred = PeerCredentials::from_fd(stream.as_raw_fd()).ok();
+        Ok((MonoioUnixStream(stream), cred))
+    }
+}
+
+// -- Unix Stream --
+
+pub struct MonoioUnixStream(monoio::net::UnixStream);
+
+impl RuntimeStream for MonoioUnixStream {
+    type HyperIo =
+        monoio_compat::hyper::MonoioIo<monoio_compat::StreamWrapper<monoio::net::UnixStream>>;
+
+    fn into_hyper_io(self) -> Self::HyperIo {
+        let compat = monoio_compat::StreamWrapper::new(self.0);
+        monoio_compat::hyper::MonoioIo::new(compat)
+    }
+}
+
 // -- Executor --
 
 #[derive(Clone, Default)]
@@ -123,26 +177,55 @@ where
     }
 }
 
+// -- Timer --
+
+#[derive(Clone, Default)]
+pub struct MonoioTimer;
+
+impl RuntimeTimer for MonoioTimer {
+    async fn sleep(&self, duration: Duration) {
+        monoio::time::sleep(duration).await;
+    }
+
+    fn hyper_sleep(&self, duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        Box::pin(LocalSleep::new(monoio::time::sleep(duration)))
+    }
+}
+
 // -- Semaphore --
 
+#[derive(Clone)]
 pub struct MonoioSemaphore {
-    permits: Rc<Cell<usize>>,
+    permits: LocalSemaphore,
 }
 
 impl RuntimeSemaphore for MonoioSemaphore {
+    type Permit = MonoioPermit;
+
     fn new(permits: usize) -> Self {
         MonoioSemaphore {
-            permits: Rc::new(Cell::new(permits)),
+            permits: LocalSemaphore::new(permits),
         }
     }
 
-    fn try_acquire(&self) -> bool {
-        let current = self.permits.get();
-        if current > 0 {
-            self.permits.set(current - 1);
-            true
-        } else {
-            false
+    fn try_acquire(&self) -> Option<Self::Permit> {
+        self.permits
+            .try_acquire()
+            .map(|permit| MonoioPermit { _permit: permit })
+    }
+
+    async fn acquire(&self) -> Self::Permit {
+        MonoioPermit {
+            _permit: self.permits.acquire().await,
         }
     }
+
+    fn available(&self) -> usize {
+        self.permits.available()
+    }
+}
+
+/// A connection permit; returns its slot to the `MonoioSemaphore` on drop.
+pub struct MonoioPermit {
+    _permit: LocalPermit,
 }
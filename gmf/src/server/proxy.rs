@@ -0,0 +1,514 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, Bytes};
+
+/// First 12 bytes of a PROXY protocol v2 header.
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Fixed part of a v2 header: signature, version/command, family, length.
+const V2_HEADER_LEN: usize = 16;
+/// Longest v1 header the spec allows, CRLF included.
+const V1_MAX_LEN: usize = 107;
+
+/// The PROXY protocol header a load balancer sent ahead of a connection, describing the
+/// client connection it forwards.
+///
+/// On listeners with [`ListenerConfig::proxy_protocol`] enabled, GMF reads it before
+/// anything else. The source address then stands in for the peer address everywhere
+/// (logs, [`GmfConnectInfo`], access policy), and the header itself is available from
+/// [`GmfConnectInfo::proxy_header`].
+///
+/// [`ListenerConfig::proxy_protocol`]: crate::server::config::ListenerConfig::proxy_protocol
+/// [`GmfConnectInfo`]: crate::server::connect_info::GmfConnectInfo
+/// [`GmfConnectInfo::proxy_header`]: crate::server::connect_info::GmfConnectInfo::proxy_header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyHeader {
+    version: u8,
+    addrs: Option<(SocketAddr, SocketAddr)>,
+    tlvs: Vec<Tlv>,
+}
+
+impl ProxyHeader {
+    /// The protocol version, 1 (text) or 2 (binary).
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The client's address. `None` for v1 `UNKNOWN`, v2 `LOCAL` (e.g. the balancer's
+    /// own health checks) and non-TCP/IP families, in which case the connection's own
+    /// peer address is used.
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.addrs.map(|(source, _)| source)
+    }
+
+    /// The address the client connected to on the balancer.
+    pub fn destination(&self) -> Option<SocketAddr> {
+        self.addrs.map(|(_, destination)| destination)
+    }
+
+    /// The v2 type-length-value extensions, in the order received.
+    pub fn tlvs(&self) -> &[Tlv] {
+        &self.tlvs
+    }
+
+    /// The value of the first TLV of type `kind`.
+    pub fn tlv(&self, kind: u8) -> Option<&[u8]> {
+        self.tlvs
+            .iter()
+            .find(|tlv| tlv.kind == kind)
+            .map(|tlv| &tlv.value[..])
+    }
+}
+
+/// A PROXY protocol v2 type-length-value extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub kind: u8,
+    pub value: Bytes,
+}
+
+impl Tlv {
+    /// `PP2_TYPE_ALPN`: the protocol the client negotiated with the balancer.
+    pub const ALPN: u8 = 0x01;
+    /// `PP2_TYPE_AUTHORITY`: the host name the client asked for (TLS SNI).
+    pub const AUTHORITY: u8 = 0x02;
+    /// `PP2_TYPE_CRC32C`: a checksum of the header.
+    pub const CRC32C: u8 = 0x03;
+    /// `PP2_TYPE_NOOP`: padding.
+    pub const NOOP: u8 = 0x04;
+    /// `PP2_TYPE_UNIQUE_ID`: an opaque connection id assigned by the balancer.
+    pub const UNIQUE_ID: u8 = 0x05;
+    /// `PP2_TYPE_SSL`: TLS details of the client connection, with sub-TLVs.
+    pub const SSL: u8 = 0x20;
+    /// `PP2_TYPE_NETNS`: the network namespace the connection was accepted in.
+    pub const NETNS: u8 = 0x30;
+    /// AWS `PP2_TYPE_AWS`: the VPC endpoint id, behind an AWS NLB.
+    pub const AWS: u8 = 0xea;
+}
+
+/// Read the PROXY header from the start of a connection. Returns the header and any
+/// bytes read past it, which belong to the connection's payload.
+pub(crate) async fn read_header<I>(io: &mut I) -> io::Result<(ProxyHeader, Bytes)>
+where
+    I: hyper::rt::Read + Unpin,
+{
+    let mut buf = Vec::with_capacity(V1_MAX_LEN);
+    let mut chunk = [0; 512];
+    loop {
+        if let Some((header, len)) = parse(&buf)? {
+            let mut rest = Bytes::from(buf);
+            rest.advance(len);
+            return Ok((header, rest));
+        }
+        let n = std::future::poll_fn(|cx| {
+            let mut read = hyper::rt::ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut *io).poll_read(cx, read.unfilled()))?;
+            Poll::Ready(Ok::<_, io::Error>(read.filled().len()))
+        })
+        .await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Parse a header from the start of `buf`, returning it with its length, or `None` if
+/// more bytes are needed.
+fn parse(buf: &[u8]) -> io::Result<Option<(ProxyHeader, usize)>> {
+    let prefix = buf.len().min(V2_SIGNATURE.len());
+    if buf[..prefix] == V2_SIGNATURE[..prefix] {
+        return if prefix < V2_SIGNATURE.len() {
+            Ok(None)
+        } else {
+            parse_v2(buf)
+        };
+    }
+    let prefix = buf.len().min(6);
+    if buf[..prefix] == b"PROXY "[..prefix] {
+        return if prefix < 6 { Ok(None) } else { parse_v1(buf) };
+    }
+    Err(invalid("no PROXY protocol header"))
+}
+
+fn parse_v1(buf: &[u8]) -> io::Result<Option<(ProxyHeader, usize)>> {
+    let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+        return if buf.len() >= V1_MAX_LEN {
+            Err(invalid("PROXY v1 header too long"))
+        } else {
+            Ok(None)
+        };
+    };
+    if end + 2 > V1_MAX_LEN {
+        return Err(invalid("PROXY v1 header too long"));
+    }
+    let line =
+        std::str::from_utf8(&buf[..end]).map_err(|_| invalid("PROXY v1 header not ASCII"))?;
+    let mut fields = line.split(' ').skip(1);
+
+    let addrs = match fields.next() {
+        Some("UNKNOWN") => None,
+        Some(family @ ("TCP4" | "TCP6")) => {
+            let mut next = || {
+                fields
+                    .next()
+                    .ok_or_else(|| invalid("PROXY v1 header truncated"))
+            };
+            let (src, dst, sport, dport) = (next()?, next()?, next()?, next()?);
+            let ip = |s: &str| -> io::Result<IpAddr> {
+                let ip = match family {
+                    "TCP4" => s.parse::<Ipv4Addr>().map(IpAddr::V4),
+                    _ => s.parse::<Ipv6Addr>().map(IpAddr::V6),
+                };
+                ip.map_err(|_| invalid("bad address in PROXY v1 header"))
+            };
+            let port = |s: &str| -> io::Result<u16> {
+                s.parse()
+                    .map_err(|_| invalid("bad port in PROXY v1 header"))
+            };
+            if fields.next().is_some() {
+                return Err(invalid("trailing fields in PROXY v1 header"));
+            }
+            Some((
+                SocketAddr::new(ip(src)?, port(sport)?),
+                SocketAddr::new(ip(dst)?, port(dport)?),
+            ))
+        }
+        _ => return Err(invalid("unknown PROXY v1 protocol family")),
+    };
+
+    let header = ProxyHeader {
+        version: 1,
+        addrs,
+        tlvs: Vec::new(),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+fn parse_v2(buf: &[u8]) -> io::Result<Option<(ProxyHeader, usize)>> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let (version, command) = (buf[12] >> 4, buf[12] & 0xf);
+    if version != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let total = V2_HEADER_LEN + usize::from(u16::from_be_bytes([buf[14], buf[15]]));
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let family = buf[13];
+    let payload = &buf[V2_HEADER_LEN..total];
+
+    // Address block length by address family (high nibble): unspecified, IPv4, IPv6,
+    // Unix.
+    let addrs_len = match family >> 4 {
+        0x0 => 0,
+        0x1 => 12,
+        0x2 => 36,
+        0x3 => 216,
+        _ => return Err(invalid("unknown PROXY v2 address family")),
+    };
+    if payload.len() < addrs_len {
+        return Err(invalid("PROXY v2 address block truncated"));
+    }
+    let (addrs_block, mut tlv_block) = payload.split_at(addrs_len);
+
+    // Only the PROXY command (1) over TCP (stream transport, low nibble 1) carries a
+    // client address; LOCAL (0) connections are the balancer's own.
+    let addrs = match (command, family) {
+        (0x0, _) => None,
+        (0x1, 0x11) => Some((
+            SocketAddr::new(ipv4(&addrs_block[0..4]), port(&addrs_block[8..10])),
+            SocketAddr::new(ipv4(&addrs_block[4..8]), port(&addrs_block[10..12])),
+        )),
+        (0x1, 0x21) => Some((
+            SocketAddr::new(ipv6(&addrs_block[0..16]), port(&addrs_block[32..34])),
+            SocketAddr::new(ipv6(&addrs_block[16..32]), port(&addrs_block[34..36])),
+        )),
+        (0x1, _) => None,
+        _ => return Err(invalid("unknown PROXY v2 command")),
+    };
+
+    let mut tlvs = Vec::new();
+    while !tlv_block.is_empty() {
+        if tlv_block.len() < 3 {
+            return Err(invalid("PROXY v2 TLV truncated"));
+        }
+        let len = usize::from(u16::from_be_bytes([tlv_block[1], tlv_block[2]]));
+        if tlv_block.len() < 3 + len {
+            return Err(invalid("PROXY v2 TLV truncated"));
+        }
+        tlvs.push(Tlv {
+            kind: tlv_block[0],
+            value: Bytes::copy_from_slice(&tlv_block[3..3 + len]),
+        });
+        tlv_block = &tlv_block[3 + len..];
+    }
+
+    let header = ProxyHeader {
+        version: 2,
+        addrs,
+        tlvs,
+    };
+    Ok(Some((header, total)))
+}
+
+fn ipv4(b: &[u8]) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+}
+
+fn ipv6(b: &[u8]) -> IpAddr {
+    let octets: [u8; 16] = b.try_into().expect("16-byte address");
+    IpAddr::V6(Ipv6Addr::from(octets))
+}
+
+fn port(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Connection IO that first replays bytes read past the PROXY header. A plain
+/// passthrough once they are consumed, or on listeners without the PROXY protocol.
+pub(crate) struct Rewind<I> {
+    prefix: Bytes,
+    io: I,
+}
+
+impl<I> Rewind<I> {
+    pub(crate) fn new(io: I) -> Self {
+        Rewind {
+            prefix: Bytes::new(),
+            io,
+        }
+    }
+
+    pub(crate) fn rewind(&mut self, prefix: Bytes) {
+        self.prefix = prefix;
+    }
+}
+
+impl<I: hyper::rt::Read + Unpin> hyper::rt::Read for Rewind<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.prefix.is_empty() {
+            return Pin::new(&mut this.io).poll_read(cx, buf);
+        }
+        let n = this.prefix.len().min(buf.remaining());
+        buf.put_slice(&this.prefix[..n]);
+        this.prefix.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: hyper::rt::Write + Unpin> hyper::rt::Write for Rewind<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write_vectored(cx, bufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2(command: u8, family: u8, addrs: &[u8], tlvs: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut payload = addrs.to_vec();
+        for (kind, value) in tlvs {
+            payload.push(*kind);
+            payload.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            payload.extend_from_slice(value);
+        }
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(&payload);
+        header
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn parsed(buf: &[u8]) -> (ProxyHeader, usize) {
+        parse(buf).unwrap().expect("incomplete header")
+    }
+
+    fn rejected(buf: &[u8]) -> String {
+        let e = parse(buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        e.to_string()
+    }
+
+    #[test]
+    fn v1_tcp4() {
+        let buf = b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nPRI";
+        let (header, len) = parsed(buf);
+        assert_eq!(len, buf.len() - 3);
+        assert_eq!(header.version(), 1);
+        assert_eq!(header.source(), Some(addr("192.0.2.1:56324")));
+        assert_eq!(header.destination(), Some(addr("198.51.100.2:443")));
+        assert!(header.tlvs().is_empty());
+    }
+
+    #[test]
+    fn v1_tcp6() {
+        let (header, _) = parsed(b"PROXY TCP6 2001:db8::1 ::1 56324 443\r\n");
+        assert_eq!(header.source(), Some(addr("[2001:db8::1]:56324")));
+        assert_eq!(header.destination(), Some(addr("[::1]:443")));
+    }
+
+    #[test]
+    fn v1_unknown() {
+        let buf = b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n";
+        let (header, len) = parsed(buf);
+        assert_eq!(len, buf.len());
+        assert_eq!(header.source(), None);
+    }
+
+    #[test]
+    fn v1_invalid() {
+        for buf in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 ::1 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 70000\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 1 2 3\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.2 1 2\r\n",
+        ] {
+            rejected(buf);
+        }
+    }
+
+    #[test]
+    fn v1_oversize() {
+        let mut buf = b"PROXY UNKNOWN ".to_vec();
+        buf.resize(V1_MAX_LEN, b'a');
+        assert_eq!(rejected(&buf), "PROXY v1 header too long");
+        buf.truncate(V1_MAX_LEN - 1);
+        buf.extend_from_slice(b"\r\n");
+        assert_eq!(rejected(&buf), "PROXY v1 header too long");
+    }
+
+    #[test]
+    fn v2_proxy_ipv4_with_tlvs() {
+        let addrs = [192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb];
+        let mut buf = v2(
+            0x1,
+            0x11,
+            &addrs,
+            &[(Tlv::AUTHORITY, b"example.com"), (Tlv::NOOP, b"")],
+        );
+        let len = buf.len();
+        buf.extend_from_slice(b"PRI");
+        let (header, parsed_len) = parsed(&buf);
+        assert_eq!(parsed_len, len);
+        assert_eq!(header.version(), 2);
+        assert_eq!(header.source(), Some(addr("192.0.2.1:56324")));
+        assert_eq!(header.destination(), Some(addr("198.51.100.2:443")));
+        assert_eq!(header.tlvs().len(), 2);
+        assert_eq!(header.tlv(Tlv::AUTHORITY), Some(&b"example.com"[..]));
+        assert_eq!(header.tlv(Tlv::NOOP), Some(&b""[..]));
+        assert_eq!(header.tlv(Tlv::ALPN), None);
+    }
+
+    #[test]
+    fn v2_proxy_ipv6() {
+        let mut addrs = Vec::new();
+        addrs.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        addrs.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        addrs.extend_from_slice(&[0xdc, 0x04, 0x01, 0xbb]);
+        let (header, _) = parsed(&v2(0x1, 0x21, &addrs, &[]));
+        assert_eq!(header.source(), Some(addr("[2001:db8::1]:56324")));
+        assert_eq!(header.destination(), Some(addr("[::1]:443")));
+    }
+
+    #[test]
+    fn v2_local_keeps_tlvs() {
+        let (header, _) = parsed(&v2(0x0, 0x00, &[], &[(Tlv::UNIQUE_ID, b"id-1")]));
+        assert_eq!(header.source(), None);
+        assert_eq!(header.tlv(Tlv::UNIQUE_ID), Some(&b"id-1"[..]));
+    }
+
+    #[test]
+    fn truncated_headers_need_more_bytes() {
+        let v1 = b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\n";
+        let v2 = v2(
+            0x1,
+            0x11,
+            &[192, 0, 2, 1, 198, 51, 100, 2, 0, 1, 0, 2],
+            &[(Tlv::ALPN, b"h2")],
+        );
+        for buf in [&v1[..], &v2[..]] {
+            for end in 0..buf.len() {
+                assert!(parse(&buf[..end]).unwrap().is_none(), "{end}");
+            }
+            assert!(parse(buf).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn v2_invalid() {
+        // The address block is shorter than its family requires.
+        assert_eq!(
+            rejected(&v2(0x1, 0x11, &[192, 0, 2, 1], &[])),
+            "PROXY v2 address block truncated"
+        );
+        // A TLV claims more bytes than the header holds.
+        let mut buf = v2(0x0, 0x00, &[], &[(Tlv::ALPN, b"h2")]);
+        buf[V2_HEADER_LEN + 2] = 3;
+        assert_eq!(rejected(&buf), "PROXY v2 TLV truncated");
+        let mut buf = v2(0x0, 0x00, &[], &[]);
+        buf[12] = 0x10;
+        assert_eq!(rejected(&buf), "unsupported PROXY protocol version");
+        assert_eq!(
+            rejected(&v2(0x2, 0x00, &[], &[])),
+            "unknown PROXY v2 command"
+        );
+        assert_eq!(
+            rejected(&v2(0x1, 0x41, &[], &[])),
+            "unknown PROXY v2 address family"
+        );
+    }
+
+    #[test]
+    fn bad_signature() {
+        assert_eq!(rejected(b"PRI * HTTP/2.0\r\n"), "no PROXY protocol header");
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf[11] = b'X';
+        assert_eq!(rejected(&buf), "no PROXY protocol header");
+        assert_eq!(rejected(b"PROXY\t"), "no PROXY protocol header");
+    }
+}
@@ -1,45 +1,167 @@
 use std::future::Future;
 use std::io;
+use std::marker::PhantomData;
 use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
 
+use crate::server::cpu::CorePlacement;
 use crate::server::error::GmfError;
+use crate::server::listener::{PeerCredentials, UnixAddr};
+use crate::server::socket::SocketOptions;
 
 /// Core trait for a thread-per-core async runtime.
 pub trait Runtime: Sized + 'static {
     type TcpListener: RuntimeTcpListener;
+    type UnixListener: RuntimeUnixListener;
     type Executor: RuntimeExecutor + Clone;
     type Semaphore: RuntimeSemaphore;
+    type Timer: RuntimeTimer;
 
-    /// Spawn one event loop per core, each running the provided closure.
-    /// The closure receives the core index (0-based).
-    fn run_multi_core<F, Fut>(cores: usize, f: F) -> Result<(), GmfError>
+    /// Whether every core can accept from one shared Unix listener
+    /// ([`UnixStrategy::Shared`]). Unix listeners default to `Shared` when it can and
+    /// to [`UnixStrategy::PerCore`] otherwise.
+    ///
+    /// [`UnixStrategy::Shared`]: crate::server::listener::UnixStrategy::Shared
+    /// [`UnixStrategy::PerCore`]: crate::server::listener::UnixStrategy::PerCore
+    const SHARED_UNIX_LISTENER: bool = true;
+
+    /// Spawn one event loop per core of `placement`, each pinned to its CPU (if any) and
+    /// running the provided closure, and return without waiting for them. The closure
+    /// receives the core index (0-based).
+    ///
+    /// Handle `i` belongs to core `i`; joining it yields that core's result, which is
+    /// [`GmfError::Affinity`] if the thread could not be pinned.
+    fn spawn_multi_core<F, Fut>(
+        placement: &CorePlacement,
+        f: F,
+    ) -> Result<Vec<CoreJoinHandle>, GmfError>
     where
         F: Fn(usize) -> Fut + Send + Clone + 'static,
         Fut: Future<Output = Result<(), GmfError>> + 'static;
+
+    /// Spawn one event loop per core and block until all of them exit.
+    fn run_multi_core<F, Fut>(placement: &CorePlacement, f: F) -> Result<(), GmfError>
+    where
+        F: Fn(usize) -> Fut + Send + Clone + 'static,
+        Fut: Future<Output = Result<(), GmfError>> + 'static,
+    {
+        for handle in Self::spawn_multi_core(placement, f)? {
+            join_core(handle)?;
+        }
+        Ok(())
+    }
 }
 
-/// Async TCP listener bound to a socket address.
-pub trait RuntimeTcpListener: Sized {
-    type Stream: RuntimeTcpStream;
+/// Join handle for a single core's event loop thread.
+pub type CoreJoinHandle = std::thread::JoinHandle<Result<(), GmfError>>;
+
+/// Join a core thread, turning a panic into a `GmfError`.
+pub(crate) fn join_core(handle: CoreJoinHandle) -> Result<(), GmfError> {
+    handle
+        .join()
+        .map_err(|_| GmfError::Io(io::Error::other("thread panicked")))?
+}
+
+/// Async TCP listener bound to a socket address. The fd is the listening socket, which
+/// [`ServerHandle::upgrade`](crate::server::handle::ServerHandle::upgrade) hands over.
+pub trait RuntimeTcpListener: Sized + AsRawFd {
+    type Stream: RuntimeStream + AsRawFd;
+
+    /// Bind a new listener with the given socket options.
+    fn bind(addr: SocketAddr, options: &SocketOptions) -> io::Result<Self> {
+        Self::from_std(options.bind_tcp(addr)?)
+    }
+
+    /// Adopt an already bound and listening std listener.
+    fn from_std(listener: std::net::TcpListener) -> io::Result<Self>;
 
-    fn bind(addr: SocketAddr) -> impl Future<Output = io::Result<Self>>;
     fn accept(&self) -> impl Future<Output = io::Result<(Self::Stream, SocketAddr)>>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+/// Async Unix domain socket listener.
+pub trait RuntimeUnixListener: Sized {
+    type Stream: RuntimeStream;
+
+    /// Bind a new listener to a filesystem path or abstract name.
+    fn bind(addr: &UnixAddr, options: &SocketOptions) -> io::Result<Self> {
+        Self::from_std(addr.bind_std(options)?)
+    }
+
+    /// Adopt an already bound, non-blocking std listener (e.g. one shared by all cores).
+    fn from_std(listener: StdUnixListener) -> io::Result<Self>;
+
+    /// The listening socket, for handing it over to a new process, or `None` if the
+    /// runtime does not expose it.
+    fn raw_fd(&self) -> Option<RawFd>;
+
+    /// Accept a connection along with the client's `SO_PEERCRED` credentials, when the
+    /// runtime can read them.
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Stream, Option<PeerCredentials>)>>;
 }
 
-/// A TCP stream that can be converted into a hyper-compatible IO type.
-pub trait RuntimeTcpStream: Sized + 'static {
+/// An accepted connection, over TCP or a Unix socket, that can be converted into a
+/// hyper-compatible IO type.
+pub trait RuntimeStream: Sized + 'static {
     type HyperIo: hyper::rt::Read + hyper::rt::Write + Unpin + 'static;
 
     fn into_hyper_io(self) -> Self::HyperIo;
 }
 
+/// The former name of [`RuntimeStream`], from before accepted streams could be Unix
+/// sockets.
+#[deprecated(note = "renamed to `RuntimeStream`")]
+pub use self::RuntimeStream as RuntimeTcpStream;
+
 /// Executor for spawning futures within the current thread's event loop.
 pub trait RuntimeExecutor: Clone + Default + 'static {
     fn spawn<F: Future<Output = ()> + 'static>(&self, fut: F);
 }
 
-/// A single-threaded semaphore for connection limiting.
-pub trait RuntimeSemaphore: Sized {
+/// Timer driven by the current thread's event loop.
+pub trait RuntimeTimer: Clone + Default + 'static {
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
+
+    /// A sleep in the form hyper's timer expects (`Send + Sync`), used for HTTP/2
+    /// keepalive pings. hyper only polls it on the connection's own core.
+    fn hyper_sleep(&self, duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>>;
+}
+
+/// `hyper::rt::Timer` backed by a runtime's timer.
+pub(crate) struct HyperTimer<T>(PhantomData<fn() -> T>);
+
+impl<T> HyperTimer<T> {
+    pub(crate) fn new() -> Self {
+        HyperTimer(PhantomData)
+    }
+}
+
+impl<T: RuntimeTimer> hyper::rt::Timer for HyperTimer<T> {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        T::default().hyper_sleep(duration)
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        T::default().hyper_sleep(deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// A single-threaded semaphore for connection limiting. Clones share the same permits.
+pub trait RuntimeSemaphore: Clone + Sized + 'static {
+    /// RAII guard for an acquired permit. Dropping it returns the permit to the semaphore.
+    type Permit: 'static;
+
     fn new(permits: usize) -> Self;
-    fn try_acquire(&self) -> bool;
+
+    /// Take a permit if one is free and nobody is waiting for one.
+    fn try_acquire(&self) -> Option<Self::Permit>;
+
+    /// Wait for a permit. Waiters are served in the order they started waiting.
+    fn acquire(&self) -> impl Future<Output = Self::Permit>;
+
+    /// Number of permits currently available.
+    fn available(&self) -> usize;
 }
@@ -0,0 +1,267 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// Single-threaded counting semaphore with a FIFO queue of waiters, shared by the
+/// runtimes' `RuntimeSemaphore` implementations.
+///
+/// Waiters are served in arrival order, and `try_acquire` does not overtake them.
+#[derive(Clone)]
+pub(crate) struct LocalSemaphore(Rc<State>);
+
+struct State {
+    permits: Cell<usize>,
+    waiters: RefCell<VecDeque<(u64, Waker)>>,
+    next_ticket: Cell<u64>,
+}
+
+impl State {
+    fn wake_front(&self) {
+        if self.permits.get() > 0 {
+            if let Some((_, waker)) = self.waiters.borrow().front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl LocalSemaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        LocalSemaphore(Rc::new(State {
+            permits: Cell::new(permits),
+            waiters: RefCell::new(VecDeque::new()),
+            next_ticket: Cell::new(0),
+        }))
+    }
+
+    pub(crate) fn try_acquire(&self) -> Option<LocalPermit> {
+        if self.0.permits.get() > 0 && self.0.waiters.borrow().is_empty() {
+            Some(self.take())
+        } else {
+            None
+        }
+    }
+
+    /// Wait for a permit.
+    pub(crate) fn acquire(&self) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            ticket: None,
+        }
+    }
+
+    pub(crate) fn available(&self) -> usize {
+        self.0.permits.get()
+    }
+
+    pub(crate) fn has_waiters(&self) -> bool {
+        !self.0.waiters.borrow().is_empty()
+    }
+
+    /// Add `n` permits, waking the first waiter.
+    pub(crate) fn add_permits(&self, n: usize) {
+        self.0.permits.set(self.0.permits.get() + n);
+        self.0.wake_front();
+    }
+
+    /// Remove up to `max` of the currently available permits, returning how many were
+    /// removed.
+    pub(crate) fn forget(&self, max: usize) -> usize {
+        let n = self.0.permits.get().min(max);
+        self.0.permits.set(self.0.permits.get() - n);
+        n
+    }
+
+    fn take(&self) -> LocalPermit {
+        self.0.permits.set(self.0.permits.get() - 1);
+        LocalPermit(self.0.clone())
+    }
+}
+
+/// Future returned by [`LocalSemaphore::acquire`]. Dropping it leaves the queue.
+pub(crate) struct Acquire<'a> {
+    semaphore: &'a LocalSemaphore,
+    ticket: Option<u64>,
+}
+
+impl Future for Acquire<'_> {
+    type Output = LocalPermit;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<LocalPermit> {
+        let this = self.get_mut();
+        let state = &this.semaphore.0;
+
+        if state.permits.get() > 0 {
+            let mut waiters = state.waiters.borrow_mut();
+            let first = match this.ticket {
+                None => waiters.is_empty(),
+                Some(ticket) => waiters.front().is_some_and(|(front, _)| *front == ticket),
+            };
+            if first {
+                if this.ticket.take().is_some() {
+                    waiters.pop_front();
+                }
+                drop(waiters);
+                let permit = this.semaphore.take();
+                state.wake_front();
+                return Poll::Ready(permit);
+            }
+        }
+
+        let mut waiters = state.waiters.borrow_mut();
+        match this.ticket {
+            Some(ticket) => {
+                if let Some((_, waker)) = waiters.iter_mut().find(|(t, _)| *t == ticket) {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let ticket = state.next_ticket.get();
+                state.next_ticket.set(ticket + 1);
+                waiters.push_back((ticket, cx.waker().clone()));
+                this.ticket = Some(ticket);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+        let state = &self.semaphore.0;
+        let mut waiters = state.waiters.borrow_mut();
+        if let Some(index) = waiters.iter().position(|(t, _)| *t == ticket) {
+            waiters.remove(index);
+            drop(waiters);
+            if index == 0 {
+                // A permit this waiter was woken for passes to the next one.
+                state.wake_front();
+            }
+        }
+    }
+}
+
+/// A permit from a [`LocalSemaphore`]; returned, and the next waiter woken, on drop.
+pub(crate) struct LocalPermit(Rc<State>);
+
+impl Drop for LocalPermit {
+    fn drop(&mut self) {
+        self.0.permits.set(self.0.permits.get() + 1);
+        self.0.wake_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn waker() -> (Arc<CountingWaker>, Waker) {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        (counter.clone(), Waker::from(counter))
+    }
+
+    fn poll(acquire: &mut Pin<Box<Acquire<'_>>>, waker: &Waker) -> Poll<LocalPermit> {
+        acquire.as_mut().poll(&mut Context::from_waker(waker))
+    }
+
+    fn wakes(counter: &CountingWaker) -> usize {
+        counter.0.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn dropped_permit_is_returned() {
+        let semaphore = LocalSemaphore::new(2);
+        let first = semaphore.try_acquire().unwrap();
+        let second = semaphore.try_acquire().unwrap();
+        assert_eq!(semaphore.available(), 0);
+        assert!(semaphore.try_acquire().is_none());
+
+        drop(first);
+        assert_eq!(semaphore.available(), 1);
+        let third = semaphore.try_acquire().unwrap();
+        drop(second);
+        drop(third);
+        assert_eq!(semaphore.available(), 2);
+    }
+
+    #[test]
+    fn waiters_are_woken_in_arrival_order() {
+        let semaphore = LocalSemaphore::new(1);
+        let held = semaphore.try_acquire().unwrap();
+        let (first_count, first_waker) = waker();
+        let (second_count, second_waker) = waker();
+        let mut first = Box::pin(semaphore.acquire());
+        let mut second = Box::pin(semaphore.acquire());
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+        assert!(semaphore.has_waiters());
+
+        drop(held);
+        assert_eq!(wakes(&first_count), 1);
+        assert_eq!(wakes(&second_count), 0);
+        // The second waiter may not overtake the first, nor may `try_acquire`.
+        assert!(poll(&mut second, &second_waker).is_pending());
+        assert!(semaphore.try_acquire().is_none());
+
+        let Poll::Ready(permit) = poll(&mut first, &first_waker) else {
+            panic!("first waiter was not granted the permit");
+        };
+        drop(permit);
+        assert_eq!(wakes(&second_count), 1);
+        assert!(poll(&mut second, &second_waker).is_ready());
+        assert!(!semaphore.has_waiters());
+    }
+
+    #[test]
+    fn dropped_waiter_passes_its_wake_up_on() {
+        let semaphore = LocalSemaphore::new(1);
+        let held = semaphore.try_acquire().unwrap();
+        let (_, first_waker) = waker();
+        let (second_count, second_waker) = waker();
+        let mut first = Box::pin(semaphore.acquire());
+        let mut second = Box::pin(semaphore.acquire());
+        assert!(poll(&mut first, &first_waker).is_pending());
+        assert!(poll(&mut second, &second_waker).is_pending());
+
+        drop(held);
+        drop(first);
+        assert_eq!(wakes(&second_count), 1);
+        assert!(poll(&mut second, &second_waker).is_ready());
+    }
+
+    #[test]
+    fn added_and_forgotten_permits() {
+        let semaphore = LocalSemaphore::new(3);
+        assert_eq!(semaphore.forget(2), 2);
+        assert_eq!(semaphore.forget(5), 1);
+        assert_eq!(semaphore.available(), 0);
+
+        let (count, waker) = waker();
+        let mut acquire = Box::pin(semaphore.acquire());
+        assert!(poll(&mut acquire, &waker).is_pending());
+        semaphore.add_permits(2);
+        assert_eq!(wakes(&count), 1);
+        let Poll::Ready(_permit) = poll(&mut acquire, &waker) else {
+            panic!("waiter was not granted an added permit");
+        };
+        assert_eq!(semaphore.available(), 1);
+    }
+}
@@ -0,0 +1,388 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// One-shot signal that can be triggered from any thread and awaited on any runtime.
+///
+/// Used as the process-wide shutdown signal shared by every core: triggering it wakes
+/// each core's accept loop immediately, even if the core is idle and parked inside
+/// `accept()`. Only the accept loops wait on it directly; connections are notified
+/// through their core's [`CoreDrain`] so the shared lock is touched at most once per
+/// core.
+#[derive(Clone, Default)]
+pub(crate) struct Signal(Arc<SignalInner>);
+
+#[derive(Default)]
+struct SignalInner {
+    triggered: AtomicBool,
+    waiters: Mutex<Waiters>,
+}
+
+/// Registered wakers; slots of dropped waits are reused so a long-lived signal does
+/// not grow with every waiter.
+#[derive(Default)]
+struct Waiters {
+    slots: Vec<Option<Waker>>,
+    free: Vec<usize>,
+}
+
+impl Signal {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn trigger(&self) {
+        if self.0.triggered.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let waiters = std::mem::take(&mut *self.0.waiters.lock().unwrap());
+        for waker in waiters.slots.into_iter().flatten() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn is_triggered(&self) -> bool {
+        self.0.triggered.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once `trigger` has been called.
+    pub(crate) fn wait(&self) -> SignalWait {
+        SignalWait {
+            signal: self.clone(),
+            slot: None,
+            waker: None,
+        }
+    }
+}
+
+pub(crate) struct SignalWait {
+    signal: Signal,
+    slot: Option<usize>,
+    waker: Option<Waker>,
+}
+
+impl Future for SignalWait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.signal.is_triggered() {
+            return Poll::Ready(());
+        }
+        // Skip the lock when the registered waker is still current.
+        if matches!(&this.waker, Some(w) if w.will_wake(cx.waker())) {
+            return Poll::Pending;
+        }
+
+        let mut waiters = this.signal.0.waiters.lock().unwrap();
+        // Re-check under the lock so a concurrent `trigger` cannot be missed.
+        if this.signal.is_triggered() {
+            return Poll::Ready(());
+        }
+        let waker = cx.waker().clone();
+        match this.slot {
+            Some(slot) => waiters.slots[slot] = Some(waker.clone()),
+            None => {
+                let slot = match waiters.free.pop() {
+                    Some(slot) => slot,
+                    None => {
+                        waiters.slots.push(None);
+                        waiters.slots.len() - 1
+                    }
+                };
+                waiters.slots[slot] = Some(waker.clone());
+                this.slot = Some(slot);
+            }
+        }
+        this.waker = Some(waker);
+        Poll::Pending
+    }
+}
+
+impl Drop for SignalWait {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            if let Ok(mut waiters) = self.signal.0.waiters.lock() {
+                // Once triggered the waiter list is discarded wholesale.
+                if !self.signal.is_triggered() {
+                    waiters.slots[slot] = None;
+                    waiters.free.push(slot);
+                }
+            }
+        }
+    }
+}
+
+/// Per-core drain state: tells live connections to shut down gracefully, then to close
+/// once the drain timeout elapses, and tracks how many of them are still running.
+///
+/// Single-threaded (`Rc` + `Cell`), like the connection semaphore.
+#[derive(Clone, Default)]
+pub(crate) struct CoreDrain(Rc<CoreDrainInner>);
+
+#[derive(Default)]
+struct CoreDrainInner {
+    draining: Stage,
+    closing: Stage,
+    active: Cell<usize>,
+    idle_waker: RefCell<Option<Waker>>,
+}
+
+/// One step of a core's drain and the connections waiting for it. Slots of dropped
+/// waits are reused, as in [`Signal`].
+#[derive(Default)]
+struct Stage {
+    reached: Cell<bool>,
+    waiters: RefCell<Vec<Option<Waker>>>,
+    free: RefCell<Vec<usize>>,
+}
+
+impl Stage {
+    fn reach(&self) {
+        if self.reached.replace(true) {
+            return;
+        }
+        let waiters = std::mem::take(&mut *self.waiters.borrow_mut());
+        self.free.borrow_mut().clear();
+        for waker in waiters.into_iter().flatten() {
+            waker.wake();
+        }
+    }
+}
+
+impl CoreDrain {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every registered connection to start a graceful shutdown.
+    pub(crate) fn start(&self) {
+        self.0.draining.reach();
+    }
+
+    /// Tell the connections still running to close now.
+    pub(crate) fn close(&self) {
+        self.0.closing.reach();
+    }
+
+    pub(crate) fn active(&self) -> usize {
+        self.0.active.get()
+    }
+
+    /// Register a live connection. The returned guard must be held for as long as the
+    /// connection is being served.
+    pub(crate) fn track(&self) -> ConnectionGuard {
+        self.0.active.set(self.0.active.get() + 1);
+        ConnectionGuard(self.clone())
+    }
+
+    /// Resolves once `start` has been called.
+    pub(crate) fn draining(&self) -> StageWait {
+        StageWait::new(self, |inner| &inner.draining)
+    }
+
+    /// Resolves once `close` has been called.
+    pub(crate) fn closing(&self) -> StageWait {
+        StageWait::new(self, |inner| &inner.closing)
+    }
+
+    /// Resolves once no tracked connections remain.
+    pub(crate) fn idle(&self) -> Idle {
+        Idle(self.clone())
+    }
+}
+
+pub(crate) struct ConnectionGuard(CoreDrain);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let inner = &self.0 .0;
+        let active = inner.active.get() - 1;
+        inner.active.set(active);
+        if active == 0 {
+            if let Some(waker) = inner.idle_waker.borrow_mut().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub(crate) struct StageWait {
+    drain: CoreDrain,
+    stage: fn(&CoreDrainInner) -> &Stage,
+    slot: Option<usize>,
+}
+
+impl StageWait {
+    fn new(drain: &CoreDrain, stage: fn(&CoreDrainInner) -> &Stage) -> Self {
+        Self {
+            drain: drain.clone(),
+            stage,
+            slot: None,
+        }
+    }
+}
+
+impl Future for StageWait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let stage = (this.stage)(&this.drain.0);
+        if stage.reached.get() {
+            return Poll::Ready(());
+        }
+
+        let mut waiters = stage.waiters.borrow_mut();
+        match this.slot {
+            Some(slot) => match &mut waiters[slot] {
+                Some(w) if w.will_wake(cx.waker()) => {}
+                entry => *entry = Some(cx.waker().clone()),
+            },
+            None => {
+                let slot = match stage.free.borrow_mut().pop() {
+                    Some(slot) => {
+                        waiters[slot] = Some(cx.waker().clone());
+                        slot
+                    }
+                    None => {
+                        waiters.push(Some(cx.waker().clone()));
+                        waiters.len() - 1
+                    }
+                };
+                this.slot = Some(slot);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for StageWait {
+    fn drop(&mut self) {
+        let stage = (self.stage)(&self.drain.0);
+        if let Some(slot) = self.slot {
+            // Once the stage is reached the waiter list is discarded wholesale.
+            if !stage.reached.get() {
+                stage.waiters.borrow_mut()[slot] = None;
+                stage.free.borrow_mut().push(slot);
+            }
+        }
+    }
+}
+
+pub(crate) struct Idle(CoreDrain);
+
+impl Future for Idle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let inner = &self.0 .0;
+        if inner.active.get() == 0 {
+            return Poll::Ready(());
+        }
+        *inner.idle_waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::task::Wake;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll<F: Future + Unpin>(future: &mut F, waker: &Arc<CountingWaker>) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(&Waker::from(waker.clone())))
+    }
+
+    #[test]
+    fn trigger_wakes_every_waiter() {
+        let signal = Signal::new();
+        let wakers: Vec<_> = (0..3).map(|_| Arc::new(CountingWaker::default())).collect();
+        let mut waits: Vec<_> = wakers.iter().map(|_| signal.wait()).collect();
+        for (wait, waker) in waits.iter_mut().zip(&wakers) {
+            assert!(poll(wait, waker).is_pending());
+        }
+        drop(waits.remove(1));
+
+        signal.trigger();
+        let woken: Vec<_> = wakers.iter().map(|w| w.0.load(Ordering::SeqCst)).collect();
+        assert_eq!(woken, [1, 0, 1]);
+        for wait in &mut waits {
+            assert!(poll(wait, &wakers[0]).is_ready());
+        }
+        assert!(poll(&mut signal.wait(), &wakers[0]).is_ready());
+    }
+
+    #[test]
+    fn dropped_waits_free_their_slot() {
+        let signal = Signal::new();
+        let waker = Arc::new(CountingWaker::default());
+        let mut held = signal.wait();
+        assert!(poll(&mut held, &waker).is_pending());
+        for _ in 0..100 {
+            let mut wait = signal.wait();
+            assert!(poll(&mut wait, &waker).is_pending());
+            // Polling again with the same waker keeps the slot.
+            assert!(poll(&mut wait, &waker).is_pending());
+        }
+        assert_eq!(signal.0.waiters.lock().unwrap().slots.len(), 2);
+
+        drop(held);
+        let waiters = signal.0.waiters.lock().unwrap();
+        assert!(waiters.slots.iter().all(Option::is_none));
+        assert_eq!(waiters.free.len(), 2);
+    }
+
+    #[test]
+    fn drain_wakes_connections_and_reports_idle() {
+        let drain = CoreDrain::new();
+        let waker = Arc::new(CountingWaker::default());
+        let guards = [drain.track(), drain.track()];
+        let mut draining = drain.draining();
+        let mut idle = drain.idle();
+        assert!(poll(&mut draining, &waker).is_pending());
+        assert!(poll(&mut idle, &waker).is_pending());
+        assert_eq!(drain.active(), 2);
+
+        drain.start();
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert!(poll(&mut draining, &waker).is_ready());
+        drop(guards);
+        assert_eq!(waker.0.load(Ordering::SeqCst), 2);
+        assert!(poll(&mut idle, &waker).is_ready());
+    }
+
+    #[test]
+    fn close_follows_drain() {
+        let drain = CoreDrain::new();
+        let waker = Arc::new(CountingWaker::default());
+        let mut closing = drain.closing();
+        assert!(poll(&mut closing, &waker).is_pending());
+        for _ in 0..10 {
+            assert!(poll(&mut drain.closing(), &waker).is_pending());
+        }
+        assert_eq!(drain.0.closing.waiters.borrow().len(), 2);
+
+        drain.start();
+        assert_eq!(waker.0.load(Ordering::SeqCst), 0);
+        assert!(poll(&mut closing, &waker).is_pending());
+        drain.close();
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert!(poll(&mut closing, &waker).is_ready());
+    }
+}
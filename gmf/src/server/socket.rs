@@ -0,0 +1,214 @@
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{BorrowedFd, RawFd};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+
+/// Options applied to every per-core listener socket and the connections it accepts.
+///
+/// Every runtime binds and accepts through the same code, so an option behaves the same
+/// on monoio, glommio and tokio. Options left unset keep the operating system's
+/// default. `SO_REUSEADDR` and `SO_REUSEPORT` are always set, since every core binds
+/// its own listener to the same address.
+#[derive(Debug, Clone)]
+pub struct SocketOptions {
+    pub(crate) backlog: i32,
+    pub(crate) nodelay: Option<bool>,
+    pub(crate) keepalive_idle: Option<Duration>,
+    pub(crate) keepalive_interval: Option<Duration>,
+    pub(crate) keepalive_retries: Option<u32>,
+    pub(crate) recv_buffer_size: Option<usize>,
+    pub(crate) send_buffer_size: Option<usize>,
+    pub(crate) defer_accept: Option<Duration>,
+    pub(crate) fastopen: Option<u32>,
+    pub(crate) only_v6: Option<bool>,
+    pub(crate) user_timeout: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            backlog: 1024,
+            nodelay: None,
+            keepalive_idle: None,
+            keepalive_interval: None,
+            keepalive_retries: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            defer_accept: None,
+            fastopen: None,
+            only_v6: None,
+            user_timeout: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Length of each core's accept queue (default 1024, capped by `somaxconn`).
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog.min(i32::MAX as u32) as i32;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on accepted connections.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Enable `SO_KEEPALIVE` on accepted connections and send the first probe after
+    /// the connection has been idle for `idle`.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive_idle = Some(idle);
+        self
+    }
+
+    /// Time between keepalive probes (`TCP_KEEPINTVL`). Enables `SO_KEEPALIVE`.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Unanswered probes before the connection is dropped (`TCP_KEEPCNT`). Enables
+    /// `SO_KEEPALIVE`.
+    pub fn keepalive_retries(mut self, retries: u32) -> Self {
+        self.keepalive_retries = Some(retries);
+        self
+    }
+
+    /// `SO_RCVBUF`. Set on the listener so accepted connections inherit it before the
+    /// handshake, when the TCP window scale is negotiated.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// `SO_SNDBUF`, set on the listener and inherited by accepted connections.
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// `TCP_DEFER_ACCEPT`: only wake the accept loop once the client has sent data,
+    /// waiting up to `timeout` for it. Linux only.
+    pub fn defer_accept(mut self, timeout: Duration) -> Self {
+        self.defer_accept = Some(timeout);
+        self
+    }
+
+    /// `TCP_FASTOPEN` with a queue of up to `queue` pending fast-open requests. Linux
+    /// only.
+    pub fn fastopen(mut self, queue: u32) -> Self {
+        self.fastopen = Some(queue);
+        self
+    }
+
+    /// `IPV6_V6ONLY` for IPv6 listeners. Set it to `true` to bind `[::]` and `0.0.0.0`
+    /// on the same port as two separate listeners.
+    pub fn only_v6(mut self, only_v6: bool) -> Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// `TCP_USER_TIMEOUT` on accepted connections: drop a connection whose sent data
+    /// stays unacknowledged for `timeout`. Linux only.
+    pub fn user_timeout(mut self, timeout: Duration) -> Self {
+        self.user_timeout = Some(timeout);
+        self
+    }
+
+    /// Create, configure, bind and listen a TCP socket. The listener is left in
+    /// blocking mode; each runtime switches it to what its driver expects.
+    pub(crate) fn bind_tcp(&self, addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_reuse_port(true)?;
+        if let (Some(only_v6), SocketAddr::V6(_)) = (self.only_v6, addr) {
+            socket.set_only_v6(only_v6)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+
+            if let Some(timeout) = self.defer_accept {
+                let secs = timeout.as_secs().min(i32::MAX as u64) as libc::c_int;
+                set_int(socket.as_raw_fd(), libc::TCP_DEFER_ACCEPT, secs)?;
+            }
+            if let Some(queue) = self.fastopen {
+                let queue = queue.min(i32::MAX as u32) as libc::c_int;
+                set_int(socket.as_raw_fd(), libc::TCP_FASTOPEN, queue)?;
+            }
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog)?;
+        Ok(socket.into())
+    }
+
+    /// Apply the per-connection options to a freshly accepted TCP stream.
+    pub(crate) fn apply_accepted(&self, fd: RawFd) -> io::Result<()> {
+        // SAFETY: the caller owns the stream for the duration of this call.
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        let socket = SockRef::from(&fd);
+
+        if let Some(nodelay) = self.nodelay {
+            socket.set_nodelay(nodelay)?;
+        }
+        if let Some(keepalive) = self.tcp_keepalive() {
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(timeout) = self.user_timeout {
+            socket.set_tcp_user_timeout(Some(timeout))?;
+        }
+        Ok(())
+    }
+
+    fn tcp_keepalive(&self) -> Option<TcpKeepalive> {
+        if self.keepalive_idle.is_none()
+            && self.keepalive_interval.is_none()
+            && self.keepalive_retries.is_none()
+        {
+            return None;
+        }
+
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(idle) = self.keepalive_idle {
+            keepalive = keepalive.with_time(idle);
+        }
+        if let Some(interval) = self.keepalive_interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+        if let Some(retries) = self.keepalive_retries {
+            keepalive = keepalive.with_retries(retries);
+        }
+        Some(keepalive)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_int(fd: RawFd, option: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,253 @@
+use std::env;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{SocketAddr as StdUnixAddr, UnixDatagram};
+use std::pin::pin;
+
+use crate::server::gmf_server::block_on_simple;
+use crate::server::shutdown::Signal;
+use crate::server::util::{select, Either};
+
+/// The first fd systemd passes (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed in by systemd socket activation.
+#[derive(Debug)]
+pub struct ListenFd {
+    pub fd: OwnedFd,
+    /// The socket's `FileDescriptorName=` from the unit, if set.
+    pub name: Option<String>,
+}
+
+/// Take the sockets systemd passed to this process (`LISTEN_FDS`), in the order the
+/// socket unit lists them.
+///
+/// Returns an empty list when the process was not socket-activated. The `LISTEN_*`
+/// variables are removed so that the fds are taken once and child processes do not see
+/// them; the fds are marked close-on-exec.
+///
+/// Changing the environment is not thread-safe, so call this at the start of `main`,
+/// before any other thread is spawned (a runtime, a tracing writer, or a server).
+///
+/// Build listeners from them with [`ListenerConfig::from_fd`] (every core accepts from
+/// the one socket) or [`ListenerConfig::from_fds`] (one socket per core, e.g. a unit
+/// with `ReusePort=yes` and one `ListenStream=` per core):
+///
+/// ```ignore
+/// let fds = systemd::listen_fds()?;
+/// GmfServer::builder()
+///     .listener(ListenerConfig::from_fds(fds.into_iter().map(|fd| fd.fd).collect())?)
+///     .systemd_notify()
+///     .build()
+///     .serve(service)?;
+/// ```
+///
+/// [`ListenerConfig::from_fd`]: crate::server::config::ListenerConfig::from_fd
+/// [`ListenerConfig::from_fds`]: crate::server::config::ListenerConfig::from_fds
+pub fn listen_fds() -> io::Result<Vec<ListenFd>> {
+    take_listen_fds(LISTEN_FDS_START)
+}
+
+/// [`listen_fds`], with the passed fds numbered from `start`.
+fn take_listen_fds(start: RawFd) -> io::Result<Vec<ListenFd>> {
+    let for_us = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    let count = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse::<RawFd>().ok());
+    let names = env::var("LISTEN_FDNAMES").ok();
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+    let (true, Some(count)) = (for_us, count) else {
+        return Ok(Vec::new());
+    };
+
+    let mut names = names.as_deref().map(|names| names.split(':'));
+    (start..start + count)
+        .map(|fd| {
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: systemd passed this fd to this process (LISTEN_PID matched), and
+            // removing LISTEN_FDS above ensures it is only taken here.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let name = names
+                .as_mut()
+                .and_then(Iterator::next)
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned);
+            Ok(ListenFd { fd, name })
+        })
+        .collect()
+}
+
+/// Send a state update (e.g. `"READY=1"`) to the service manager over `NOTIFY_SOCKET`.
+///
+/// Returns `false` without doing anything when `NOTIFY_SOCKET` is not set, i.e. when
+/// not running under systemd with `Type=notify`.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let addr = match path.as_encoded_bytes() {
+        #[cfg(target_os = "linux")]
+        [b'@', name @ ..] => {
+            use std::os::linux::net::SocketAddrExt;
+            StdUnixAddr::from_abstract_name(name)?
+        }
+        _ => StdUnixAddr::from_pathname(&path)?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(true)
+}
+
+/// Tell the service manager `READY=1` once every core is accepting, and `STOPPING=1`
+/// when shutdown starts, from a thread of its own so neither waits on a core.
+pub(crate) fn spawn_notifier(ready: Signal, shutdown: Signal) {
+    let send = |state: &str| {
+        if let Err(e) = notify(state) {
+            tracing::warn!(state = state, error = %e, "sd_notify failed");
+        }
+    };
+    std::thread::spawn(move || {
+        block_on_simple(async {
+            let mut ready = pin!(ready.wait());
+            let mut stop = pin!(shutdown.wait());
+            if let Either::Left(()) = select(ready.as_mut(), stop.as_mut()).await {
+                send("READY=1");
+                stop.await;
+            }
+            send("STOPPING=1");
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::AsRawFd;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Serializes the tests, which share the process environment.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Where the passed fds start in these tests, clear of the fds the test harness
+    /// may hold at `SD_LISTEN_FDS_START`.
+    const START: RawFd = 900;
+
+    /// Place `count` sockets at consecutive fds from [`START`], as systemd would.
+    fn pass_fds(count: RawFd) {
+        for fd in START..START + count {
+            let socket = UnixDatagram::unbound().unwrap();
+            assert_eq!(unsafe { libc::dup2(socket.as_raw_fd(), fd) }, fd);
+        }
+    }
+
+    fn set_listen_env(pid: u32, count: RawFd, names: Option<&str>) {
+        env::set_var("LISTEN_PID", pid.to_string());
+        env::set_var("LISTEN_FDS", count.to_string());
+        match names {
+            Some(names) => env::set_var("LISTEN_FDNAMES", names),
+            None => env::remove_var("LISTEN_FDNAMES"),
+        }
+    }
+
+    fn assert_listen_env_removed() {
+        for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            assert!(env::var_os(var).is_none(), "{var} left set");
+        }
+    }
+
+    #[test]
+    fn takes_named_fds() {
+        let _env = ENV.lock().unwrap();
+        pass_fds(3);
+        set_listen_env(std::process::id(), 3, Some("grpc::admin"));
+
+        let fds = take_listen_fds(START).unwrap();
+        assert_listen_env_removed();
+        let taken: Vec<_> = fds
+            .iter()
+            .map(|fd| (fd.fd.as_raw_fd(), fd.name.as_deref()))
+            .collect();
+        assert_eq!(
+            taken,
+            [
+                (START, Some("grpc")),
+                (START + 1, None),
+                (START + 2, Some("admin"))
+            ]
+        );
+        for fd in &fds {
+            let flags = unsafe { libc::fcntl(fd.fd.as_raw_fd(), libc::F_GETFD) };
+            assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+        }
+    }
+
+    #[test]
+    fn names_are_optional() {
+        let _env = ENV.lock().unwrap();
+        pass_fds(2);
+        set_listen_env(std::process::id(), 2, None);
+
+        let fds = take_listen_fds(START).unwrap();
+        assert_eq!(fds.len(), 2);
+        assert!(fds.iter().all(|fd| fd.name.is_none()));
+    }
+
+    #[test]
+    fn ignores_fds_for_another_process() {
+        let _env = ENV.lock().unwrap();
+        set_listen_env(std::process::id() + 1, 2, Some("grpc:admin"));
+
+        assert!(take_listen_fds(START).unwrap().is_empty());
+        assert_listen_env_removed();
+
+        env::set_var("LISTEN_FDS", "2");
+        assert!(take_listen_fds(START).unwrap().is_empty());
+    }
+
+    #[test]
+    fn notify_without_socket_does_nothing() {
+        let _env = ENV.lock().unwrap();
+        env::remove_var("NOTIFY_SOCKET");
+        assert!(!notify("READY=1").unwrap());
+    }
+
+    #[test]
+    fn notifier_reports_ready_then_stopping() {
+        let _env = ENV.lock().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        env::set_var("NOTIFY_SOCKET", &path);
+        let recv = || {
+            let mut buf = [0; 64];
+            let n = manager.recv(&mut buf).unwrap();
+            String::from_utf8(buf[..n].to_vec()).unwrap()
+        };
+
+        let (ready, shutdown) = (Signal::new(), Signal::new());
+        spawn_notifier(ready.clone(), shutdown.clone());
+        ready.trigger();
+        assert_eq!(recv(), "READY=1");
+        shutdown.trigger();
+        assert_eq!(recv(), "STOPPING=1");
+
+        // Shut down before ever becoming ready: only STOPPING=1.
+        let (ready, shutdown) = (Signal::new(), Signal::new());
+        spawn_notifier(ready, shutdown.clone());
+        shutdown.trigger();
+        assert_eq!(recv(), "STOPPING=1");
+        env::remove_var("NOTIFY_SOCKET");
+    }
+}
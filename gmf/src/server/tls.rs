@@ -0,0 +1,324 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{CertificateError, RootCertStore};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tonic::transport::server::{Connected, TcpConnectInfo};
+
+use crate::server::error::GmfError;
+use crate::server::metrics::TlsFailureReason;
+
+/// A TLS session over a runtime's hyper IO type.
+///
+/// rustls (via tokio-rustls) speaks tokio's IO traits, so the runtime stream is adapted
+/// with `TokioIo` on the way in and the session is adapted back to `hyper::rt` on the
+/// way out. This works the same for monoio (compat layer), glommio (`HyperIo`) and tokio.
+pub(crate) type TlsIo<I> = TokioIo<TlsStream<PeerIo<I>>>;
+
+/// TLS termination settings.
+///
+/// The handshake runs on the core that accepted the connection. Only `h2` is offered
+/// via ALPN, since GMF serves HTTP/2 exclusively.
+#[derive(Clone)]
+pub struct TlsConfig {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsConfig {
+    /// Use a prepared rustls server config. Its ALPN protocols are replaced with `h2`.
+    pub fn new(mut config: rustls::ServerConfig) -> Self {
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        TlsConfig {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        }
+    }
+
+    /// Build a config from a PEM-encoded certificate chain and private key.
+    pub fn from_pem(cert_chain: &[u8], private_key: &[u8]) -> Result<Self, GmfError> {
+        let (certs, key) = parse_identity(cert_chain, private_key)?;
+
+        let config = server_config_builder()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| GmfError::TlsConfig(e.into()))?;
+
+        Ok(Self::new(config))
+    }
+
+    /// Like [`from_pem`](Self::from_pem), but require every client to present a
+    /// certificate that verifies against `client_auth`.
+    ///
+    /// The verified chain is available to handlers through `request.peer_certs()` and
+    /// the [`PeerIdentity`] request extension.
+    pub fn from_pem_with_client_auth(
+        cert_chain: &[u8],
+        private_key: &[u8],
+        client_auth: ClientAuth,
+    ) -> Result<Self, GmfError> {
+        let (certs, key) = parse_identity(cert_chain, private_key)?;
+
+        let config = server_config_builder()?
+            .with_client_cert_verifier(client_auth.verifier()?)
+            .with_single_cert(certs, key)
+            .map_err(|e| GmfError::TlsConfig(e.into()))?;
+
+        Ok(Self::new(config))
+    }
+
+    /// Run the server side of the handshake.
+    ///
+    /// The TCP addresses (none for Unix sockets) are carried into the session so
+    /// tonic's `TlsConnectInfo` can report them.
+    pub(crate) async fn accept<I>(&self, io: I, info: TcpConnectInfo) -> io::Result<TlsIo<I>>
+    where
+        I: hyper::rt::Read + hyper::rt::Write + Unpin,
+    {
+        let io = PeerIo {
+            io: TokioIo::new(io),
+            info,
+        };
+        let stream = self.acceptor.accept(io).await?;
+        Ok(TokioIo::new(stream))
+    }
+}
+
+/// Client certificate verification for mutual TLS.
+///
+/// Clients must present a chain that leads to one of the configured CAs and that no
+/// configured CRL revokes.
+#[derive(Clone)]
+pub struct ClientAuth {
+    roots: Arc<RootCertStore>,
+    crls: Vec<CertificateRevocationListDer<'static>>,
+}
+
+impl ClientAuth {
+    /// Trust the CA certificates in a PEM bundle.
+    pub fn from_pem(ca_bundle: &[u8]) -> Result<Self, GmfError> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(ca_bundle) {
+            let cert = cert.map_err(|e| GmfError::TlsConfig(e.into()))?;
+            roots.add(cert).map_err(|e| GmfError::TlsConfig(e.into()))?;
+        }
+
+        Ok(ClientAuth {
+            roots: Arc::new(roots),
+            crls: Vec::new(),
+        })
+    }
+
+    /// Add the certificate revocation lists in a PEM file. May be called repeatedly.
+    pub fn crls_pem(mut self, crls: &[u8]) -> Result<Self, GmfError> {
+        for crl in CertificateRevocationListDer::pem_slice_iter(crls) {
+            self.crls
+                .push(crl.map_err(|e| GmfError::TlsConfig(e.into()))?);
+        }
+        Ok(self)
+    }
+
+    fn verifier(self) -> Result<Arc<dyn ClientCertVerifier>, GmfError> {
+        WebPkiClientVerifier::builder_with_provider(self.roots, Arc::new(provider()))
+            .with_crls(self.crls)
+            .build()
+            .map_err(|e| GmfError::TlsConfig(e.into()))
+    }
+}
+
+/// The verified identity of an mTLS client, inserted into the extensions of every
+/// request on the connection.
+///
+/// ```ignore
+/// let identity = request.extensions().get::<PeerIdentity>();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PeerIdentity(Arc<PeerIdentityInner>);
+
+#[derive(Debug)]
+struct PeerIdentityInner {
+    chain: Vec<CertificateDer<'static>>,
+    dns_names: Vec<String>,
+    uri_names: Vec<String>,
+}
+
+impl PeerIdentity {
+    fn new(chain: &[CertificateDer<'_>]) -> Self {
+        let chain: Vec<_> = chain.iter().map(|cert| cert.clone().into_owned()).collect();
+        let (dns_names, uri_names) = chain
+            .first()
+            .and_then(|leaf| webpki::EndEntityCert::try_from(leaf).ok())
+            .map(|leaf| {
+                (
+                    leaf.valid_dns_names().map(str::to_owned).collect(),
+                    leaf.valid_uri_names().map(str::to_owned).collect(),
+                )
+            })
+            .unwrap_or_default();
+
+        PeerIdentity(Arc::new(PeerIdentityInner {
+            chain,
+            dns_names,
+            uri_names,
+        }))
+    }
+
+    /// The client's certificate chain, leaf first.
+    pub fn chain(&self) -> &[CertificateDer<'static>] {
+        &self.0.chain
+    }
+
+    /// DNS names from the leaf certificate's subject alternative names.
+    pub fn dns_names(&self) -> &[String] {
+        &self.0.dns_names
+    }
+
+    /// URIs from the leaf certificate's subject alternative names (e.g. SPIFFE IDs).
+    pub fn uri_names(&self) -> &[String] {
+        &self.0.uri_names
+    }
+}
+
+/// Connection-level request extensions for an established session: tonic's
+/// `TlsConnectInfo` and, when the client authenticated, its [`PeerIdentity`].
+pub(crate) fn session_extensions<I>(io: &TlsIo<I>) -> http::Extensions
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin,
+{
+    let stream = io.inner();
+    let mut extensions = http::Extensions::new();
+    extensions.insert(stream.connect_info());
+    if let Some(chain) = stream.get_ref().1.peer_certificates() {
+        extensions.insert(PeerIdentity::new(chain));
+    }
+    extensions
+}
+
+/// Map a failed handshake to the reason it is counted under.
+pub(crate) fn failure_reason(error: &io::Error) -> TlsFailureReason {
+    let Some(error) = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<rustls::Error>())
+    else {
+        return TlsFailureReason::Io;
+    };
+
+    match error {
+        rustls::Error::NoCertificatesPresented => TlsFailureReason::NoClientCertificate,
+        rustls::Error::InvalidCertificate(error) => match error {
+            CertificateError::UnknownIssuer => TlsFailureReason::UnknownIssuer,
+            CertificateError::Expired | CertificateError::ExpiredContext { .. } => {
+                TlsFailureReason::Expired
+            }
+            CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+                TlsFailureReason::NotValidYet
+            }
+            CertificateError::Revoked => TlsFailureReason::Revoked,
+            CertificateError::UnknownRevocationStatus => TlsFailureReason::UnknownRevocationStatus,
+            CertificateError::ExpiredRevocationList
+            | CertificateError::ExpiredRevocationListContext { .. } => {
+                TlsFailureReason::ExpiredRevocationList
+            }
+            CertificateError::BadSignature => TlsFailureReason::BadSignature,
+            CertificateError::InvalidPurpose | CertificateError::InvalidPurposeContext { .. } => {
+                TlsFailureReason::InvalidPurpose
+            }
+            CertificateError::BadEncoding => TlsFailureReason::BadEncoding,
+            _ => TlsFailureReason::OtherCertificateError,
+        },
+        _ => TlsFailureReason::Protocol,
+    }
+}
+
+/// The runtime stream as seen by rustls: tokio IO plus the addresses tonic reports via
+/// `Connected`, so the session yields a `TlsConnectInfo<TcpConnectInfo>`.
+pub(crate) struct PeerIo<I> {
+    io: TokioIo<I>,
+    info: TcpConnectInfo,
+}
+
+impl<I> Connected for PeerIo<I> {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> TcpConnectInfo {
+        self.info.clone()
+    }
+}
+
+impl<I> AsyncRead for PeerIo<I>
+where
+    I: hyper::rt::Read + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl<I> AsyncWrite for PeerIo<I>
+where
+    I: hyper::rt::Write + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write_vectored(cx, bufs)
+    }
+}
+
+fn parse_identity(
+    cert_chain: &[u8],
+    private_key: &[u8],
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), GmfError> {
+    let certs = CertificateDer::pem_slice_iter(cert_chain)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| GmfError::TlsConfig(e.into()))?;
+    let key =
+        PrivateKeyDer::from_pem_slice(private_key).map_err(|e| GmfError::TlsConfig(e.into()))?;
+    Ok((certs, key))
+}
+
+fn provider() -> rustls::crypto::CryptoProvider {
+    rustls::crypto::ring::default_provider()
+}
+
+/// A rustls config builder pinned to the ring provider, so GMF does not depend on
+/// which provider the application installed as the process default.
+pub(crate) fn server_config_builder(
+) -> Result<rustls::ConfigBuilder<rustls::ServerConfig, rustls::WantsVerifier>, GmfError> {
+    rustls::ServerConfig::builder_with_provider(Arc::new(provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| GmfError::TlsConfig(e.into()))
+}
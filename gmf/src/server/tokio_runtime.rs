@@ -1,46 +1,54 @@
 use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
+use crate::server::cpu::CorePlacement;
 use crate::server::error::GmfError;
+use crate::server::listener::PeerCredentials;
 use crate::server::runtime::{
-    Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeTcpListener, RuntimeTcpStream,
+    CoreJoinHandle, Runtime, RuntimeExecutor, RuntimeSemaphore, RuntimeStream, RuntimeTcpListener,
+    RuntimeTimer, RuntimeUnixListener,
 };
+use crate::server::semaphore::{LocalPermit, LocalSemaphore};
 
 /// Thread-per-core runtime using tokio (current-thread mode, one per core).
 pub struct TokioRuntime;
 
 impl Runtime for TokioRuntime {
     type TcpListener = TokioListener;
+    type UnixListener = TokioUnixListener;
     type Executor = TokioExec;
     type Semaphore = TokioSemaphore;
+    type Timer = TokioTimer;
 
-    fn run_multi_core<F, Fut>(cores: usize, f: F) -> Result<(), GmfError>
+    fn spawn_multi_core<F, Fut>(
+        placement: &CorePlacement,
+        f: F,
+    ) -> Result<Vec<CoreJoinHandle>, GmfError>
     where
         F: Fn(usize) -> Fut + Send + Clone + 'static,
         Fut: Future<Output = Result<(), GmfError>> + 'static,
     {
-        let mut handles = Vec::with_capacity(cores);
+        let mut handles = Vec::with_capacity(placement.cores());
 
-        for cpu in 0..cores {
+        for cpu in 0..placement.cores() {
             let f = f.clone();
+            let placement = placement.clone();
             let handle = std::thread::Builder::new()
                 .name(format!("gmf_core_{cpu}"))
                 .spawn(move || {
+                    placement.bind_current_thread(cpu)?;
+
                     let rt = tokio::runtime::Builder::new_current_thread()
                         .enable_all()
                         .build()
                         .map_err(|e| GmfError::SpawnExecutor { cpu, source: e })?;
 
-                    // Pin to CPU core on Linux
-                    #[cfg(target_os = "linux")]
-                    {
-                        let cpuset = libc_cpuset(cpu);
-                        unsafe {
-                            libc::sched_setaffinity(0, std::mem::size_of_val(&cpuset), &cpuset);
-                        }
-                    }
-
                     let local_set = tokio::task::LocalSet::new();
                     rt.block_on(local_set.run_until(f(cpu)))
                 })
@@ -49,49 +57,26 @@ impl Runtime for TokioRuntime {
             handles.push(handle);
         }
 
-        for handle in handles {
-            handle
-                .join()
-                .map_err(|_| GmfError::Io(io::Error::other("thread panicked")))??;
-        }
-
-        Ok(())
+        Ok(handles)
     }
 }
 
-#[cfg(target_os = "linux")]
-fn libc_cpuset(cpu: usize) -> libc::cpu_set_t {
-    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
-    unsafe { libc::CPU_SET(cpu, &mut set) };
-    set
-}
-
 // -- TCP Listener --
 
 pub struct TokioListener(tokio::net::TcpListener);
 
+impl AsRawFd for TokioListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
 impl RuntimeTcpListener for TokioListener {
     type Stream = TokioStream;
 
-    async fn bind(addr: SocketAddr) -> io::Result<Self> {
-        let socket = socket2::Socket::new(
-            match addr {
-                SocketAddr::V4(_) => socket2::Domain::IPV4,
-                SocketAddr::V6(_) => socket2::Domain::IPV6,
-            },
-            socket2::Type::STREAM,
-            Some(socket2::Protocol::TCP),
-        )?;
-        socket.set_reuse_address(true)?;
-        #[cfg(target_os = "linux")]
-        {
-            socket.set_reuse_port(true)?;
-        }
-        socket.set_nonblocking(true)?;
-        socket.bind(&addr.into())?;
-        socket.listen(1024)?;
-        let std_listener: std::net::TcpListener = socket.into();
-        let listener = tokio::net::TcpListener::from_std(std_listener)?;
+    fn from_std(listener: std::net::TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
         Ok(TokioListener(listener))
     }
 
@@ -99,13 +84,23 @@ impl RuntimeTcpListener for TokioListener {
         let (stream, addr) = self.0.accept().await?;
         Ok((TokioStream(stream), addr))
     }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
 }
 
 // -- TCP Stream --
 
 pub struct TokioStream(tokio::net::TcpStream);
 
-impl RuntimeTcpStream for TokioStream {
+impl AsRawFd for TokioStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl RuntimeStream for TokioStream {
     type HyperIo = hyper_util::rt::TokioIo<tokio::net::TcpStream>;
 
     fn into_hyper_io(self) -> Self::HyperIo {
@@ -113,6 +108,42 @@ impl RuntimeTcpStream for TokioStream {
     }
 }
 
+// -- Unix Listener --
+
+pub struct TokioUnixListener(tokio::net::UnixListener);
+
+impl RuntimeUnixListener for TokioUnixListener {
+    type Stream = TokioUnixStream;
+
+    fn from_std(listener: StdUnixListener) -> io::Result<Self> {
+        Ok(TokioUnixListener(tokio::net::UnixListener::from_std(
+            listener,
+        )?))
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.0.as_raw_fd())
+    }
+
+    async fn accept(&self) -> io::Result<(Self::Stream, Option<PeerCredentials>)> {
+        let (stream, _) = self.0.accept().await?;
+        let cred = PeerCredentials::from_fd(stream.as_raw_fd()).ok();
+        Ok((TokioUnixStream(stream), cred))
+    }
+}
+
+// -- Unix Stream --
+
+pub struct TokioUnixStream(tokio::net::UnixStream);
+
+impl RuntimeStream for TokioUnixStream {
+    type HyperIo = hyper_util::rt::TokioIo<tokio::net::UnixStream>;
+
+    fn into_hyper_io(self) -> Self::HyperIo {
+        hyper_util::rt::TokioIo::new(self.0)
+    }
+}
+
 // -- Executor --
 
 #[derive(Clone, Default)]
@@ -136,32 +167,69 @@ where
     }
 }
 
+// -- Timer --
+
+#[derive(Clone, Default)]
+pub struct TokioTimer;
+
+impl RuntimeTimer for TokioTimer {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    fn hyper_sleep(&self, duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>> {
+        Box::pin(TokioSleep(tokio::time::sleep(duration)))
+    }
+}
+
+/// tokio's sleep is already `Send + Sync`; hyper only needs it to implement `Sleep`.
+#[pin_project::pin_project]
+struct TokioSleep(#[pin] tokio::time::Sleep);
+
+impl Future for TokioSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.project().0.poll(cx)
+    }
+}
+
+impl hyper::rt::Sleep for TokioSleep {}
+
 // -- Semaphore --
 
+#[derive(Clone)]
 pub struct TokioSemaphore {
-    permits: std::cell::Cell<usize>,
+    permits: LocalSemaphore,
 }
 
 impl RuntimeSemaphore for TokioSemaphore {
+    type Permit = TokioPermit;
+
     fn new(permits: usize) -> Self {
         TokioSemaphore {
-            permits: std::cell::Cell::new(permits),
+            permits: LocalSemaphore::new(permits),
         }
     }
 
-    fn try_acquire(&self) -> bool {
-        let current = self.permits.get();
-        if current > 0 {
-            self.permits.set(current - 1);
-            true
-        } else {
-            false
+    fn try_acquire(&self) -> Option<Self::Permit> {
+        self.permits
+            .try_acquire()
+            .map(|permit| TokioPermit { _permit: permit })
+    }
+
+    async fn acquire(&self) -> Self::Permit {
+        TokioPermit {
+            _permit: self.permits.acquire().await,
         }
     }
-}
 
-impl TokioSemaphore {
-    pub fn release(&self) {
-        self.permits.set(self.permits.get() + 1);
+    fn available(&self) -> usize {
+        self.permits.available()
     }
 }
+
+/// A connection permit; returns its slot to the `TokioSemaphore` on drop.
+pub struct TokioPermit {
+    _permit: LocalPermit,
+}
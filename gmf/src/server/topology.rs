@@ -0,0 +1,349 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::server::cpu::CpuSet;
+
+/// Where Linux describes the CPUs.
+pub const SYSFS_CPU_ROOT: &str = "/sys/devices/system/cpu";
+
+/// One logical CPU and the physical core it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicalCpu {
+    pub cpu: usize,
+    /// `physical_package_id`, i.e. the socket.
+    pub package: Option<usize>,
+    /// `core_id`, unique within the package.
+    pub core: Option<usize>,
+    /// The logical CPUs of the same physical core (SMT siblings), this one included.
+    pub siblings: CpuSet,
+    /// The NUMA node, from the `cpuN/nodeM` link; `None` on kernels without NUMA.
+    pub node: Option<usize>,
+}
+
+/// The machine's online CPUs, as described by sysfs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuTopology {
+    cpus: Vec<LogicalCpu>,
+}
+
+impl CpuTopology {
+    /// Read the running system's topology from [`SYSFS_CPU_ROOT`].
+    pub fn system() -> io::Result<Self> {
+        Self::from_sysfs(SYSFS_CPU_ROOT)
+    }
+
+    /// Read the topology from a directory laid out like `/sys/devices/system/cpu`
+    /// (`online`, `cpuN/topology/*` and `cpuN/nodeM`), e.g. a fixture tree.
+    pub fn from_sysfs(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref();
+        let online = match fs::read_to_string(root.join("online")) {
+            Ok(list) => parse_list(&list)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => cpu_dirs(root)?,
+            Err(e) => return Err(e),
+        };
+        let cpus = online
+            .iter()
+            .map(|cpu| read_cpu(&root.join(format!("cpu{cpu}")), cpu))
+            .collect::<io::Result<_>>()?;
+        Ok(CpuTopology { cpus })
+    }
+
+    /// `true` if nothing is known about the CPUs, e.g. outside Linux.
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_empty()
+    }
+
+    /// Every online CPU in ascending order.
+    pub fn cpus(&self) -> &[LogicalCpu] {
+        &self.cpus
+    }
+
+    pub fn cpu(&self, cpu: usize) -> Option<&LogicalCpu> {
+        self.cpus
+            .binary_search_by_key(&cpu, |logical| logical.cpu)
+            .ok()
+            .map(|index| &self.cpus[index])
+    }
+
+    /// The CPUs of `cpus` grouped by physical core. Each group is in ascending order and
+    /// the groups are ordered by their first CPU; a CPU the topology does not describe
+    /// is a core of its own.
+    pub fn physical_cores(&self, cpus: &CpuSet) -> Vec<Vec<usize>> {
+        let mut seen = CpuSet::new();
+        let mut cores = Vec::new();
+        for cpu in cpus.iter() {
+            if seen.contains(cpu) {
+                continue;
+            }
+            let core: Vec<usize> = match self.cpu(cpu) {
+                Some(logical) => logical.siblings.intersection(cpus).iter().collect(),
+                None => vec![cpu],
+            };
+            for &sibling in &core {
+                seen.insert(sibling);
+            }
+            cores.push(core);
+        }
+        cores
+    }
+}
+
+impl fmt::Display for LogicalCpu {
+    /// `cpu 3 (node 0, package 0, core 1)`, leaving out what is unknown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cpu {}", self.cpu)?;
+        let known = [
+            ("node", self.node),
+            ("package", self.package),
+            ("core", self.core),
+        ];
+        let mut known = known
+            .iter()
+            .filter_map(|&(name, id)| id.map(|id| (name, id)))
+            .peekable();
+        if known.peek().is_none() {
+            return Ok(());
+        }
+        f.write_str(" (")?;
+        let mut first = true;
+        for (name, id) in known {
+            if !first {
+                f.write_str(", ")?;
+            }
+            first = false;
+            write!(f, "{name} {id}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+fn read_cpu(dir: &Path, cpu: usize) -> io::Result<LogicalCpu> {
+    let topology = dir.join("topology");
+    // `core_cpus_list` replaced `thread_siblings_list` in Linux 5.5.
+    let siblings = match read_optional(&topology.join("core_cpus_list"))? {
+        Some(list) => Some(list),
+        None => read_optional(&topology.join("thread_siblings_list"))?,
+    };
+    let mut siblings = match siblings {
+        Some(list) => parse_list(&list)?,
+        None => CpuSet::new(),
+    };
+    siblings.insert(cpu);
+    Ok(LogicalCpu {
+        cpu,
+        package: read_id(&topology.join("physical_package_id"))?,
+        core: read_id(&topology.join("core_id"))?,
+        siblings,
+        node: read_node(dir)?,
+    })
+}
+
+/// The `nodeM` entry of a CPU's directory (a link to `/sys/devices/system/node/nodeM`).
+fn read_node(dir: &Path) -> io::Result<Option<usize>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let name = entry?.file_name();
+        if let Some(node) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|n| n.parse().ok())
+        {
+            return Ok(Some(node));
+        }
+    }
+    Ok(None)
+}
+
+/// An id file; architectures without the notion report `-1`.
+fn read_id(path: &Path) -> io::Result<Option<usize>> {
+    let Some(id) = read_optional(path)? else {
+        return Ok(None);
+    };
+    let id: i64 = id.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid id in {}: {id:?}", path.display()),
+        )
+    })?;
+    Ok(usize::try_from(id).ok())
+}
+
+/// A file's contents, or `None` if it does not exist.
+pub(crate) fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn parse_list(list: &str) -> io::Result<CpuSet> {
+    list.parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The `cpuN` directories, for trees without an `online` file.
+fn cpu_dirs(root: &Path) -> io::Result<CpuSet> {
+    let mut cpus = CpuSet::new();
+    for entry in fs::read_dir(root)? {
+        let name = entry?.file_name();
+        if let Some(cpu) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .and_then(|n| n.parse().ok())
+        {
+            cpus.insert(cpu);
+        }
+    }
+    Ok(cpus)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// One fixture CPU: its number, package, core id, SMT siblings and NUMA node.
+    pub(crate) type FixtureCpu<'a> = (usize, usize, usize, &'a str, Option<usize>);
+
+    /// A sysfs CPU tree describing `cpus`, all online.
+    pub(crate) fn sysfs(cpus: &[FixtureCpu<'_>]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let online: CpuSet = cpus.iter().map(|&(cpu, ..)| cpu).collect();
+        fs::write(root.path().join("online"), format!("{online}\n")).unwrap();
+        for &(cpu, package, core, siblings, node) in cpus {
+            let dir = root.path().join(format!("cpu{cpu}"));
+            let topology = dir.join("topology");
+            fs::create_dir_all(&topology).unwrap();
+            fs::write(topology.join("physical_package_id"), format!("{package}\n")).unwrap();
+            fs::write(topology.join("core_id"), format!("{core}\n")).unwrap();
+            fs::write(topology.join("core_cpus_list"), format!("{siblings}\n")).unwrap();
+            if let Some(node) = node {
+                fs::create_dir(dir.join(format!("node{node}"))).unwrap();
+            }
+        }
+        root
+    }
+
+    /// `n` CPUs on one package, each a physical core of its own.
+    pub(crate) fn flat(n: usize) -> CpuTopology {
+        let siblings: Vec<String> = (0..n).map(|cpu| cpu.to_string()).collect();
+        let cpus: Vec<FixtureCpu<'_>> = (0..n)
+            .map(|cpu| (cpu, 0, cpu, siblings[cpu].as_str(), None))
+            .collect();
+        CpuTopology::from_sysfs(sysfs(&cpus).path()).unwrap()
+    }
+
+    /// Two packages, one NUMA node each, of two SMT cores each, numbered across the
+    /// nodes as many BIOSes do: node 0 holds CPUs 0, 2, 4 and 6, with 4 and 6 the
+    /// siblings of 0 and 2.
+    pub(crate) fn two_nodes() -> CpuTopology {
+        let cpus: Vec<FixtureCpu<'_>> = vec![
+            (0, 0, 0, "0,4", Some(0)),
+            (1, 1, 0, "1,5", Some(1)),
+            (2, 0, 1, "2,6", Some(0)),
+            (3, 1, 1, "3,7", Some(1)),
+            (4, 0, 0, "0,4", Some(0)),
+            (5, 1, 0, "1,5", Some(1)),
+            (6, 0, 1, "2,6", Some(0)),
+            (7, 1, 1, "3,7", Some(1)),
+        ];
+        CpuTopology::from_sysfs(sysfs(&cpus).path()).unwrap()
+    }
+
+    #[test]
+    fn reads_nodes_and_siblings() {
+        let topology = two_nodes();
+        assert_eq!(topology.cpus().len(), 8);
+        let cpu = topology.cpu(5).unwrap();
+        assert_eq!(
+            (cpu.package, cpu.core, cpu.node),
+            (Some(1), Some(0), Some(1))
+        );
+        assert_eq!(cpu.siblings.to_string(), "1,5");
+        assert_eq!(cpu.to_string(), "cpu 5 (node 1, package 1, core 0)");
+        assert!(topology.cpu(8).is_none());
+    }
+
+    #[test]
+    fn groups_physical_cores() {
+        let topology = two_nodes();
+        let all: CpuSet = (0..8).collect();
+        assert_eq!(
+            topology.physical_cores(&all),
+            [vec![0, 4], vec![1, 5], vec![2, 6], vec![3, 7]]
+        );
+        // Only the allowed siblings; CPUs outside the topology are cores of their own.
+        let some: CpuSet = [0, 4, 5, 9].into_iter().collect();
+        assert_eq!(
+            topology.physical_cores(&some),
+            [vec![0, 4], vec![5], vec![9]]
+        );
+    }
+
+    #[test]
+    fn reads_flat_topology() {
+        let topology = flat(4);
+        assert_eq!(topology.cpus().len(), 4);
+        let cpu = topology.cpu(2).unwrap();
+        assert_eq!((cpu.package, cpu.core, cpu.node), (Some(0), Some(2), None));
+        assert_eq!(cpu.siblings.to_string(), "2");
+        assert_eq!(cpu.to_string(), "cpu 2 (package 0, core 2)");
+    }
+
+    #[test]
+    fn older_kernels_and_missing_ids() {
+        let root = tempfile::tempdir().unwrap();
+        // No `online` file; `thread_siblings_list` instead of `core_cpus_list`; `-1`
+        // ids on architectures without packages; no `nodeM` link.
+        for cpu in 0..2 {
+            let topology = root.path().join(format!("cpu{cpu}/topology"));
+            fs::create_dir_all(&topology).unwrap();
+            fs::write(topology.join("thread_siblings_list"), "0-1\n").unwrap();
+            fs::write(topology.join("physical_package_id"), "-1\n").unwrap();
+        }
+        fs::create_dir(root.path().join("cpufreq")).unwrap();
+        let topology = CpuTopology::from_sysfs(root.path()).unwrap();
+
+        assert_eq!(topology.cpus().len(), 2);
+        let cpu = topology.cpu(1).unwrap();
+        assert_eq!((cpu.package, cpu.core, cpu.node), (None, None, None));
+        assert_eq!(cpu.siblings.to_string(), "0-1");
+        assert_eq!(cpu.to_string(), "cpu 1");
+        assert_eq!(topology.physical_cores(&(0..2).collect()), [vec![0, 1]]);
+    }
+
+    #[test]
+    fn cpu_without_topology_is_its_own_core() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("online"), "0\n").unwrap();
+        let topology = CpuTopology::from_sysfs(root.path()).unwrap();
+        assert_eq!(topology.cpu(0).unwrap().siblings.to_string(), "0");
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("online"), "0-\n").unwrap();
+        let e = CpuTopology::from_sysfs(root.path()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let topology = root.path().join("cpu0/topology");
+        fs::create_dir_all(&topology).unwrap();
+        fs::write(root.path().join("online"), "0\n").unwrap();
+        fs::write(topology.join("core_id"), "zero\n").unwrap();
+        let e = CpuTopology::from_sysfs(root.path()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn missing_root_is_an_error() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(CpuTopology::from_sysfs(root.path().join("missing")).is_err());
+    }
+}
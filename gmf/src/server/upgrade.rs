@@ -0,0 +1,364 @@
+use std::env;
+use std::io;
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Socket, Type};
+
+use crate::server::config::ListenerConfig;
+use crate::server::gmf_server::block_on_simple;
+use crate::server::handle::ServerHandle;
+
+/// Environment variable through which [`ServerHandle::upgrade`] passes the channel's fd
+/// to the new process.
+pub const UPGRADE_FD_VAR: &str = "GMF_UPGRADE_FD";
+
+/// First message on the channel: magic, then the number of listeners and of cores.
+const MAGIC: [u8; 4] = *b"GMF1";
+/// The new process's answer once it is serving.
+const READY: &[u8] = b"READY";
+
+/// One end of the Unix socket a zero-downtime upgrade runs over.
+///
+/// The running server sends every core's listening sockets down it with `SCM_RIGHTS`
+/// (see [`ServerHandle::hand_over`]); the new process [`receive`](Self::receive)s them,
+/// starts serving on them and reports [`ready`](Self::ready). Both processes then
+/// accept from the same sockets until the old one has stopped accepting, so no
+/// connection is refused.
+pub struct UpgradeChannel(Socket);
+
+impl UpgradeChannel {
+    /// A connected pair of channels, one for each process.
+    pub fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = Socket::pair(Domain::UNIX, Type::SEQPACKET, None)?;
+        Ok((UpgradeChannel(a), UpgradeChannel(b)))
+    }
+
+    /// The channel passed by [`ServerHandle::upgrade`], or `None` when this process was
+    /// not started by one. The variable is removed so that the channel is taken once,
+    /// and its fd is marked close-on-exec.
+    ///
+    /// Changing the environment is not thread-safe, so call this at the start of
+    /// `main`, before any other thread is spawned (a runtime, a tracing writer, or a
+    /// server).
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(fd) = env::var(UPGRADE_FD_VAR).ok() else {
+            return Ok(None);
+        };
+        env::remove_var(UPGRADE_FD_VAR);
+        let fd: RawFd = fd.parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid {UPGRADE_FD_VAR}: {fd:?}"),
+            )
+        })?;
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the old process passed this fd for the channel, and removing the
+        // variable above ensures it is only taken here.
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+        if socket.r#type()? != Type::SEQPACKET {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{UPGRADE_FD_VAR} is not an upgrade channel"),
+            ));
+        }
+        Ok(Some(UpgradeChannel(socket)))
+    }
+
+    /// Receive the old server's listening sockets: one [`ListenerConfig`] per listener,
+    /// in the old server's order (see [`ServerHandle::listener_addrs`]), each with one
+    /// socket per core.
+    ///
+    /// Listener settings such as TLS or connection limits are not passed; set them on
+    /// the returned configs. The new server must run as many cores as the old one.
+    pub fn receive(&self, timeout: Duration) -> io::Result<Vec<ListenerConfig>> {
+        let deadline = Instant::now() + timeout;
+        let mut header = [0u8; 12];
+        let (n, _) = self.recv(&mut header, deadline)?;
+        if n != header.len() || header[..4] != MAGIC {
+            return Err(invalid("unexpected upgrade header"));
+        }
+        let listeners = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        let cores = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+
+        (0..listeners)
+            .map(|listener| {
+                let fds = (0..cores)
+                    .map(|cpu| {
+                        let mut index = [0u8; 8];
+                        match self.recv(&mut index, deadline)? {
+                            (8, Some(fd)) if index == socket_index(listener, cpu) => Ok(fd),
+                            _ => Err(invalid("unexpected upgrade message")),
+                        }
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                ListenerConfig::from_fds(fds)
+            })
+            .collect()
+    }
+
+    /// Tell the old server that the new one is serving, once every core of `handle` is
+    /// accepting. The old server then drains and exits.
+    pub fn ready(self, handle: &ServerHandle) -> io::Result<()> {
+        block_on_simple(handle.ready());
+        self.0.send_with_flags(READY, libc::MSG_NOSIGNAL)?;
+        Ok(())
+    }
+
+    /// Send each listener's per-core sockets, by listener and then by core.
+    pub(crate) fn send(&self, sockets: &[Vec<OwnedFd>], deadline: Instant) -> io::Result<()> {
+        self.0.set_write_timeout(Some(remaining(deadline)?))?;
+        let cores = sockets.first().map_or(0, Vec::len);
+        let mut header = [0u8; 12];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&(sockets.len() as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&(cores as u32).to_le_bytes());
+        self.send_msg(&header, None).map_err(timed_out)?;
+        for (listener, fds) in sockets.iter().enumerate() {
+            for (cpu, fd) in fds.iter().enumerate() {
+                self.send_msg(&socket_index(listener, cpu), Some(fd.as_fd()))
+                    .map_err(timed_out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the new process to report that it is serving.
+    pub(crate) fn wait_ready(&self, deadline: Instant) -> io::Result<()> {
+        let mut buf = [0u8; 16];
+        match self.recv(&mut buf, deadline)? {
+            (0, _) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the new process closed the upgrade channel",
+            )),
+            (n, _) if &buf[..n] == READY => Ok(()),
+            _ => Err(invalid("unexpected upgrade message")),
+        }
+    }
+
+    /// The channel's fd without close-on-exec, for a child process to inherit.
+    pub(crate) fn inheritable(&self) -> io::Result<OwnedFd> {
+        let fd = unsafe { libc::fcntl(self.0.as_raw_fd(), libc::F_DUPFD, 3) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `F_DUPFD` returned a new fd that nothing else owns.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn send_msg(&self, data: &[u8], fd: Option<BorrowedFd<'_>>) -> io::Result<()> {
+        // Aligned for `cmsghdr` and large enough for one fd.
+        let mut control = [0u64; 4];
+        let mut iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        // SAFETY: `msg` points at `iov` and `control`, which outlive the call, and the
+        // control message written through `CMSG_*` fits in `control`.
+        unsafe {
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            if let Some(fd) = fd {
+                msg.msg_control = control.as_mut_ptr().cast();
+                msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                (*cmsg).cmsg_level = libc::SOL_SOCKET;
+                (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+                ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd.as_raw_fd());
+            }
+            loop {
+                if libc::sendmsg(self.0.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) >= 0 {
+                    return Ok(());
+                }
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Receive one message and the fd passed with it, if any, before `deadline`.
+    fn recv(&self, buf: &mut [u8], deadline: Instant) -> io::Result<(usize, Option<OwnedFd>)> {
+        self.0.set_read_timeout(Some(remaining(deadline)?))?;
+        let mut control = [0u64; 4];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // SAFETY: `msg` points at `iov` and `control`, which outlive the call; the
+        // kernel only reports control messages within `msg_controllen`, and every fd
+        // in an `SCM_RIGHTS` message is new to this process.
+        unsafe {
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = mem::size_of_val(&control) as _;
+            let n = loop {
+                let n = libc::recvmsg(self.0.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC);
+                if n >= 0 {
+                    break n as usize;
+                }
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(timed_out(e));
+                }
+            };
+
+            let mut fds = Vec::new();
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                    let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                    for i in 0..len / mem::size_of::<RawFd>() {
+                        fds.push(OwnedFd::from_raw_fd(ptr::read_unaligned(data.add(i))));
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            if msg.msg_flags & (libc::MSG_TRUNC | libc::MSG_CTRUNC) != 0 || fds.len() > 1 {
+                return Err(invalid("unexpected upgrade message"));
+            }
+            Ok((n, fds.pop()))
+        }
+    }
+}
+
+impl From<OwnedFd> for UpgradeChannel {
+    fn from(fd: OwnedFd) -> Self {
+        UpgradeChannel(Socket::from(fd))
+    }
+}
+
+impl From<UpgradeChannel> for OwnedFd {
+    fn from(channel: UpgradeChannel) -> Self {
+        channel.0.into()
+    }
+}
+
+impl AsFd for UpgradeChannel {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+fn socket_index(listener: usize, cpu: usize) -> [u8; 8] {
+    let mut index = [0u8; 8];
+    index[..4].copy_from_slice(&(listener as u32).to_le_bytes());
+    index[4..].copy_from_slice(&(cpu as u32).to_le_bytes());
+    index
+}
+
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if !left.is_zero() => Ok(left),
+        _ => Err(timed_out(io::ErrorKind::WouldBlock.into())),
+    }
+}
+
+/// Socket timeouts surface as `WouldBlock`.
+fn timed_out(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+            io::ErrorKind::TimedOut,
+            "timed out waiting for the other process",
+        ),
+        _ => e,
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Every core's listening socket, by listener and core, for handing them over. A core's
+/// entry is removed as it stops accepting.
+#[derive(Clone)]
+pub(crate) struct ListenerTable(Arc<ListenerTableInner>);
+
+struct ListenerTableInner {
+    fds: Mutex<Vec<Vec<Option<RawFd>>>>,
+    handed_over: AtomicBool,
+}
+
+impl ListenerTable {
+    pub(crate) fn new(listeners: usize, cores: usize) -> Self {
+        ListenerTable(Arc::new(ListenerTableInner {
+            fds: Mutex::new(vec![vec![None; cores]; listeners]),
+            handed_over: AtomicBool::new(false),
+        }))
+    }
+
+    /// Record core `cpu`'s socket for `listener` (`None` if the runtime does not expose
+    /// it) until the returned guard is dropped, which must happen before the socket is
+    /// closed.
+    pub(crate) fn register(&self, listener: usize, cpu: usize, fd: Option<RawFd>) -> Registered {
+        self.0.fds.lock().unwrap()[listener][cpu] = fd;
+        Registered {
+            table: self.clone(),
+            listener,
+            cpu,
+        }
+    }
+
+    /// A duplicate of every core's socket for every listener, or an error if one is not
+    /// registered.
+    pub(crate) fn duplicate(&self) -> io::Result<Vec<Vec<OwnedFd>>> {
+        let fds = self.0.fds.lock().unwrap();
+        fds.iter()
+            .enumerate()
+            .map(|(listener, cores)| {
+                cores
+                    .iter()
+                    .enumerate()
+                    .map(|(cpu, fd)| {
+                        let fd = fd.ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::Unsupported,
+                                format!(
+                                    "listener {listener} on core {cpu} is not accepting or cannot be handed over"
+                                ),
+                            )
+                        })?;
+                        // SAFETY: registered fds stay open until their entry is removed,
+                        // which needs the lock held here.
+                        unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub(crate) fn set_handed_over(&self) {
+        self.0.handed_over.store(true, Ordering::Release);
+    }
+
+    /// `true` once the sockets belong to a new process, which then owns Unix socket
+    /// files as well.
+    pub(crate) fn is_handed_over(&self) -> bool {
+        self.0.handed_over.load(Ordering::Acquire)
+    }
+}
+
+/// A socket recorded in a [`ListenerTable`]; removed on drop.
+pub(crate) struct Registered {
+    table: ListenerTable,
+    listener: usize,
+    cpu: usize,
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.table.0.fds.lock().unwrap()[self.listener][self.cpu] = None;
+    }
+}
@@ -0,0 +1,136 @@
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+use crate::server::runtime::RuntimeTimer;
+
+/// Output of [`select`].
+pub(crate) enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// Poll two futures concurrently and return whichever finishes first.
+///
+/// The futures are borrowed, so the loser can be polled again later.
+pub(crate) async fn select<A, B>(a: Pin<&mut A>, b: Pin<&mut B>) -> Either<A::Output, B::Output>
+where
+    A: Future + ?Sized,
+    B: Future + ?Sized,
+{
+    let (mut a, mut b) = (a, b);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(out) = a.as_mut().poll(cx) {
+            return Poll::Ready(Either::Left(out));
+        }
+        if let Poll::Ready(out) = b.as_mut().poll(cx) {
+            return Poll::Ready(Either::Right(out));
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Run `fut` until `deadline`, returning `None` if the deadline passes first.
+pub(crate) async fn until<T, F>(timer: &T, deadline: Option<Instant>, fut: F) -> Option<F::Output>
+where
+    T: RuntimeTimer,
+    F: Future,
+{
+    let Some(deadline) = deadline else {
+        return Some(fut.await);
+    };
+    let fut = std::pin::pin!(fut);
+    let expired = std::pin::pin!(timer.sleep(deadline.saturating_duration_since(Instant::now())));
+    match select(fut, expired).await {
+        Either::Left(out) => Some(out),
+        Either::Right(()) => None,
+    }
+}
+
+/// Poll all futures concurrently until every one has succeeded or one has failed.
+///
+/// The first error is returned right away and the remaining futures are dropped.
+pub(crate) async fn try_join_all<F, E>(futures: impl IntoIterator<Item = F>) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+{
+    let mut pending: Vec<_> = futures.into_iter().map(|f| Some(Box::pin(f))).collect();
+    std::future::poll_fn(|cx| {
+        for slot in pending.iter_mut() {
+            if let Some(fut) = slot {
+                if let Poll::Ready(result) = fut.as_mut().poll(cx) {
+                    result?;
+                    *slot = None;
+                }
+            }
+        }
+        if pending.iter().all(Option::is_none) {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Adapts a runtime's thread-local sleep future to `hyper::rt::Sleep`, which must be
+/// `Send + Sync`.
+///
+/// A connection is served entirely on the core that accepted it, so the sleep never
+/// leaves its thread in practice. If it did, polling it panics and dropping it leaks
+/// the inner future rather than touching another thread's timer state.
+#[cfg_attr(
+    not(any(feature = "monoio-runtime", feature = "glommio-runtime")),
+    allow(dead_code)
+)]
+pub(crate) struct LocalSleep<F> {
+    inner: ManuallyDrop<F>,
+    thread: ThreadId,
+}
+
+// SAFETY: the inner future is only polled or dropped on the thread that created it.
+unsafe impl<F> Send for LocalSleep<F> {}
+// SAFETY: no method gives shared access to the inner future.
+unsafe impl<F> Sync for LocalSleep<F> {}
+
+#[cfg_attr(
+    not(any(feature = "monoio-runtime", feature = "glommio-runtime")),
+    allow(dead_code)
+)]
+impl<F> LocalSleep<F> {
+    pub(crate) fn new(inner: F) -> Self {
+        LocalSleep {
+            inner: ManuallyDrop::new(inner),
+            thread: thread::current().id(),
+        }
+    }
+}
+
+impl<F: Future<Output = ()>> Future for LocalSleep<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        assert_eq!(
+            self.thread,
+            thread::current().id(),
+            "sleep polled outside its core"
+        );
+        // SAFETY: `inner` is structurally pinned; it is never moved out.
+        unsafe { self.map_unchecked_mut(|sleep| &mut *sleep.inner) }.poll(cx)
+    }
+}
+
+impl<F: Future<Output = ()>> hyper::rt::Sleep for LocalSleep<F> {}
+
+impl<F> Drop for LocalSleep<F> {
+    fn drop(&mut self) {
+        if self.thread == thread::current().id() {
+            // SAFETY: dropped exactly once, in place.
+            unsafe { ManuallyDrop::drop(&mut self.inner) };
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::io;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+
+use http_body::{Body as HttpBody, Frame, SizeHint};
+
+use crate::server::frames::ConnFrames;
+use crate::server::listener::PeerAddr;
+use crate::server::metrics::{ServerMetrics, SlowPeer, WriteTimeoutScope};
+use crate::server::runtime::{RuntimeExecutor, RuntimeTimer};
+use crate::server::util::{select, Either};
+
+/// Write-progress timeouts for slow-reading clients.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct WriteTimeouts {
+    pub(crate) stream: Option<Duration>,
+    pub(crate) connection: Option<Duration>,
+}
+
+impl WriteTimeouts {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.stream.is_some() || self.connection.is_some()
+    }
+}
+
+/// Write-progress state shared by one connection's IO, its response bodies and its
+/// stream tasks. Single-threaded, like the rest of the per-connection state.
+#[derive(Clone)]
+pub(crate) struct ConnProgress(Rc<ConnProgressInner>);
+
+struct ConnProgressInner {
+    timeouts: WriteTimeouts,
+    peer_addr: PeerAddr,
+    cpu: usize,
+    metrics: Arc<ServerMetrics>,
+    last_write: Cell<Instant>,
+    write_pending_since: Cell<Option<Instant>>,
+    blocked_streams: Cell<usize>,
+    blocked_since: Cell<Option<Instant>>,
+    /// Progress of the stream whose service future was created last. hyper spawns the
+    /// stream task right after calling the service, and the executor claims it then.
+    next_stream: Cell<Option<StreamProgress>>,
+}
+
+impl ConnProgress {
+    pub(crate) fn new(
+        timeouts: WriteTimeouts,
+        peer_addr: PeerAddr,
+        cpu: usize,
+        metrics: Arc<ServerMetrics>,
+    ) -> Self {
+        ConnProgress(Rc::new(ConnProgressInner {
+            timeouts,
+            peer_addr,
+            cpu,
+            metrics,
+            last_write: Cell::new(Instant::now()),
+            write_pending_since: Cell::new(None),
+            blocked_streams: Cell::new(0),
+            blocked_since: Cell::new(None),
+            next_stream: Cell::new(None),
+        }))
+    }
+
+    pub(crate) fn connection_timeout(&self) -> Option<Duration> {
+        self.0.timeouts.connection
+    }
+
+    /// How long the connection has had something to write without writing anything.
+    pub(crate) fn stalled_for(&self, now: Instant) -> Option<Duration> {
+        let inner = &self.0;
+        let waiting_since = match (inner.write_pending_since.get(), inner.blocked_since.get()) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b)?,
+        };
+        Some(now.saturating_duration_since(waiting_since.max(inner.last_write.get())))
+    }
+
+    /// Count and log an eviction.
+    pub(crate) fn evicted(&self, scope: WriteTimeoutScope) {
+        let inner = &self.0;
+        tracing::warn!(
+            cpu = inner.cpu,
+            peer = %inner.peer_addr,
+            scope = ?scope,
+            "peer stopped reading, write timeout elapsed"
+        );
+        inner.metrics.write_timeouts().record(SlowPeer {
+            peer_addr: inner.peer_addr,
+            cpu: inner.cpu,
+            scope,
+            at: SystemTime::now(),
+        });
+    }
+
+    fn wrote(&self) {
+        self.0.last_write.set(Instant::now());
+        self.0.write_pending_since.set(None);
+    }
+
+    fn flushed(&self) {
+        self.0.write_pending_since.set(None);
+    }
+
+    fn write_pending(&self) {
+        let since = &self.0.write_pending_since;
+        if since.get().is_none() {
+            since.set(Some(Instant::now()));
+        }
+    }
+
+    fn stream_blocked(&self, now: Instant) {
+        let inner = &self.0;
+        inner.blocked_streams.set(inner.blocked_streams.get() + 1);
+        if inner.blocked_since.get().is_none() {
+            inner.blocked_since.set(Some(now));
+        }
+    }
+
+    fn stream_unblocked(&self) {
+        let inner = &self.0;
+        let blocked = inner.blocked_streams.get() - 1;
+        inner.blocked_streams.set(blocked);
+        if blocked == 0 {
+            inner.blocked_since.set(None);
+        }
+    }
+}
+
+/// Write-progress state of one stream: when hyper last took a data frame and has not
+/// come back for the next one.
+#[derive(Clone, Default)]
+struct StreamProgress(Rc<Cell<Option<Instant>>>);
+
+impl StreamProgress {
+    fn stalled_for(&self, now: Instant) -> Option<Duration> {
+        self.0
+            .get()
+            .map(|since| now.saturating_duration_since(since))
+    }
+}
+
+/// Resolves once `stalled_for` has reported at least `timeout` without progress.
+///
+/// Sleeps on the core's timer and only wakes when a stall could have reached the
+/// timeout, so an active connection costs one timer per `timeout`.
+pub(crate) async fn stall<T, F>(timer: &T, timeout: Duration, stalled_for: F)
+where
+    T: RuntimeTimer,
+    F: Fn(Instant) -> Option<Duration>,
+{
+    let mut wait = timeout;
+    loop {
+        timer.sleep(wait).await;
+        match stalled_for(Instant::now()) {
+            Some(stalled) if stalled >= timeout => return,
+            Some(stalled) => wait = timeout - stalled,
+            None => wait = timeout,
+        }
+    }
+}
+
+/// Per-connection service wrapper that tracks write progress of response bodies.
+pub(crate) struct TrackedService<S> {
+    inner: S,
+    progress: Option<ConnProgress>,
+}
+
+impl<S> TrackedService<S> {
+    pub(crate) fn new(inner: S, progress: Option<ConnProgress>) -> Self {
+        TrackedService { inner, progress }
+    }
+}
+
+impl<S, ReqBody, RespBd> hyper::service::Service<hyper::Request<ReqBody>> for TrackedService<S>
+where
+    S: hyper::service::Service<hyper::Request<ReqBody>, Response = hyper::Response<RespBd>>,
+{
+    type Response = hyper::Response<TrackedBody<RespBd>>;
+    type Error = S::Error;
+    type Future = TrackedFuture<S::Future>;
+
+    fn call(&self, req: hyper::Request<ReqBody>) -> Self::Future {
+        let progress = self.progress.as_ref().map(|conn| {
+            let stream = conn.0.timeouts.stream.map(|_| {
+                let stream = StreamProgress::default();
+                conn.0.next_stream.set(Some(stream.clone()));
+                stream
+            });
+            BodyProgress {
+                conn: conn.clone(),
+                stream,
+                blocked: false,
+            }
+        });
+
+        TrackedFuture {
+            inner: self.inner.call(req),
+            progress,
+        }
+    }
+}
+
+#[pin_project::pin_project]
+pub(crate) struct TrackedFuture<F> {
+    #[pin]
+    inner: F,
+    progress: Option<BodyProgress>,
+}
+
+impl<F, RespBd, E> Future for TrackedFuture<F>
+where
+    F: Future<Output = Result<hyper::Response<RespBd>, E>>,
+{
+    type Output = Result<hyper::Response<TrackedBody<RespBd>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.inner.poll(cx))?;
+        let progress = this.progress.take();
+        Poll::Ready(Ok(response.map(|inner| TrackedBody { inner, progress })))
+    }
+}
+
+/// A response body that records when hyper stops pulling frames from it, which is
+/// what happens once the peer's flow-control window is exhausted.
+#[pin_project::pin_project]
+pub(crate) struct TrackedBody<B> {
+    #[pin]
+    inner: B,
+    progress: Option<BodyProgress>,
+}
+
+struct BodyProgress {
+    conn: ConnProgress,
+    stream: Option<StreamProgress>,
+    blocked: bool,
+}
+
+impl BodyProgress {
+    fn block(&mut self) {
+        let now = Instant::now();
+        self.conn.stream_blocked(now);
+        if let Some(stream) = &self.stream {
+            stream.0.set(Some(now));
+        }
+        self.blocked = true;
+    }
+
+    fn unblock(&mut self) {
+        if std::mem::take(&mut self.blocked) {
+            self.conn.stream_unblocked();
+            if let Some(stream) = &self.stream {
+                stream.0.set(None);
+            }
+        }
+    }
+}
+
+impl Drop for BodyProgress {
+    fn drop(&mut self) {
+        self.unblock();
+    }
+}
+
+impl<B: HttpBody> HttpBody for TrackedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let Some(progress) = this.progress else {
+            return this.inner.poll_frame(cx);
+        };
+
+        // hyper only polls again once it has window to send more.
+        progress.unblock();
+        let frame = ready!(this.inner.poll_frame(cx));
+        if matches!(&frame, Some(Ok(frame)) if frame.is_data()) {
+            progress.block();
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Executor handed to hyper for one connection. Spawns stream tasks on the core's
+/// executor and, with a stream write timeout, resets streams that stall.
+#[derive(Clone)]
+pub(crate) struct StreamExec<E, T> {
+    exec: E,
+    timer: T,
+    progress: Option<ConnProgress>,
+    frames: Option<ConnFrames>,
+}
+
+impl<E, T> StreamExec<E, T> {
+    pub(crate) fn new(
+        exec: E,
+        timer: T,
+        progress: Option<ConnProgress>,
+        frames: Option<ConnFrames>,
+    ) -> Self {
+        StreamExec {
+            exec,
+            timer,
+            progress,
+            frames,
+        }
+    }
+}
+
+impl<E, T, F> hyper::rt::Executor<F> for StreamExec<E, T>
+where
+    E: RuntimeExecutor,
+    T: RuntimeTimer,
+    F: Future<Output = ()> + 'static,
+{
+    fn execute(&self, fut: F) {
+        let open = self.frames.as_ref().map(ConnFrames::stream_opened);
+        let fut = async move {
+            let _open = open;
+            fut.await
+        };
+        let watched = self.progress.as_ref().and_then(|conn| {
+            let timeout = conn.0.timeouts.stream?;
+            let stream = conn.0.next_stream.take()?;
+            Some((conn.clone(), stream, timeout))
+        });
+
+        match watched {
+            Some((conn, stream, timeout)) => {
+                let timer = self.timer.clone();
+                self.exec.spawn(async move {
+                    let mut fut = pin!(fut);
+                    let mut stalled = pin!(stall(&timer, timeout, |now| stream.stalled_for(now)));
+                    if let Either::Right(()) = select(fut.as_mut(), stalled.as_mut()).await {
+                        // Dropping the stream task makes h2 reset the stream (CANCEL).
+                        conn.evicted(WriteTimeoutScope::Stream);
+                    }
+                });
+            }
+            None => self.exec.spawn(fut),
+        }
+    }
+}
+
+/// Connection IO that records socket write progress.
+pub(crate) struct ProgressIo<I> {
+    io: I,
+    progress: Option<ConnProgress>,
+}
+
+impl<I> ProgressIo<I> {
+    pub(crate) fn new(io: I, progress: Option<ConnProgress>) -> Self {
+        ProgressIo { io, progress }
+    }
+
+    fn record<T>(&self, poll: &Poll<io::Result<T>>) {
+        if let Some(progress) = &self.progress {
+            match poll {
+                Poll::Ready(Ok(_)) => progress.wrote(),
+                Poll::Pending => progress.write_pending(),
+                Poll::Ready(Err(_)) => {}
+            }
+        }
+    }
+}
+
+impl<I: hyper::rt::Read + Unpin> hyper::rt::Read for ProgressIo<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl<I: hyper::rt::Write + Unpin> hyper::rt::Write for ProgressIo<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.io).poll_write(cx, buf);
+        this.record(&poll);
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.io).poll_flush(cx);
+        if let Some(progress) = &this.progress {
+            // A completed flush only shows nothing is left queued; it is not new progress.
+            match &poll {
+                Poll::Ready(Ok(())) => progress.flushed(),
+                Poll::Pending => progress.write_pending(),
+                Poll::Ready(Err(_)) => {}
+            }
+        }
+        poll
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.io).poll_write_vectored(cx, bufs);
+        this.record(&poll);
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::server::gmf_server::block_on_simple;
+
+    /// Sleeps by blocking the thread, and records every sleep it is asked for.
+    #[derive(Clone, Default)]
+    struct TestTimer(Rc<RefCell<Vec<Duration>>>);
+
+    impl RuntimeTimer for TestTimer {
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+            self.0.borrow_mut().push(duration);
+            async move { std::thread::sleep(duration) }
+        }
+
+        fn hyper_sleep(&self, _duration: Duration) -> Pin<Box<dyn hyper::rt::Sleep>> {
+            unreachable!("not used by `stall`")
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn progress(timeouts: WriteTimeouts) -> (ConnProgress, Arc<ServerMetrics>) {
+        let metrics = Arc::new(ServerMetrics::new(1));
+        let peer = PeerAddr::Tcp("127.0.0.1:4000".parse().unwrap());
+        (
+            ConnProgress::new(timeouts, peer, 0, metrics.clone()),
+            metrics,
+        )
+    }
+
+    #[test]
+    fn stall_waits_out_the_remaining_timeout() {
+        let timer = TestTimer::default();
+        let reports = RefCell::new(vec![
+            Some(TIMEOUT),
+            Some(Duration::from_millis(30)),
+            None,
+            Some(Duration::from_millis(10)),
+        ]);
+        block_on_simple(stall(&timer, TIMEOUT, |_| {
+            reports.borrow_mut().pop().unwrap()
+        }));
+        // A stall that started 10ms before the first check is due 40ms later; no stall
+        // waits a whole timeout; the last check finds the timeout reached.
+        assert_eq!(
+            *timer.0.borrow(),
+            [50, 40, 50, 20].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn stalled_connection_is_evicted_after_timeout() {
+        let timer = TestTimer::default();
+        let (progress, metrics) = progress(WriteTimeouts {
+            stream: None,
+            connection: Some(TIMEOUT),
+        });
+        assert_eq!(progress.stalled_for(Instant::now()), None);
+
+        let started = Instant::now();
+        progress.write_pending();
+        block_on_simple(stall(&timer, TIMEOUT, |now| progress.stalled_for(now)));
+        assert!(started.elapsed() >= TIMEOUT);
+        assert_eq!(timer.0.borrow().len(), 1);
+
+        progress.evicted(WriteTimeoutScope::Connection);
+        let writes = metrics.write_timeouts();
+        assert_eq!(
+            (writes.connections_closed(), writes.streams_reset()),
+            (1, 0)
+        );
+        let slow = writes.recent_slow_peers();
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].scope, WriteTimeoutScope::Connection);
+        assert_eq!(
+            slow[0].peer_addr,
+            PeerAddr::Tcp("127.0.0.1:4000".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn writes_reset_the_stall() {
+        let (progress, _) = progress(WriteTimeouts {
+            stream: None,
+            connection: Some(TIMEOUT),
+        });
+        progress.write_pending();
+        std::thread::sleep(Duration::from_millis(20));
+        let stalled = progress.stalled_for(Instant::now()).unwrap();
+        assert!(stalled >= Duration::from_millis(20));
+
+        progress.wrote();
+        assert_eq!(progress.stalled_for(Instant::now()), None);
+
+        let now = Instant::now();
+        progress.stream_blocked(now);
+        progress.stream_blocked(now);
+        progress.stream_unblocked();
+        assert!(progress.stalled_for(now).is_some());
+        progress.stream_unblocked();
+        assert_eq!(progress.stalled_for(now), None);
+    }
+}
@@ -0,0 +1,218 @@
+#![allow(dead_code)]
+
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+
+/// The HTTP/2 client preface followed by an empty `SETTINGS` frame.
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0\0\x04\0\0\0\0\0";
+
+/// A service answering every request with `200 OK` and body `ok`.
+pub fn ok_service() -> impl tower_service::Service<
+    hyper::Request<Incoming>,
+    Response = hyper::Response<Full<Bytes>>,
+    Error = Infallible,
+    Future = impl Send + 'static,
+> + Clone
+       + Send
+       + 'static {
+    tower::service_fn(|_request: hyper::Request<Incoming>| async {
+        Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::from_static(b"ok"))))
+    })
+}
+
+/// Open an HTTP/2 connection and wait for the server's `SETTINGS` frame. Returns
+/// `None` if the server closed the connection instead.
+pub fn h2_connect(addr: SocketAddr) -> io::Result<Option<TcpStream>> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(PREFACE)?;
+    match read_frame(&mut stream) {
+        Ok((0x4, _, _, _)) => Ok(Some(stream)),
+        Ok(frame) => Err(io::Error::other(format!("unexpected frame {frame:?}"))),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Retry `f` until it returns `Some` or `timeout` passes.
+pub fn eventually<T>(timeout: Duration, mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(value) = f() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Read one HTTP/2 frame: its type, flags, stream id and payload.
+pub fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, u8, u32, Vec<u8>)> {
+    let mut header = [0u8; 9];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & !(1 << 31);
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    Ok((header[3], header[4], stream_id, payload))
+}
+
+/// Read frames until a `GOAWAY`, returning its error code. `None` if the server closed
+/// the connection without one. `PING`s are acknowledged on the way, as a graceful
+/// shutdown waits for one round trip before its final `GOAWAY`.
+pub fn read_goaway(stream: &mut TcpStream) -> io::Result<Option<u32>> {
+    loop {
+        match read_frame(stream) {
+            Ok((0x7, _, _, payload)) => {
+                return Ok(Some(u32::from_be_bytes(payload[4..8].try_into().unwrap())))
+            }
+            Ok((0x6, 0, _, payload)) => {
+                stream.write_all(&[0, 0, 8, 0x6, 0x1, 0, 0, 0, 0])?;
+                stream.write_all(&payload)?;
+            }
+            Ok(_) => continue,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether the server has closed `stream`, reading and discarding whatever it sends
+/// first.
+pub fn is_closed(stream: &mut TcpStream) -> bool {
+    let mut buf = [0u8; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return true,
+            Ok(_) => continue,
+            Err(e) => return e.kind() == io::ErrorKind::ConnectionReset,
+        }
+    }
+}
+
+/// A future that completes `duration` after it is first polled, on any runtime.
+pub fn delay(duration: Duration) -> impl std::future::Future<Output = ()> + Send {
+    let mut deadline = None;
+    std::future::poll_fn(move |cx| {
+        let deadline = *deadline.get_or_insert_with(|| {
+            let waker = cx.waker().clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                waker.wake();
+            });
+            Instant::now() + duration
+        });
+        match Instant::now() >= deadline {
+            true => std::task::Poll::Ready(()),
+            false => std::task::Poll::Pending,
+        }
+    })
+}
+
+/// A current-thread tokio runtime to drive test clients.
+pub fn client_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Open a hyper HTTP/2 client connection to `addr`, driven on the current runtime.
+pub async fn h2_client(
+    addr: SocketAddr,
+) -> (
+    hyper::client::conn::http2::SendRequest<http_body_util::Empty<Bytes>>,
+    tokio::task::JoinHandle<()>,
+) {
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (sender, connection) = hyper::client::conn::http2::handshake(
+        hyper_util::rt::TokioExecutor::new(),
+        hyper_util::rt::TokioIo::new(stream),
+    )
+    .await
+    .unwrap();
+    let connection = tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    (sender, connection)
+}
+
+/// Send a `GET` for `path` and collect the response body.
+pub async fn get(
+    sender: &mut hyper::client::conn::http2::SendRequest<http_body_util::Empty<Bytes>>,
+    path: &str,
+) -> Result<Bytes, String> {
+    use http_body_util::BodyExt;
+
+    let request = http::Request::builder()
+        .uri(format!("http://localhost{path}"))
+        .body(http_body_util::Empty::new())
+        .unwrap();
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| e.to_string())?;
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(body.to_bytes())
+}
+
+/// One `GET /` on a new HTTP/2 connection over `io`, which is closed again before
+/// returning so it does not hold up the server's drain.
+pub async fn get_once<I>(io: I) -> Bytes
+where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http2::handshake(
+        hyper_util::rt::TokioExecutor::new(),
+        hyper_util::rt::TokioIo::new(io),
+    )
+    .await
+    .unwrap();
+    let connection = tokio::spawn(connection);
+    let body = get(&mut sender, "/").await.unwrap();
+    drop(sender);
+    connection.await.unwrap().unwrap();
+    body
+}
+
+/// One `GET` for `path` on a connection of its own, from a thread of its own so the
+/// caller can act on the server meanwhile.
+pub fn spawn_get(
+    addr: SocketAddr,
+    path: &'static str,
+) -> std::thread::JoinHandle<Result<Bytes, String>> {
+    std::thread::spawn(move || {
+        client_runtime().block_on(async {
+            let (mut sender, _connection) = h2_client(addr).await;
+            get(&mut sender, path).await
+        })
+    })
+}
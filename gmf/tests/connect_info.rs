@@ -0,0 +1,135 @@
+mod common;
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use gmf::server::connect_info::GmfConnectInfo;
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::listener::{ListenAddr, PeerAddr, PeerCredentials, UnixAddr};
+use gmf::server::runtime::Runtime;
+use http_body_util::Full;
+use hyper::body::Incoming;
+
+use common::{client_runtime, get_once};
+
+/// What a handler saw of one request's connection.
+struct Seen {
+    /// `tonic::Request::remote_addr()` and `local_addr()`.
+    remote_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    info: GmfConnectInfo,
+    credentials: Option<PeerCredentials>,
+}
+
+/// Handlers see the client and listener addresses through tonic's `Request` and
+/// through [`GmfConnectInfo`], for TCP and Unix socket clients. `credentials` is
+/// whether the runtime reports Unix peer credentials.
+fn reports_connection<R: Runtime>(credentials: bool) {
+    let dir = tempfile::tempdir().unwrap();
+    let unix = UnixAddr::path(dir.path().join("grpc.sock"));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let record = seen.clone();
+    let handle = GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .add_listener(unix)
+        .num_cores(1)
+        .build()
+        .start(tower::service_fn(
+            move |request: hyper::Request<Incoming>| {
+                let record = record.clone();
+                async move {
+                    let credentials = request.extensions().get::<PeerCredentials>().copied();
+                    let request = tonic::Request::from_http(request);
+                    record.lock().unwrap().push(Seen {
+                        remote_addr: request.remote_addr(),
+                        local_addr: request.local_addr(),
+                        info: request
+                            .extensions()
+                            .get::<GmfConnectInfo>()
+                            .unwrap()
+                            .clone(),
+                        credentials,
+                    });
+                    Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::new())))
+                }
+            },
+        ))
+        .unwrap();
+    let addr = handle.local_addr();
+    let ListenAddr::Unix(UnixAddr::Path(path)) = &handle.listener_addrs()[1][0] else {
+        panic!("not a Unix socket path");
+    };
+
+    let client = client_runtime();
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        client.block_on(async {
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            clients.push(stream.local_addr().unwrap());
+            get_once(stream).await
+        });
+    }
+    client.block_on(async { get_once(tokio::net::UnixStream::connect(path).await.unwrap()).await });
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 3);
+    for (seen, client) in seen.iter().zip(&clients) {
+        assert_eq!(seen.remote_addr, Some(*client));
+        assert_eq!(seen.local_addr, Some(addr));
+        assert_eq!(seen.info.peer_addr(), PeerAddr::Tcp(*client));
+        assert_eq!(seen.info.remote_addr(), Some(*client));
+        assert_eq!(seen.info.local_addr(), &ListenAddr::Tcp(addr));
+        assert_eq!(seen.info.cpu(), 0);
+        assert!(seen.info.proxy_header().is_none());
+        assert!(seen.credentials.is_none());
+    }
+    assert_ne!(seen[0].info.connection_id(), seen[1].info.connection_id());
+
+    let unix = &seen[2];
+    assert_eq!((unix.remote_addr, unix.local_addr), (None, None));
+    assert_eq!(unix.info.remote_addr(), None);
+    assert_eq!(unix.info.local_addr(), &handle.listener_addrs()[1][0]);
+    assert_eq!(unix.info.peer_addr(), PeerAddr::Unix(unix.credentials));
+    match unix.credentials {
+        Some(cred) => assert_eq!(cred.pid as u32, std::process::id()),
+        None => assert!(!credentials, "no peer credentials"),
+    }
+    drop(seen);
+
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn reports_connection() {
+        super::reports_connection::<R>(true);
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn reports_connection() {
+        super::reports_connection::<R>(true);
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn reports_connection() {
+        super::reports_connection::<R>(false);
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use gmf::server::gmf_server::{GmfServer, GmfServerBuilder};
+use gmf::server::handle::ServerHandle;
+use gmf::server::metrics::HandshakeStage;
+use gmf::server::runtime::Runtime;
+
+use common::{eventually, h2_connect, is_closed, ok_service, read_frame};
+
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+fn start<R: Runtime>(builder: GmfServerBuilder<R>) -> ServerHandle {
+    builder
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .handshake_timeout(TIMEOUT)
+        .build()
+        .start(ok_service())
+        .unwrap()
+}
+
+/// Connect, send `sent`, and check the server closes the connection at the handshake
+/// deadline.
+fn closed_at_deadline(handle: &ServerHandle, sent: &[u8]) {
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(sent).unwrap();
+    assert!(is_closed(&mut stream), "connection left open");
+    let elapsed = started.elapsed();
+    assert!(elapsed >= TIMEOUT, "closed early, after {elapsed:?}");
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// Clients that never send the HTTP/2 preface, or stop partway through it, are closed
+/// at the deadline and counted; a client that completes it in time is kept.
+fn closes_stalled_handshakes<R: Runtime>() {
+    let handle = start(GmfServer::<R>::builder());
+    let metrics = handle.metrics().clone();
+
+    closed_at_deadline(&handle, b"");
+    closed_at_deadline(&handle, b"PRI * HTTP/2.0\r\n");
+    let counted = eventually(Duration::from_secs(5), || {
+        (metrics.handshake_timeouts().count(0, HandshakeStage::Http2) == 2).then_some(())
+    });
+    assert!(counted.is_some(), "timeouts not counted");
+
+    let mut client = h2_connect(handle.local_addr())
+        .unwrap()
+        .expect("connection refused");
+    std::thread::sleep(TIMEOUT * 2);
+    client
+        .write_all(&[0, 0, 8, 0x6, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8])
+        .unwrap();
+    let ack = loop {
+        match read_frame(&mut client).unwrap() {
+            (0x6, 0x1, _, payload) => break payload,
+            _ => continue,
+        }
+    };
+    assert_eq!(ack, [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(metrics.handshake_timeouts().total(), 2);
+
+    drop(client);
+    stop(handle);
+}
+
+/// On a PROXY protocol listener the deadline also covers the header.
+fn closes_stalled_proxy_header<R: Runtime>() {
+    let handle = start(GmfServer::<R>::builder().proxy_protocol());
+    let metrics = handle.metrics().clone();
+
+    closed_at_deadline(&handle, b"PROXY TCP4 ");
+    let counted = eventually(Duration::from_secs(5), || {
+        (metrics.handshake_timeouts().count(0, HandshakeStage::Proxy) == 1).then_some(())
+    });
+    assert!(counted.is_some(), "timeout not counted");
+    assert_eq!(metrics.handshake_timeouts().per_core(), [1]);
+    stop(handle);
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn closes_stalled_handshakes() {
+        super::closes_stalled_handshakes::<R>();
+    }
+
+    #[test]
+    fn closes_stalled_proxy_header() {
+        super::closes_stalled_proxy_header::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn closes_stalled_handshakes() {
+        super::closes_stalled_handshakes::<R>();
+    }
+
+    #[test]
+    fn closes_stalled_proxy_header() {
+        super::closes_stalled_proxy_header::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn closes_stalled_handshakes() {
+        super::closes_stalled_handshakes::<R>();
+    }
+
+    #[test]
+    fn closes_stalled_proxy_header() {
+        super::closes_stalled_proxy_header::<R>();
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use gmf::server::gmf_server::{GmfServer, GmfServerBuilder};
+use gmf::server::runtime::Runtime;
+
+use common::{ok_service, read_frame, PREFACE};
+
+const MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const INITIAL_WINDOW_SIZE: u16 = 0x4;
+const MAX_FRAME_SIZE: u16 = 0x5;
+const MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+/// The settings a server built from `builder` advertises in its first `SETTINGS`
+/// frame, and the connection window it grants beyond the default 65535 bytes with
+/// the `WINDOW_UPDATE` that follows, if any.
+fn advertised<R: Runtime>(builder: GmfServerBuilder<R>) -> (HashMap<u16, u32>, Option<u32>) {
+    let handle = builder
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .build()
+        .start(ok_service())
+        .unwrap();
+    let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    stream.write_all(PREFACE).unwrap();
+
+    let (kind, _, _, payload) = read_frame(&mut stream).unwrap();
+    assert_eq!(kind, 0x4, "the server's first frame is not SETTINGS");
+    let settings = payload
+        .chunks(6)
+        .map(|s| {
+            let id = u16::from_be_bytes([s[0], s[1]]);
+            (id, u32::from_be_bytes([s[2], s[3], s[4], s[5]]))
+        })
+        .collect();
+    let mut window_update = None;
+    while let Ok((kind, _, stream_id, payload)) = read_frame(&mut stream) {
+        if (kind, stream_id) == (0x8, 0) {
+            window_update = Some(u32::from_be_bytes(payload[..4].try_into().unwrap()));
+            break;
+        }
+    }
+
+    drop(stream);
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+    (settings, window_update)
+}
+
+/// The HTTP/2 builder settings reach the client in the server's `SETTINGS` frame.
+fn advertises_settings<R: Runtime>() {
+    let (settings, window_update) = advertised(
+        GmfServer::<R>::builder()
+            .http2_initial_stream_window_size(1 << 20)
+            .http2_initial_connection_window_size(2 << 20)
+            .http2_max_concurrent_streams(50)
+            .http2_max_frame_size(32 * 1024)
+            .http2_max_header_list_size(8 * 1024),
+    );
+    assert_eq!(settings[&INITIAL_WINDOW_SIZE], 1 << 20);
+    assert_eq!(settings[&MAX_CONCURRENT_STREAMS], 50);
+    assert_eq!(settings[&MAX_FRAME_SIZE], 32 * 1024);
+    assert_eq!(settings[&MAX_HEADER_LIST_SIZE], 8 * 1024);
+    // The connection window is not a setting; it is raised with a WINDOW_UPDATE.
+    assert_eq!(window_update, Some((2 << 20) - 65_535));
+}
+
+/// Out-of-range sizes are clamped to what HTTP/2 allows.
+fn clamps_settings<R: Runtime>() {
+    let (settings, _) = advertised(
+        GmfServer::<R>::builder()
+            .http2_initial_stream_window_size(u32::MAX)
+            .http2_max_frame_size(u32::MAX),
+    );
+    assert_eq!(settings[&INITIAL_WINDOW_SIZE], (1 << 31) - 1);
+    assert_eq!(settings[&MAX_FRAME_SIZE], (1 << 24) - 1);
+
+    let (settings, _) = advertised(GmfServer::<R>::builder().http2_max_frame_size(1));
+    // 16 KiB is the protocol default, so it need not be advertised.
+    assert!(settings
+        .get(&MAX_FRAME_SIZE)
+        .is_none_or(|&size| size == 16 * 1024));
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn advertises_settings() {
+        super::advertises_settings::<R>();
+    }
+
+    #[test]
+    fn clamps_settings() {
+        super::clamps_settings::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn advertises_settings() {
+        super::advertises_settings::<R>();
+    }
+
+    #[test]
+    fn clamps_settings() {
+        super::clamps_settings::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn advertises_settings() {
+        super::advertises_settings::<R>();
+    }
+
+    #[test]
+    fn clamps_settings() {
+        super::clamps_settings::<R>();
+    }
+}
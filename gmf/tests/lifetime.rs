@@ -0,0 +1,203 @@
+mod common;
+
+use std::convert::Infallible;
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use gmf::server::gmf_server::{GmfServer, GmfServerBuilder};
+use gmf::server::handle::ServerHandle;
+use gmf::server::runtime::Runtime;
+use http_body_util::Full;
+use hyper::body::Incoming;
+
+use common::{delay, h2_connect, is_closed, read_frame, read_goaway};
+
+/// Answers `/slow/{ms}` after that many milliseconds, anything else right away.
+fn start<R: Runtime>(builder: GmfServerBuilder<R>) -> ServerHandle {
+    builder
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .build()
+        .start(tower::service_fn(
+            |request: hyper::Request<Incoming>| async move {
+                if let Some(ms) = request.uri().path().strip_prefix("/slow/") {
+                    delay(Duration::from_millis(ms.parse().unwrap())).await;
+                }
+                Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::from_static(b"done"))))
+            },
+        ))
+        .unwrap()
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// Open stream 1 with a `GET` for `path` (HPACK: `:method GET`, `:scheme http` from
+/// the static table and `:path` as a literal).
+fn send_get(stream: &mut TcpStream, path: &str) {
+    let mut block = vec![0x82, 0x86, 0x04, path.len() as u8];
+    block.extend_from_slice(path.as_bytes());
+    let mut frame = vec![0, 0, block.len() as u8, 0x1, 0x5, 0, 0, 0, 1];
+    frame.extend_from_slice(&block);
+    stream.write_all(&frame).unwrap();
+}
+
+/// What the server sent on a connection until it closed it.
+#[derive(Debug, Default)]
+struct Received {
+    /// The last stream id of each GOAWAY, and whether a response had started by then.
+    goaways: Vec<(u32, bool)>,
+    response: bool,
+    body: Vec<u8>,
+}
+
+/// Read frames until the server closes the connection, acknowledging its PINGs.
+fn receive(stream: &mut TcpStream) -> Received {
+    let mut received = Received::default();
+    loop {
+        match read_frame(stream) {
+            Ok((0x1, _, 1, _)) => received.response = true,
+            Ok((0x0, _, 1, payload)) => received.body.extend_from_slice(&payload),
+            Ok((0x7, _, _, payload)) => {
+                let last_stream_id = u32::from_be_bytes(payload[..4].try_into().unwrap());
+                received.goaways.push((last_stream_id, received.response));
+            }
+            Ok((0x6, 0, _, payload)) => {
+                stream.write_all(&[0, 0, 8, 0x6, 0x1, 0, 0, 0, 0]).unwrap();
+                stream.write_all(&payload).unwrap();
+            }
+            Ok(_) => continue,
+            Err(_) => return received,
+        }
+    }
+}
+
+/// A connection without open streams is sent GOAWAY once it has been idle for
+/// `max_connection_idle`, then closed.
+fn retires_idle_connection<R: Runtime>() {
+    let max_idle = Duration::from_millis(200);
+    let handle = start(GmfServer::<R>::builder().max_connection_idle(max_idle));
+    let started = Instant::now();
+    let mut client = h2_connect(handle.local_addr())
+        .unwrap()
+        .expect("connection refused");
+
+    assert_eq!(read_goaway(&mut client).unwrap(), Some(0));
+    assert!(started.elapsed() >= max_idle);
+    // The final GOAWAY follows the PING round trip, and the connection closes.
+    assert_eq!(read_goaway(&mut client).unwrap(), Some(0));
+    assert!(is_closed(&mut client));
+    stop(handle);
+}
+
+/// A connection that reaches `max_connection_age` is sent GOAWAY, but an RPC already
+/// in flight still completes within the grace period.
+fn age_lets_in_flight_rpc_finish<R: Runtime>() {
+    let handle = start(
+        GmfServer::<R>::builder()
+            .max_connection_age(Duration::from_millis(200))
+            .max_connection_age_grace(Duration::from_secs(5)),
+    );
+    let mut client = h2_connect(handle.local_addr())
+        .unwrap()
+        .expect("connection refused");
+    send_get(&mut client, "/slow/1000");
+
+    let received = receive(&mut client);
+    assert!(!received.goaways.is_empty(), "no GOAWAY");
+    assert!(
+        !received.goaways[0].1,
+        "GOAWAY only sent after the response started"
+    );
+    // The final GOAWAY keeps the in-flight stream.
+    assert_eq!(received.goaways.last().unwrap().0, 1);
+    assert!(received.response);
+    assert_eq!(received.body, b"done");
+    stop(handle);
+}
+
+/// Streams still running when the grace period ends are cut off with the connection.
+fn grace_period_ends_streams<R: Runtime>() {
+    let handle = start(
+        GmfServer::<R>::builder()
+            .max_connection_age(Duration::from_millis(200))
+            .max_connection_age_grace(Duration::from_millis(300)),
+    );
+    let started = Instant::now();
+    let mut client = h2_connect(handle.local_addr())
+        .unwrap()
+        .expect("connection refused");
+    send_get(&mut client, "/slow/5000");
+
+    let received = receive(&mut client);
+    assert!(!received.goaways.is_empty(), "no GOAWAY");
+    assert!(!received.response);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    stop(handle);
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn retires_idle_connection() {
+        super::retires_idle_connection::<R>();
+    }
+
+    #[test]
+    fn age_lets_in_flight_rpc_finish() {
+        super::age_lets_in_flight_rpc_finish::<R>();
+    }
+
+    #[test]
+    fn grace_period_ends_streams() {
+        super::grace_period_ends_streams::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn retires_idle_connection() {
+        super::retires_idle_connection::<R>();
+    }
+
+    #[test]
+    fn age_lets_in_flight_rpc_finish() {
+        super::age_lets_in_flight_rpc_finish::<R>();
+    }
+
+    #[test]
+    fn grace_period_ends_streams() {
+        super::grace_period_ends_streams::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn retires_idle_connection() {
+        super::retires_idle_connection::<R>();
+    }
+
+    #[test]
+    fn age_lets_in_flight_rpc_finish() {
+        super::age_lets_in_flight_rpc_finish::<R>();
+    }
+
+    #[test]
+    fn grace_period_ends_streams() {
+        super::grace_period_ends_streams::<R>();
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use gmf::server::connect_info::GmfConnectInfo;
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::listener::{ListenAddr, UnixAddr};
+use gmf::server::runtime::Runtime;
+use http_body_util::Full;
+use hyper::body::Incoming;
+
+use common::{client_runtime, get_once};
+
+/// Every core accepts on every listener, TCP and Unix alike, and all of them feed the
+/// one service, which sees which listener a connection came in on.
+fn serves_every_listener<R: Runtime>() {
+    let dir = tempfile::tempdir().unwrap();
+    let handle = GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .add_listener("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+        .add_listener(UnixAddr::path(dir.path().join("grpc.sock")))
+        .num_cores(2)
+        .build()
+        .start(tower::service_fn(
+            |request: hyper::Request<Incoming>| async move {
+                let info = request.extensions().get::<GmfConnectInfo>().unwrap();
+                let body = info.local_addr().to_string();
+                Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::from(body))))
+            },
+        ))
+        .unwrap();
+
+    let listeners = handle.listener_addrs();
+    assert_eq!(listeners.len(), 3);
+    assert!(listeners.iter().all(|cores| cores.len() == 2));
+    let (first, second) = (listeners[0][0].as_tcp(), listeners[1][0].as_tcp());
+    assert_ne!(first.unwrap().port(), second.unwrap().port());
+
+    let client = client_runtime();
+    for addr in listeners.iter().flatten() {
+        let body = client.block_on(async {
+            match addr {
+                ListenAddr::Tcp(addr) => {
+                    get_once(tokio::net::TcpStream::connect(addr).await.unwrap()).await
+                }
+                ListenAddr::Unix(UnixAddr::Path(path)) => {
+                    get_once(tokio::net::UnixStream::connect(path).await.unwrap()).await
+                }
+                ListenAddr::Unix(UnixAddr::Abstract(_)) => unreachable!(),
+            }
+        });
+        assert_eq!(body, addr.to_string());
+    }
+
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn serves_every_listener() {
+        super::serves_every_listener::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn serves_every_listener() {
+        super::serves_every_listener::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn serves_every_listener() {
+        super::serves_every_listener::<R>();
+    }
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use gmf::server::config::{GoAwayReason, OverloadPolicy};
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::handle::ServerHandle;
+use gmf::server::metrics::HandshakeStage;
+use gmf::server::runtime::Runtime;
+
+use common::{eventually, h2_connect, is_closed, ok_service, read_frame, read_goaway, PREFACE};
+
+/// Fill a core's `max_connections`, check the next connection is turned away, then
+/// disconnect and check a new one is admitted again.
+fn permits_return_after_disconnect<R: Runtime>() {
+    let handle = GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .max_connections(2)
+        .build()
+        .start(ok_service())
+        .unwrap();
+    let addr = handle.local_addr();
+
+    let first = h2_connect(addr).unwrap().expect("first connection refused");
+    let second = h2_connect(addr)
+        .unwrap()
+        .expect("second connection refused");
+    assert!(
+        h2_connect(addr).unwrap().is_none(),
+        "third connection admitted"
+    );
+
+    drop(first);
+    drop(second);
+    let admitted = eventually(Duration::from_secs(5), || h2_connect(addr).unwrap());
+    assert!(
+        admitted.is_some(),
+        "permits were not returned after disconnect"
+    );
+
+    drop(admitted);
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// A one-core server admitting one connection at a time, under `policy`.
+fn start<R: Runtime>(policy: OverloadPolicy) -> ServerHandle {
+    GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .max_connections(1)
+        .overload_policy(policy)
+        .build()
+        .start(ok_service())
+        .unwrap()
+}
+
+/// Connect and send the client preface without waiting for the server.
+fn connect(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(PREFACE).unwrap();
+    stream
+}
+
+/// Whether the server's `SETTINGS` frame arrives on `stream` within `timeout`, i.e.
+/// whether the connection is being served.
+fn served_within(stream: &mut TcpStream, timeout: Duration) -> bool {
+    stream.set_read_timeout(Some(timeout)).unwrap();
+    matches!(read_frame(stream), Ok((0x4, 0, _, _)))
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// Under `Pause` a connection over the limit waits in the accept queue, unserved but
+/// not closed, until a slot frees up.
+fn pause_holds_connections_in_the_backlog<R: Runtime>() {
+    let handle = start::<R>(OverloadPolicy::Pause);
+    let addr = handle.local_addr();
+    let first = h2_connect(addr).unwrap().expect("first connection refused");
+
+    let mut second = connect(addr);
+    assert!(!served_within(&mut second, Duration::from_millis(300)));
+    drop(first);
+    assert!(served_within(&mut second, Duration::from_secs(5)));
+
+    drop(second);
+    stop(handle);
+}
+
+/// Under `Wait` a connection over the limit is served if a slot frees up within the
+/// timeout, and closed unserved otherwise.
+fn wait_serves_or_closes_after_timeout<R: Runtime>() {
+    let timeout = Duration::from_millis(400);
+    let handle = start::<R>(OverloadPolicy::Wait(timeout));
+    let addr = handle.local_addr();
+    let first = h2_connect(addr).unwrap().expect("first connection refused");
+
+    let started = Instant::now();
+    let mut second = connect(addr);
+    second
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    assert!(is_closed(&mut second), "waiting connection left open");
+    assert!(started.elapsed() >= timeout);
+
+    let mut third = connect(addr);
+    std::thread::sleep(Duration::from_millis(100));
+    drop(first);
+    assert!(served_within(&mut third, Duration::from_secs(5)));
+
+    drop(third);
+    stop(handle);
+}
+
+/// Under `GoAway` a connection over the limit completes the handshake, learns it may
+/// open no streams, and is sent GOAWAY with the configured code. A client that never
+/// completes the handshake is closed once `REFUSE_TIMEOUT` (10s) passes.
+fn goaway_refuses_with_reason<R: Runtime>() {
+    for (reason, code) in [
+        (GoAwayReason::RefusedStream, 0x7),
+        (GoAwayReason::EnhanceYourCalm, 0xb),
+    ] {
+        let handle = start::<R>(OverloadPolicy::GoAway(reason));
+        let addr = handle.local_addr();
+        let first = h2_connect(addr).unwrap().expect("first connection refused");
+
+        let mut refused = connect(addr);
+        refused
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (kind, _, _, settings) = read_frame(&mut refused).unwrap();
+        // SETTINGS_MAX_CONCURRENT_STREAMS = 0.
+        assert_eq!((kind, &settings[..]), (0x4, &[0, 0x3, 0, 0, 0, 0][..]));
+        assert_eq!(read_goaway(&mut refused).unwrap(), Some(code));
+        assert!(is_closed(&mut refused));
+
+        drop(first);
+        stop(handle);
+    }
+
+    let handle = start::<R>(OverloadPolicy::GoAway(GoAwayReason::RefusedStream));
+    let addr = handle.local_addr();
+    let first = h2_connect(addr).unwrap().expect("first connection refused");
+    let started = Instant::now();
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    stalled
+        .set_read_timeout(Some(Duration::from_secs(15)))
+        .unwrap();
+    assert!(is_closed(&mut stalled), "stalled connection left open");
+    assert!(started.elapsed() >= Duration::from_secs(10));
+    let metrics = handle.metrics();
+    let counted = eventually(Duration::from_secs(5), || {
+        (metrics.handshake_timeouts().count(0, HandshakeStage::Http2) == 1).then_some(())
+    });
+    assert!(counted.is_some(), "timeout not counted");
+
+    drop(first);
+    stop(handle);
+}
+
+#[cfg(feature = "monoio-runtime")]
+#[test]
+fn monoio_permits_return_after_disconnect() {
+    permits_return_after_disconnect::<gmf::server::monoio_runtime::MonoioRuntime>();
+}
+
+#[cfg(feature = "tokio-runtime")]
+#[test]
+fn tokio_permits_return_after_disconnect() {
+    permits_return_after_disconnect::<gmf::server::tokio_runtime::TokioRuntime>();
+}
+
+#[cfg(feature = "glommio-runtime")]
+#[test]
+fn glommio_permits_return_after_disconnect() {
+    permits_return_after_disconnect::<gmf::server::glommio_runtime::GlommioRuntime>();
+}
+
+#[cfg(feature = "monoio-runtime")]
+#[test]
+fn monoio_pause_holds_connections_in_the_backlog() {
+    pause_holds_connections_in_the_backlog::<gmf::server::monoio_runtime::MonoioRuntime>();
+}
+
+#[cfg(feature = "tokio-runtime")]
+#[test]
+fn tokio_pause_holds_connections_in_the_backlog() {
+    pause_holds_connections_in_the_backlog::<gmf::server::tokio_runtime::TokioRuntime>();
+}
+
+#[cfg(feature = "glommio-runtime")]
+#[test]
+fn glommio_pause_holds_connections_in_the_backlog() {
+    pause_holds_connections_in_the_backlog::<gmf::server::glommio_runtime::GlommioRuntime>();
+}
+
+#[cfg(feature = "monoio-runtime")]
+#[test]
+fn monoio_wait_serves_or_closes_after_timeout() {
+    wait_serves_or_closes_after_timeout::<gmf::server::monoio_runtime::MonoioRuntime>();
+}
+
+#[cfg(feature = "tokio-runtime")]
+#[test]
+fn tokio_wait_serves_or_closes_after_timeout() {
+    wait_serves_or_closes_after_timeout::<gmf::server::tokio_runtime::TokioRuntime>();
+}
+
+#[cfg(feature = "glommio-runtime")]
+#[test]
+fn glommio_wait_serves_or_closes_after_timeout() {
+    wait_serves_or_closes_after_timeout::<gmf::server::glommio_runtime::GlommioRuntime>();
+}
+
+#[cfg(feature = "monoio-runtime")]
+#[test]
+fn monoio_goaway_refuses_with_reason() {
+    goaway_refuses_with_reason::<gmf::server::monoio_runtime::MonoioRuntime>();
+}
+
+#[cfg(feature = "tokio-runtime")]
+#[test]
+fn tokio_goaway_refuses_with_reason() {
+    goaway_refuses_with_reason::<gmf::server::tokio_runtime::TokioRuntime>();
+}
+
+#[cfg(feature = "glommio-runtime")]
+#[test]
+fn glommio_goaway_refuses_with_reason() {
+    goaway_refuses_with_reason::<gmf::server::glommio_runtime::GlommioRuntime>();
+}
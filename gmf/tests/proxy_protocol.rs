@@ -0,0 +1,110 @@
+mod common;
+
+use std::convert::Infallible;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bytes::Bytes;
+use gmf::server::connect_info::GmfConnectInfo;
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::listener::{UnixAddr, UnixStrategy};
+use gmf::server::runtime::Runtime;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+
+/// Answers every request with the peer address the server saw.
+fn peer_service() -> impl tower_service::Service<
+    hyper::Request<Incoming>,
+    Response = hyper::Response<Full<Bytes>>,
+    Error = Infallible,
+    Future = impl Send + 'static,
+> + Clone
+       + Send
+       + 'static {
+    tower::service_fn(|request: hyper::Request<Incoming>| async move {
+        let info = request.extensions().get::<GmfConnectInfo>().unwrap();
+        let peer = format!("{:?}", info.peer_addr().as_tcp());
+        Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::from(peer))))
+    })
+}
+
+/// Send `header` and one HTTP/2 request, returning the response body.
+fn request(path: &Path, header: &[u8]) -> Bytes {
+    let mut stream = UnixStream::connect(path).unwrap();
+    stream.write_all(header).unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async move {
+        let stream = tokio::net::UnixStream::from_std(stream).unwrap();
+        let (mut sender, connection) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+        let request = http::Request::builder()
+            .uri("http://localhost/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        response.into_body().collect().await.unwrap().to_bytes()
+    })
+}
+
+/// A Unix socket listener with the PROXY protocol enabled takes the client address
+/// from the header, and closes connections that do not send one.
+fn unix_listener_reads_header<R: Runtime>() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gmf.sock");
+    let handle = GmfServer::<R>::builder()
+        .unix(UnixAddr::path(&path))
+        .unix_strategy(UnixStrategy::PerCore)
+        .proxy_protocol()
+        .num_cores(1)
+        .build()
+        .start(peer_service())
+        .unwrap();
+    let socket = PathBuf::from(format!("{}.0", path.display()));
+
+    let body = request(&socket, b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\n");
+    assert_eq!(body, "Some(192.0.2.1:56324)");
+    let body = request(&socket, b"PROXY UNKNOWN\r\n");
+    assert_eq!(body, "None");
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+        .unwrap();
+    assert_eq!(stream.read(&mut [0; 64]).unwrap(), 0);
+
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+#[cfg(feature = "monoio-runtime")]
+#[test]
+fn monoio_unix_listener_reads_header() {
+    unix_listener_reads_header::<gmf::server::monoio_runtime::MonoioRuntime>();
+}
+
+#[cfg(feature = "tokio-runtime")]
+#[test]
+fn tokio_unix_listener_reads_header() {
+    unix_listener_reads_header::<gmf::server::tokio_runtime::TokioRuntime>();
+}
+
+#[cfg(feature = "glommio-runtime")]
+#[test]
+fn glommio_unix_listener_reads_header() {
+    unix_listener_reads_header::<gmf::server::glommio_runtime::GlommioRuntime>();
+}
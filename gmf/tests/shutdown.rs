@@ -0,0 +1,143 @@
+mod common;
+
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::handle::ServerHandle;
+use gmf::server::runtime::Runtime;
+use http_body_util::Full;
+use hyper::body::Incoming;
+
+use common::{delay, eventually, h2_connect, is_closed, read_goaway, spawn_get};
+
+/// Answers `/slow/{ms}` after that many milliseconds, anything else right away.
+fn start<R: Runtime>(cores: usize, drain_timeout: Duration) -> ServerHandle {
+    GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(cores)
+        .drain_timeout(drain_timeout)
+        .build()
+        .start(tower::service_fn(
+            |request: hyper::Request<Incoming>| async move {
+                if let Some(ms) = request.uri().path().strip_prefix("/slow/") {
+                    delay(Duration::from_millis(ms.parse().unwrap())).await;
+                }
+                Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::from_static(b"done"))))
+            },
+        ))
+        .unwrap()
+}
+
+/// Every core exits promptly on shutdown, including cores with no connections.
+fn stop_wakes_idle_cores<R: Runtime>() {
+    let handle = start::<R>(2, Duration::from_secs(30));
+    let started = Instant::now();
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+/// Open connections receive GOAWAY and in-flight RPCs complete before the server
+/// exits.
+fn drains_gracefully<R: Runtime>() {
+    let handle = start::<R>(1, Duration::from_secs(30));
+    let addr = handle.local_addr();
+    let mut idle = h2_connect(addr).unwrap().expect("connection refused");
+    let in_flight = spawn_get(addr, "/slow/500");
+    // Let the request reach the handler.
+    std::thread::sleep(Duration::from_millis(100));
+
+    handle.shutdown();
+    // A first GOAWAY announces the shutdown; the final one follows the PING round trip.
+    assert_eq!(read_goaway(&mut idle).unwrap(), Some(0));
+    assert_eq!(read_goaway(&mut idle).unwrap(), Some(0));
+    assert!(is_closed(&mut idle));
+    assert!(h2_connect(addr).is_err() || h2_connect(addr).unwrap().is_none());
+    assert_eq!(in_flight.join().unwrap().unwrap(), "done");
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// Connections still busy at the drain timeout are closed.
+fn drain_timeout_closes_stragglers<R: Runtime>() {
+    let handle = start::<R>(1, Duration::from_millis(300));
+    let straggler = spawn_get(handle.local_addr(), "/slow/10000");
+    std::thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    handle.shutdown();
+    let joined = eventually(Duration::from_secs(5), || {
+        handle.is_finished().then_some(())
+    });
+    assert!(joined.is_some(), "server still draining");
+    for result in handle.join() {
+        result.unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(straggler.join().unwrap().is_err());
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn stop_wakes_idle_cores() {
+        super::stop_wakes_idle_cores::<R>();
+    }
+
+    #[test]
+    fn drains_gracefully() {
+        super::drains_gracefully::<R>();
+    }
+
+    #[test]
+    fn drain_timeout_closes_stragglers() {
+        super::drain_timeout_closes_stragglers::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn stop_wakes_idle_cores() {
+        super::stop_wakes_idle_cores::<R>();
+    }
+
+    #[test]
+    fn drains_gracefully() {
+        super::drains_gracefully::<R>();
+    }
+
+    #[test]
+    fn drain_timeout_closes_stragglers() {
+        super::drain_timeout_closes_stragglers::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn stop_wakes_idle_cores() {
+        super::stop_wakes_idle_cores::<R>();
+    }
+
+    #[test]
+    fn drains_gracefully() {
+        super::drains_gracefully::<R>();
+    }
+
+    #[test]
+    fn drain_timeout_closes_stragglers() {
+        super::drain_timeout_closes_stragglers::<R>();
+    }
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::os::fd::{BorrowedFd, RawFd};
+use std::time::Duration;
+
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::runtime::Runtime;
+use gmf::server::socket::SocketOptions;
+use socket2::SockRef;
+
+use common::{h2_connect, ok_service};
+
+const BUFFER: usize = 64 * 1024;
+
+/// The fd of this process's socket bound to `local` and connected to `peer` (a
+/// listener when `peer` is `None`).
+fn find_socket(local: SocketAddr, peer: Option<SocketAddr>) -> RawFd {
+    std::fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .find(|&fd| {
+            // SAFETY: only used for getsockopt calls while the server is running.
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            let socket = SockRef::from(&fd);
+            let bound = socket.local_addr().ok().and_then(|addr| addr.as_socket());
+            let connected = socket.peer_addr().ok().and_then(|addr| addr.as_socket());
+            bound == Some(local) && connected == peer
+        })
+        .expect("socket not found")
+}
+
+fn get_int(fd: RawFd, option: libc::c_int) -> libc::c_int {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            option,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    assert_eq!(ret, 0, "{}", std::io::Error::last_os_error());
+    value
+}
+
+/// The configured options are set on the listener and on the connections it accepts,
+/// as the kernel reports them back.
+fn applies_socket_options<R: Runtime>() {
+    let handle = GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .socket_options(
+            SocketOptions::new()
+                .nodelay(true)
+                .keepalive(Duration::from_secs(30))
+                .keepalive_interval(Duration::from_secs(5))
+                .keepalive_retries(4)
+                .recv_buffer_size(BUFFER)
+                .send_buffer_size(BUFFER)
+                .defer_accept(Duration::from_secs(5))
+                .fastopen(16)
+                .user_timeout(Duration::from_secs(10)),
+        )
+        .build()
+        .start(ok_service())
+        .unwrap();
+    let addr = handle.local_addr();
+
+    let listener_fd = find_socket(addr, None);
+    // SAFETY: the server holds the listener open until it is shut down below.
+    let listener = unsafe { BorrowedFd::borrow_raw(listener_fd) };
+    let socket = SockRef::from(&listener);
+    // Linux reports twice the requested size, to account for its bookkeeping overhead.
+    assert_eq!(socket.recv_buffer_size().unwrap(), 2 * BUFFER);
+    assert_eq!(socket.send_buffer_size().unwrap(), 2 * BUFFER);
+    // Rounded to the SYN-ACK retransmission schedule.
+    assert!(get_int(listener_fd, libc::TCP_DEFER_ACCEPT) >= 5);
+    assert_eq!(get_int(listener_fd, libc::TCP_FASTOPEN), 16);
+
+    let client = h2_connect(addr).unwrap().expect("connection refused");
+    let accepted = find_socket(addr, Some(client.local_addr().unwrap()));
+    // SAFETY: the server holds the connection open while the client does.
+    let accepted = unsafe { BorrowedFd::borrow_raw(accepted) };
+    let socket = SockRef::from(&accepted);
+    assert!(socket.nodelay().unwrap());
+    assert!(socket.keepalive().unwrap());
+    assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+    assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+    assert_eq!(socket.keepalive_retries().unwrap(), 4);
+    assert_eq!(
+        socket.tcp_user_timeout().unwrap(),
+        Some(Duration::from_secs(10))
+    );
+    assert_eq!(socket.recv_buffer_size().unwrap(), 2 * BUFFER);
+    assert_eq!(socket.send_buffer_size().unwrap(), 2 * BUFFER);
+
+    drop(client);
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn applies_socket_options() {
+        super::applies_socket_options::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn applies_socket_options() {
+        super::applies_socket_options::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn applies_socket_options() {
+        super::applies_socket_options::<R>();
+    }
+}
@@ -0,0 +1,248 @@
+#![cfg(feature = "tls")]
+
+mod common;
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use gmf::server::gmf_server::GmfServer;
+use gmf::server::handle::ServerHandle;
+use gmf::server::metrics::{HandshakeStage, TlsFailureReason};
+use gmf::server::runtime::Runtime;
+use gmf::server::tls::{ClientAuth, TlsConfig};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::RootCertStore;
+use tokio_rustls::TlsConnector;
+
+use common::{eventually, is_closed, ok_service};
+
+fn start<R: Runtime>(tls: TlsConfig) -> ServerHandle {
+    GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .tls(tls)
+        .build()
+        .start(ok_service())
+        .unwrap()
+}
+
+fn connector(root: &CertificateDer<'static>, client: Option<&CertifiedKey>) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add(root.clone()).unwrap();
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots);
+    let mut config = match client {
+        Some(client) => builder
+            .with_client_auth_cert(
+                vec![client.cert.der().clone()],
+                PrivateKeyDer::try_from(client.key_pair.serialize_der()).unwrap(),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    TlsConnector::from(Arc::new(config))
+}
+
+/// Send one request over TLS and HTTP/2, returning the negotiated ALPN protocol and
+/// the response body.
+fn request(handle: &ServerHandle, connector: TlsConnector) -> Result<(Vec<u8>, Bytes), String> {
+    let addr = handle.local_addr();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async move {
+        let tcp = tokio::net::TcpStream::connect(addr)
+            .await
+            .map_err(|e| e.to_string())?;
+        let tls = connector
+            .connect("localhost".try_into().unwrap(), tcp)
+            .await
+            .map_err(|e| e.to_string())?;
+        let alpn = tls.get_ref().1.alpn_protocol().unwrap_or_default().to_vec();
+        let (mut sender, connection) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(tls))
+                .await
+                .map_err(|e| e.to_string())?;
+        tokio::spawn(connection);
+        let request = http::Request::builder()
+            .uri("https://localhost/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|e| e.to_string())?;
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        Ok((alpn, body))
+    })
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+fn self_signed_h2<R: Runtime>() {
+    let server = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let tls = TlsConfig::from_pem(
+        server.cert.pem().as_bytes(),
+        server.key_pair.serialize_pem().as_bytes(),
+    )
+    .unwrap();
+    let handle = start::<R>(tls);
+
+    let (alpn, body) = request(&handle, connector(server.cert.der(), None)).unwrap();
+    assert_eq!(alpn, b"h2");
+    assert_eq!(body, "ok");
+    stop(handle);
+}
+
+fn mtls_rejects_client_without_certificate<R: Runtime>() {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server = CertificateParams::new(vec!["localhost".into()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+    let tls = TlsConfig::from_pem_with_client_auth(
+        server.pem().as_bytes(),
+        server_key.serialize_pem().as_bytes(),
+        ClientAuth::from_pem(ca.pem().as_bytes()).unwrap(),
+    )
+    .unwrap();
+    let handle = start::<R>(tls);
+
+    assert!(request(&handle, connector(ca.der(), None)).is_err());
+    let tls = handle.metrics().tls();
+    let recorded = eventually(Duration::from_secs(5), || {
+        (tls.handshake_failures(TlsFailureReason::NoClientCertificate) == 1).then_some(())
+    });
+    assert!(
+        recorded.is_some(),
+        "failure not recorded: {:?}",
+        tls.snapshot()
+    );
+    stop(handle);
+}
+
+/// Clients that never start the TLS handshake, or stall partway through the
+/// ClientHello, are closed at the handshake deadline and counted under the TLS stage.
+fn handshake_timeout_closes_stalled_client<R: Runtime>() {
+    let timeout = Duration::from_millis(300);
+    let server = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let tls = TlsConfig::from_pem(
+        server.cert.pem().as_bytes(),
+        server.key_pair.serialize_pem().as_bytes(),
+    )
+    .unwrap();
+    let handle = GmfServer::<R>::builder()
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .tls(tls)
+        .handshake_timeout(timeout)
+        .build()
+        .start(ok_service())
+        .unwrap();
+
+    // Nothing at all, then the header of a handshake record whose body never comes.
+    for sent in [&b""[..], &[0x16, 0x03, 0x01, 0x02, 0x00]] {
+        let started = Instant::now();
+        let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(sent).unwrap();
+        assert!(is_closed(&mut stream), "connection left open");
+        assert!(started.elapsed() >= timeout);
+    }
+
+    let timeouts = handle.metrics().handshake_timeouts();
+    let counted = eventually(Duration::from_secs(5), || {
+        (timeouts.count(0, HandshakeStage::Tls) == 2).then_some(())
+    });
+    assert!(counted.is_some(), "timeouts not counted");
+    assert_eq!(timeouts.count(0, HandshakeStage::Http2), 0);
+    stop(handle);
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn self_signed_h2() {
+        super::self_signed_h2::<R>();
+    }
+
+    #[test]
+    fn mtls_rejects_client_without_certificate() {
+        super::mtls_rejects_client_without_certificate::<R>();
+    }
+
+    #[test]
+    fn handshake_timeout_closes_stalled_client() {
+        super::handshake_timeout_closes_stalled_client::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn self_signed_h2() {
+        super::self_signed_h2::<R>();
+    }
+
+    #[test]
+    fn mtls_rejects_client_without_certificate() {
+        super::mtls_rejects_client_without_certificate::<R>();
+    }
+
+    #[test]
+    fn handshake_timeout_closes_stalled_client() {
+        super::handshake_timeout_closes_stalled_client::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn self_signed_h2() {
+        super::self_signed_h2::<R>();
+    }
+
+    #[test]
+    fn mtls_rejects_client_without_certificate() {
+        super::mtls_rejects_client_without_certificate::<R>();
+    }
+
+    #[test]
+    fn handshake_timeout_closes_stalled_client() {
+        super::handshake_timeout_closes_stalled_client::<R>();
+    }
+}
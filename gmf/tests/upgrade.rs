@@ -0,0 +1,213 @@
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use gmf::server::error::GmfError;
+use gmf::server::gmf_server::{GmfServer, GmfServerBuilder};
+use gmf::server::handle::ServerHandle;
+use gmf::server::runtime::Runtime;
+use gmf::server::upgrade::UpgradeChannel;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::Incoming;
+use hyper::client::conn::http2::SendRequest;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::runtime::Runtime as Client;
+use tokio::task::JoinHandle;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A one-core server on `builder`'s listeners whose responses carry `name`.
+fn start<R: Runtime>(builder: GmfServerBuilder<R>, name: &'static str) -> ServerHandle {
+    builder
+        .num_cores(1)
+        .build()
+        .start(tower::service_fn(
+            move |_request: hyper::Request<Incoming>| async move {
+                Ok::<_, Infallible>(hyper::Response::new(Full::new(Bytes::from_static(
+                    name.as_bytes(),
+                ))))
+            },
+        ))
+        .unwrap()
+}
+
+fn client() -> Client {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+async fn connect(addr: SocketAddr) -> (SendRequest<Empty<Bytes>>, JoinHandle<()>) {
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    let connection = tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    (sender, connection)
+}
+
+/// Which server answered a request on `sender`.
+async fn get(sender: &mut SendRequest<Empty<Bytes>>) -> Bytes {
+    let request = http::Request::builder()
+        .uri("http://localhost/")
+        .body(Empty::new())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    response.into_body().collect().await.unwrap().to_bytes()
+}
+
+/// Which server answers a new connection to `addr`. The connection is closed again
+/// before returning, so it does not hold up the server's drain.
+fn answer(client: &Client, addr: SocketAddr) -> Bytes {
+    client.block_on(async {
+        let (mut sender, connection) = connect(addr).await;
+        let body = get(&mut sender).await;
+        drop(sender);
+        connection.await.unwrap();
+        body
+    })
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// Hand a bound listener to a second server in this process, as the new process of an
+/// upgrade would receive it, and check the new server takes over the port while the
+/// old one drains.
+fn hands_over_listener<R: Runtime>() {
+    let client = client();
+    let old = start(
+        GmfServer::<R>::builder().addr("127.0.0.1:0".parse().unwrap()),
+        "old",
+    );
+    let addr = old.local_addr();
+    // Driven by a thread of its own, so it answers the old server's drain.
+    let held_client = self::client();
+    let (mut held, held_connection) = held_client.block_on(connect(addr));
+    assert_eq!(held_client.block_on(get(&mut held)), "old");
+    let held_connection = std::thread::spawn(move || held_client.block_on(held_connection));
+
+    let (ours, theirs) = UpgradeChannel::pair().unwrap();
+    let handing_over = std::thread::spawn(move || old.hand_over(&ours, TIMEOUT).map(|()| old));
+    let mut listeners = theirs.receive(TIMEOUT).unwrap();
+    assert_eq!(listeners.len(), 1);
+    let new = start(
+        GmfServer::<R>::builder().listener(listeners.remove(0)),
+        "new",
+    );
+    assert_eq!(new.local_addr(), addr);
+    theirs.ready(&new).unwrap();
+    let old = handing_over.join().unwrap().unwrap();
+
+    // The old server drains: it closes its idle connection and exits.
+    for result in old.join() {
+        result.unwrap();
+    }
+    held_connection.join().unwrap().unwrap();
+    assert!(held.is_closed());
+
+    // The port stays open, now served by the new server alone.
+    for _ in 0..3 {
+        assert_eq!(answer(&client, addr), "new");
+    }
+    stop(new);
+}
+
+/// A new process that never reports ready, or goes away, fails the hand-over and
+/// leaves the old server running.
+fn hand_over_fails_without_ready<R: Runtime>() {
+    let client = client();
+    let old = start(
+        GmfServer::<R>::builder().addr("127.0.0.1:0".parse().unwrap()),
+        "old",
+    );
+    let addr = old.local_addr();
+
+    let (ours, theirs) = UpgradeChannel::pair().unwrap();
+    let receiving = std::thread::spawn(move || {
+        let listeners = theirs.receive(TIMEOUT).unwrap();
+        (theirs, listeners)
+    });
+    let err = old
+        .hand_over(&ours, Duration::from_millis(300))
+        .unwrap_err();
+    assert!(
+        matches!(&err, GmfError::Upgrade(e) if e.kind() == io::ErrorKind::TimedOut),
+        "{err:?}"
+    );
+    let (theirs, listeners) = receiving.join().unwrap();
+    assert_eq!(listeners.len(), 1);
+    assert!(!old.is_finished());
+    assert_eq!(answer(&client, addr), "old");
+
+    // The other end closes the channel instead of reporting ready.
+    drop((theirs, listeners));
+    let (ours, theirs) = UpgradeChannel::pair().unwrap();
+    let receiving = std::thread::spawn(move || {
+        theirs.receive(TIMEOUT).unwrap();
+    });
+    let err = old.hand_over(&ours, TIMEOUT).unwrap_err();
+    assert!(
+        matches!(&err, GmfError::Upgrade(e) if e.kind() == io::ErrorKind::UnexpectedEof),
+        "{err:?}"
+    );
+    receiving.join().unwrap();
+    assert!(!old.is_finished());
+    assert_eq!(answer(&client, addr), "old");
+    stop(old);
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn hands_over_listener() {
+        super::hands_over_listener::<R>();
+    }
+
+    #[test]
+    fn hand_over_fails_without_ready() {
+        super::hand_over_fails_without_ready::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn hands_over_listener() {
+        super::hands_over_listener::<R>();
+    }
+
+    #[test]
+    fn hand_over_fails_without_ready() {
+        super::hand_over_fails_without_ready::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn hands_over_listener() {
+        super::hands_over_listener::<R>();
+    }
+
+    #[test]
+    fn hand_over_fails_without_ready() {
+        super::hand_over_fails_without_ready::<R>();
+    }
+}
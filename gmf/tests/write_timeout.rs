@@ -0,0 +1,191 @@
+mod common;
+
+use std::convert::Infallible;
+use std::io::Write;
+use std::net::TcpStream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use gmf::server::gmf_server::{GmfServer, GmfServerBuilder};
+use gmf::server::handle::ServerHandle;
+use gmf::server::listener::PeerAddr;
+use gmf::server::metrics::WriteTimeoutScope;
+use gmf::server::runtime::Runtime;
+use http_body::Frame;
+use hyper::body::Incoming;
+
+use common::{h2_connect, is_closed, read_frame, read_goaway};
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A server-streaming response that never ends.
+struct Endless;
+
+impl http_body::Body for Endless {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(&[0; 16 * 1024])))))
+    }
+}
+
+fn start<R: Runtime>(builder: GmfServerBuilder<R>) -> ServerHandle {
+    builder
+        .addr("127.0.0.1:0".parse().unwrap())
+        .num_cores(1)
+        .build()
+        .start(tower::service_fn(
+            |_request: hyper::Request<Incoming>| async {
+                Ok::<_, Infallible>(hyper::Response::new(Endless))
+            },
+        ))
+        .unwrap()
+}
+
+/// Open stream 1 with a `GET /` and then read what the server sends without ever
+/// granting more flow-control window, so the response stalls once the initial window
+/// is used up.
+fn stalled_client(handle: &ServerHandle) -> TcpStream {
+    let mut stream = h2_connect(handle.local_addr())
+        .unwrap()
+        .expect("connection refused");
+    // HEADERS, END_STREAM | END_HEADERS: `:method GET`, `:scheme http`, `:path /` from
+    // the static table.
+    stream
+        .write_all(&[0, 0, 3, 0x1, 0x5, 0, 0, 0, 1, 0x82, 0x86, 0x84])
+        .unwrap();
+    stream
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    for result in handle.join() {
+        result.unwrap();
+    }
+}
+
+/// A stream the client stopped reading is reset with `RST_STREAM(CANCEL)`, the
+/// connection stays usable, and the eviction names the peer.
+fn resets_stalled_stream<R: Runtime>() {
+    let handle = start(GmfServer::<R>::builder().stream_write_timeout(TIMEOUT));
+    let started = Instant::now();
+    let mut client = stalled_client(&handle);
+
+    let (stream_id, code) = loop {
+        match read_frame(&mut client).unwrap() {
+            (0x3, _, stream_id, payload) => {
+                break (
+                    stream_id,
+                    u32::from_be_bytes(payload[..4].try_into().unwrap()),
+                )
+            }
+            (0x7, ..) => panic!("connection closed instead of the stream"),
+            _ => continue,
+        }
+    };
+    assert_eq!((stream_id, code), (1, 0x8));
+    assert!(started.elapsed() >= TIMEOUT);
+
+    // The connection itself is still served: a PING is answered.
+    client
+        .write_all(&[0, 0, 8, 0x6, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8])
+        .unwrap();
+    let ack = loop {
+        match read_frame(&mut client).unwrap() {
+            (0x6, 0x1, _, payload) => break payload,
+            _ => continue,
+        }
+    };
+    assert_eq!(ack, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let metrics = handle.metrics().write_timeouts();
+    assert_eq!(
+        (metrics.streams_reset(), metrics.connections_closed()),
+        (1, 0)
+    );
+    let slow = metrics.recent_slow_peers();
+    assert_eq!(slow.len(), 1);
+    assert_eq!(slow[0].scope, WriteTimeoutScope::Stream);
+    assert_eq!(
+        slow[0].peer_addr,
+        PeerAddr::Tcp(client.local_addr().unwrap())
+    );
+
+    drop(client);
+    stop(handle);
+}
+
+/// A connection with data to send that writes nothing is sent GOAWAY and closed.
+fn closes_stalled_connection<R: Runtime>() {
+    let handle = start(GmfServer::<R>::builder().connection_write_timeout(TIMEOUT));
+    let started = Instant::now();
+    let mut client = stalled_client(&handle);
+    let peer = PeerAddr::Tcp(client.local_addr().unwrap());
+
+    assert_eq!(read_goaway(&mut client).unwrap(), Some(0));
+    assert!(started.elapsed() >= TIMEOUT);
+    assert!(is_closed(&mut client));
+
+    let metrics = handle.metrics().write_timeouts();
+    assert_eq!(
+        (metrics.streams_reset(), metrics.connections_closed()),
+        (0, 1)
+    );
+    let slow = metrics.recent_slow_peers();
+    assert_eq!(slow.len(), 1);
+    assert_eq!(slow[0].scope, WriteTimeoutScope::Connection);
+    assert_eq!(slow[0].peer_addr, peer);
+
+    stop(handle);
+}
+
+#[cfg(feature = "monoio-runtime")]
+mod monoio {
+    type R = gmf::server::monoio_runtime::MonoioRuntime;
+
+    #[test]
+    fn resets_stalled_stream() {
+        super::resets_stalled_stream::<R>();
+    }
+
+    #[test]
+    fn closes_stalled_connection() {
+        super::closes_stalled_connection::<R>();
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_rt {
+    type R = gmf::server::tokio_runtime::TokioRuntime;
+
+    #[test]
+    fn resets_stalled_stream() {
+        super::resets_stalled_stream::<R>();
+    }
+
+    #[test]
+    fn closes_stalled_connection() {
+        super::closes_stalled_connection::<R>();
+    }
+}
+
+#[cfg(feature = "glommio-runtime")]
+mod glommio {
+    type R = gmf::server::glommio_runtime::GlommioRuntime;
+
+    #[test]
+    fn resets_stalled_stream() {
+        super::resets_stalled_stream::<R>();
+    }
+
+    #[test]
+    fn closes_stalled_connection() {
+        super::closes_stalled_connection::<R>();
+    }
+}